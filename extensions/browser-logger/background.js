@@ -1,23 +1,82 @@
-const DEFAULT_ENDPOINT = "http://localhost:3030";
+// The app listens on 3030, or on one of the next ports when that one is taken
+const DEFAULT_PORTS = { first: 3030, last: 3040 };
+const PROBE_TIMEOUT_MS = 500;
 const dwellTimers = new Map();
 const pageStartTimes = new Map();
 
-async function sendEvent(payload) {
-  const endpoint = DEFAULT_ENDPOINT.replace(/\/$/, "") + "/ingest/browser";
+async function answersHealthCheck(baseUrl) {
+  try {
+    const response = await fetch(`${baseUrl}/health`, { signal: AbortSignal.timeout(PROBE_TIMEOUT_MS) });
+    return response.ok && (await response.text()) === "OK";
+  } catch {
+    return false;
+  }
+}
+
+async function findLocalServer() {
+  for (let port = DEFAULT_PORTS.first; port <= DEFAULT_PORTS.last; port++) {
+    const baseUrl = `http://127.0.0.1:${port}`;
+    if (await answersHealthCheck(baseUrl)) {
+      return baseUrl;
+    }
+  }
+  return null;
+}
+
+// An endpoint set in the options wins. Otherwise the server found last is reused until it stops answering.
+async function resolveEndpoint({ refresh = false } = {}) {
+  const { endpoint, token } = await chrome.storage.sync.get({ endpoint: "", token: "" });
+  if (endpoint) {
+    return { baseUrl: endpoint.replace(/\/$/, ""), token, configured: true };
+  }
+
+  let { discoveredEndpoint } = await chrome.storage.session.get("discoveredEndpoint");
+  if (!discoveredEndpoint || refresh) {
+    discoveredEndpoint = await findLocalServer();
+    await chrome.storage.session.set({ discoveredEndpoint });
+  }
+  return discoveredEndpoint ? { baseUrl: discoveredEndpoint, token, configured: false } : null;
+}
 
+async function postEvent({ baseUrl, token }, payload) {
+  const headers = { "Content-Type": "application/json" };
+  if (token) {
+    headers.Authorization = `Bearer ${token}`;
+  }
+
+  return fetch(`${baseUrl}/ingest/browser`, {
+    method: "POST",
+    headers,
+    body: JSON.stringify({
+      source: "browser",
+      schema_version: 1,
+      payload: payload
+    })
+  });
+}
+
+async function sendEvent(payload) {
   try {
-    const response = await fetch(endpoint, {
-      method: "POST",
-      headers: {
-        "Content-Type": "application/json"
-      },
-      body: JSON.stringify({
-        source: "browser",
-        payload: payload
-      })
-    });
-    
-    if (!response.ok) {
+    let endpoint = await resolveEndpoint();
+    let response;
+    try {
+      response = endpoint && (await postEvent(endpoint, payload));
+    } catch (error) {
+      // The app may have restarted on another fallback port
+      if (endpoint.configured) {
+        throw error;
+      }
+      response = null;
+    }
+
+    if (!response && !endpoint?.configured) {
+      endpoint = await resolveEndpoint({ refresh: true });
+      response = endpoint && (await postEvent(endpoint, payload));
+    }
+
+    if (!response) {
+      console.error(`DevChronicle Browser Logger: no server answered on ports ${DEFAULT_PORTS.first} to ${DEFAULT_PORTS.last}`);
+    } else if (!response.ok) {
       console.error("DevChronicle Browser Logger: server returned error", response.status);
     }
   } catch (error) {
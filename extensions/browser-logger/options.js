@@ -0,0 +1,33 @@
+const endpointInput = document.getElementById("endpoint");
+const tokenInput = document.getElementById("token");
+const status = document.getElementById("status");
+
+chrome.storage.sync.get({ endpoint: "", token: "" }, ({ endpoint, token }) => {
+  endpointInput.value = endpoint;
+  tokenInput.value = token;
+});
+
+document.getElementById("save").addEventListener("click", async () => {
+  const endpoint = endpointInput.value.trim().replace(/\/$/, "");
+  const token = tokenInput.value.trim();
+
+  if (endpoint) {
+    let origin;
+    try {
+      origin = new URL(endpoint).origin;
+    } catch {
+      status.textContent = "Not a valid URL.";
+      return;
+    }
+
+    // Hosts other than this machine need the user's permission before the extension can post to them
+    const granted = await chrome.permissions.request({ origins: [`${origin}/*`] });
+    if (!granted) {
+      status.textContent = `No permission to send events to ${origin}.`;
+      return;
+    }
+  }
+
+  await chrome.storage.sync.set({ endpoint, token });
+  status.textContent = endpoint ? `Sending events to ${endpoint}.` : "Finding the app on this machine.";
+});
@@ -2,9 +2,26 @@
 # DevChronicle Terminal Logger Hook
 # This script hooks into bash/zsh to capture terminal commands and send them to DevChronicle
 
-DEVCHRONICLE_ENDPOINT="${DEVCHRONICLE_ENDPOINT:-http://localhost:3030}"
 DEVCHRONICLE_ENABLED="${DEVCHRONICLE_ENABLED:-1}"
 
+# The app writes the address it actually listens on to server.json in its data directory
+if [ "$(uname)" = "Darwin" ]; then
+    DEVCHRONICLE_DISCOVERY_FILE="$HOME/Library/Application Support/com.devchronicle.app/server.json"
+else
+    DEVCHRONICLE_DISCOVERY_FILE="${XDG_DATA_HOME:-$HOME/.local/share}/com.devchronicle.app/server.json"
+fi
+
+# DEVCHRONICLE_ENDPOINT wins, then the discovery file, then the default port
+devchronicle_endpoint() {
+    if [ -n "$DEVCHRONICLE_ENDPOINT" ]; then
+        echo "$DEVCHRONICLE_ENDPOINT"
+    elif [ -r "$DEVCHRONICLE_DISCOVERY_FILE" ] && jq -er .url "$DEVCHRONICLE_DISCOVERY_FILE" 2>/dev/null; then
+        :
+    else
+        echo "http://localhost:3030"
+    fi
+}
+
 # Function to send event to DevChronicle
 devchronicle_send() {
     if [ "$DEVCHRONICLE_ENABLED" != "1" ]; then
@@ -25,6 +42,7 @@ devchronicle_send() {
     local payload=$(cat <<EOF
 {
   "source": "terminal",
+  "schema_version": 1,
   "payload": {
     "command": $(echo "$command" | jq -Rs .),
     "exit_code": $exit_code,
@@ -36,7 +54,7 @@ EOF
 )
 
     # Send to endpoint (non-blocking)
-    curl -sS -X POST "$DEVCHRONICLE_ENDPOINT/ingest/terminal" \
+    curl -sS -X POST "$(devchronicle_endpoint)/ingest/terminal" \
         -H "Content-Type: application/json" \
         -d "$payload" > /dev/null 2>&1 &
 }
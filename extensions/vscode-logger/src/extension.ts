@@ -1,7 +1,53 @@
+import { promises as fs } from "fs";
+import * as os from "os";
+import * as path from "path";
 import * as vscode from "vscode";
 
+// The parts of the built-in Git extension's API used here
+interface GitRepository {
+  state: { HEAD?: { name?: string } };
+  onDidCommit?: vscode.Event<void>;
+  onDidCheckout?: vscode.Event<void>;
+}
+
+interface GitApi {
+  repositories: GitRepository[];
+  onDidOpenRepository: vscode.Event<GitRepository>;
+}
+
+function secondsSince(start: number | undefined): number | undefined {
+  return start === undefined ? undefined : Math.floor((Date.now() - start) / 1000);
+}
+
+// The app writes the address it actually listens on to server.json in its data directory
+function discoveryFile(): string {
+  const home = os.homedir();
+  const base =
+    process.platform === "win32"
+      ? process.env.APPDATA ?? path.join(home, "AppData", "Roaming")
+      : process.platform === "darwin"
+        ? path.join(home, "Library", "Application Support")
+        : process.env.XDG_DATA_HOME ?? path.join(home, ".local", "share");
+  return path.join(base, "com.devchronicle.app", "server.json");
+}
+
+// An endpoint set by the user wins. Otherwise the app may have moved to a fallback port.
+async function resolveEndpoint(configured: string): Promise<string> {
+  const setting = vscode.workspace.getConfiguration("devChronicle").inspect<string>("endpoint");
+  if (setting?.globalValue ?? setting?.workspaceValue ?? setting?.workspaceFolderValue) {
+    return configured;
+  }
+
+  try {
+    const { url } = JSON.parse(await fs.readFile(discoveryFile(), "utf8"));
+    return typeof url === "string" ? url : configured;
+  } catch {
+    return configured;
+  }
+}
+
 async function postEvent(endpoint: string, payload: Record<string, unknown>) {
-  const url = `${endpoint.replace(/\/$/, "")}/ingest/vscode`;
+  const url = `${(await resolveEndpoint(endpoint)).replace(/\/$/, "")}/ingest/vscode`;
 
   try {
     const response = await fetch(url, {
@@ -11,6 +57,7 @@ async function postEvent(endpoint: string, payload: Record<string, unknown>) {
       },
       body: JSON.stringify({
         source: "vscode",
+        schema_version: 1,
         payload,
       }),
     });
@@ -44,7 +91,7 @@ export function activate(context: vscode.ExtensionContext) {
 
       await postEvent(endpoint, {
         event: "file_open",
-        file: document.uri.fsPath,
+        file_path: document.uri.fsPath,
         language: document.languageId,
         workspace: workspaceFolder?.uri.fsPath,
         cursor_line: 1,
@@ -66,7 +113,7 @@ export function activate(context: vscode.ExtensionContext) {
 
       await postEvent(endpoint, {
         event: "file_save",
-        file: document.uri.fsPath,
+        file_path: document.uri.fsPath,
         language: document.languageId,
         workspace: workspaceFolder?.uri.fsPath,
         time_spent_sec: timeSpent,
@@ -74,6 +121,98 @@ export function activate(context: vscode.ExtensionContext) {
     })
   );
 
+  // Track which file is being worked on when switching editors
+  let lastFocusedFile: string | undefined;
+  context.subscriptions.push(
+    vscode.window.onDidChangeActiveTextEditor(async (editor) => {
+      const document = editor?.document;
+      if (!endpoint || !document || document.uri.scheme !== "file" || document.uri.fsPath === lastFocusedFile) {
+        return;
+      }
+      lastFocusedFile = document.uri.fsPath;
+
+      const workspaceFolder = vscode.workspace.getWorkspaceFolder(document.uri);
+      await postEvent(endpoint, {
+        event: "file_focus",
+        file_path: document.uri.fsPath,
+        language: document.languageId,
+        workspace: workspaceFolder?.uri.fsPath,
+      });
+    })
+  );
+
+  // Track debug sessions
+  const debugStartTimes = new Map<string, number>();
+  context.subscriptions.push(
+    vscode.debug.onDidStartDebugSession(async (session) => {
+      debugStartTimes.set(session.id, Date.now());
+      if (endpoint) {
+        await postEvent(endpoint, { event: "debug_start", name: session.name, debug_type: session.type });
+      }
+    }),
+    vscode.debug.onDidTerminateDebugSession(async (session) => {
+      const duration = secondsSince(debugStartTimes.get(session.id));
+      debugStartTimes.delete(session.id);
+      if (endpoint) {
+        await postEvent(endpoint, { event: "debug_stop", name: session.name, duration_sec: duration });
+      }
+    })
+  );
+
+  // Track task executions. Tasks in the Test group are reported as test runs.
+  const taskStartTimes = new Map<vscode.TaskExecution, number>();
+  context.subscriptions.push(
+    vscode.tasks.onDidStartTaskProcess((event) => {
+      taskStartTimes.set(event.execution, Date.now());
+    }),
+    vscode.tasks.onDidEndTaskProcess(async (event) => {
+      const task = event.execution.task;
+      const duration = secondsSince(taskStartTimes.get(event.execution));
+      taskStartTimes.delete(event.execution);
+      if (!endpoint) {
+        return;
+      }
+
+      const isTest = task.group?.id === vscode.TaskGroup.Test.id;
+      await postEvent(endpoint, {
+        event: isTest ? "test_run" : "task_run",
+        name: task.name,
+        exit_code: event.exitCode,
+        duration_sec: duration,
+      });
+    })
+  );
+
+  // Track commits and checkouts made through the built-in Git extension
+  const watchRepository = (repository: GitRepository) => {
+    const report = (operation: string) => async () => {
+      if (endpoint) {
+        await postEvent(endpoint, {
+          event: "git_operation",
+          operation,
+          branch: repository.state.HEAD?.name,
+        });
+      }
+    };
+    if (repository.onDidCommit) {
+      context.subscriptions.push(repository.onDidCommit(report("commit")));
+    }
+    if (repository.onDidCheckout) {
+      context.subscriptions.push(repository.onDidCheckout(report("checkout")));
+    }
+  };
+
+  const gitExtension = vscode.extensions.getExtension<{ getAPI(version: 1): GitApi }>("vscode.git");
+  if (gitExtension) {
+    Promise.resolve(gitExtension.isActive ? gitExtension.exports : gitExtension.activate())
+      .then((git) => {
+        const api = git.getAPI(1);
+        api.repositories.forEach(watchRepository);
+        context.subscriptions.push(api.onDidOpenRepository(watchRepository));
+      })
+      .catch((error) => console.error(`DevChronicle: git integration unavailable (${error})`));
+  }
+
   context.subscriptions.push(
     vscode.workspace.onDidChangeConfiguration((event) => {
       if (event.affectsConfiguration("devChronicle.endpoint")) {
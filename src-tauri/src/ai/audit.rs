@@ -0,0 +1,114 @@
+use anyhow::Error;
+use chrono::Utc;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::db::{crypto, time::StoredTimestamp};
+
+pub const STATUS_OK: &str = "ok";
+pub const STATUS_ERROR: &str = "error";
+
+/// A request as it was sent to the AI provider. `prompt` is the exact request body, or `None`
+/// when only its hash was kept.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct AiRequestRecord {
+    pub id: i64,
+    pub report_id: Option<i64>,
+    pub created_at: StoredTimestamp,
+    pub model: String,
+    pub provider_host: String,
+    pub prompt: Option<String>,
+    pub prompt_sha256: String,
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub total_tokens: Option<i64>,
+    pub latency_ms: i64,
+    pub http_status: Option<i64>,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+/// Token counts from the `usage` field of a chat completion response
+#[derive(Debug, Default, Clone, Copy, serde::Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: Option<i64>,
+    pub completion_tokens: Option<i64>,
+    pub total_tokens: Option<i64>,
+}
+
+/// Everything known about one provider call once it has finished
+pub struct CompletedRequest<'a> {
+    pub model: &'a str,
+    pub provider_url: &'a str,
+    pub body: &'a str,
+    pub store_prompt: bool,
+    pub usage: Usage,
+    pub latency_ms: i64,
+    pub http_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+const SELECT_COLUMNS: &str = "id, report_id, created_at, model, provider_host, prompt, prompt_sha256, \
+    prompt_tokens, completion_tokens, total_tokens, latency_ms, http_status, status, error";
+
+/// Store the audit record for a finished request and return its id
+pub async fn record(pool: &SqlitePool, request: CompletedRequest<'_>) -> Result<i64, Error> {
+    let provider_host = url::Url::parse(request.provider_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| request.provider_url.to_string());
+    let prompt = request.store_prompt.then_some(request.body);
+    let status = if request.error.is_none() { STATUS_OK } else { STATUS_ERROR };
+
+    let _writes = crypto::write_access().await;
+    let result = sqlx::query(
+        "INSERT INTO ai_requests (created_at, model, provider_host, prompt, prompt_sha256, prompt_tokens, \
+         completion_tokens, total_tokens, latency_ms, http_status, status, error) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+    )
+    .bind(Utc::now().timestamp_millis())
+    .bind(request.model)
+    .bind(provider_host)
+    .bind(crypto::seal_opt(prompt)?)
+    .bind(format!("{:x}", Sha256::digest(request.body.as_bytes())))
+    .bind(request.usage.prompt_tokens)
+    .bind(request.usage.completion_tokens)
+    .bind(request.usage.total_tokens)
+    .bind(request.latency_ms)
+    .bind(request.http_status.map(i64::from))
+    .bind(status)
+    .bind(request.error)
+    .execute(pool)
+    .await?;
+
+    Ok(result.last_insert_rowid())
+}
+
+/// Point a request at the report that was saved from its response
+pub async fn link_report(pool: &SqlitePool, request_id: i64, report_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE ai_requests SET report_id = ?1 WHERE id = ?2")
+        .bind(report_id)
+        .bind(request_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// The request a report was generated from, with the prompt decrypted
+pub async fn for_report(pool: &SqlitePool, report_id: i64) -> Result<Option<AiRequestRecord>, Error> {
+    let record = sqlx::query_as::<_, AiRequestRecord>(&format!(
+        "SELECT {SELECT_COLUMNS} FROM ai_requests WHERE report_id = ?1 ORDER BY id DESC LIMIT 1"
+    ))
+    .bind(report_id)
+    .fetch_optional(pool)
+    .await?;
+
+    record
+        .map(|mut record| {
+            record.prompt = crypto::open_opt(record.prompt)?;
+            Ok(record)
+        })
+        .transpose()
+}
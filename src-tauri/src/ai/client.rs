@@ -1,20 +1,76 @@
-use anyhow::{Context, Result};
-use reqwest::Client;
+use std::net::{IpAddr, SocketAddr};
 
-use crate::db::models::AiSettings;
+use anyhow::{anyhow, bail, Context, Result};
+use reqwest::{redirect, Client};
+use url::{Host, Url};
+
+use crate::{db::models::AiSettings, sanitizer::is_public_ip, secrets::SecretStore};
 
 #[derive(Clone)]
 pub struct AiClient {
     http: Client,
+    secrets: SecretStore,
+}
+
+/// A provider that passed the local-only check, with the addresses its host resolved to
+pub struct LocalProvider {
+    host: String,
+    addrs: Vec<SocketAddr>,
+}
+
+/// Resolve the provider's host and require every address to be loopback or private.
+/// All addresses are checked so a hostname with one private and one public record is refused.
+pub async fn resolve_local_provider(provider_url: &str) -> Result<LocalProvider> {
+    let url = Url::parse(provider_url.trim()).with_context(|| format!("invalid provider URL '{provider_url}'"))?;
+    let port = url
+        .port_or_known_default()
+        .ok_or_else(|| anyhow!("provider URL '{provider_url}' has no port"))?;
+
+    let (host, addrs) = match url.host() {
+        Some(Host::Ipv4(ip)) => (ip.to_string(), vec![SocketAddr::new(IpAddr::V4(ip), port)]),
+        Some(Host::Ipv6(ip)) => (ip.to_string(), vec![SocketAddr::new(IpAddr::V6(ip), port)]),
+        Some(Host::Domain(domain)) => {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((domain, port))
+                .await
+                .with_context(|| format!("failed to resolve AI provider host '{domain}'"))?
+                .collect();
+            (domain.to_string(), addrs)
+        }
+        None => bail!("provider URL '{provider_url}' has no host"),
+    };
+
+    if addrs.is_empty() {
+        bail!("AI provider host '{host}' did not resolve to any address");
+    }
+    if let Some(public) = addrs.iter().find(|addr| is_public_ip(&addr.ip())) {
+        bail!(
+            "local-only mode is on: AI provider host '{host}' resolves to public address {}",
+            public.ip()
+        );
+    }
+
+    Ok(LocalProvider { host, addrs })
 }
 
 impl AiClient {
-    pub fn new() -> Self {
+    pub fn new(secrets: SecretStore) -> Self {
         Self {
             http: Client::new(),
+            secrets,
         }
     }
 
+    /// A client that can only reach the checked addresses: DNS answers are pinned so the host
+    /// can't be re-resolved elsewhere, and redirects and proxies are disabled.
+    fn pinned_client(provider: &LocalProvider) -> Result<Client> {
+        Client::builder()
+            .resolve_to_addrs(&provider.host, &provider.addrs)
+            .redirect(redirect::Policy::none())
+            .no_proxy()
+            .build()
+            .context("failed to build local-only HTTP client")
+    }
+
     pub async fn send_chat_completion<T: serde::ser::Serialize>(
         &self,
         settings: &AiSettings,
@@ -23,9 +79,20 @@ impl AiClient {
         let base = settings.provider_url.trim_end_matches('/');
         let endpoint = format!("{}/chat/completions", base);
 
-        let mut request = self.http.post(&endpoint).json(payload);
+        let http = if settings.local_only {
+            Self::pinned_client(&resolve_local_provider(base).await?)?
+        } else {
+            self.http.clone()
+        };
+
+        let mut request = http.post(&endpoint).json(payload);
 
-        if let Some(api_key) = settings.api_key.as_ref().filter(|value| !value.is_empty()) {
+        // The key is stored encrypted and only decrypted for the duration of the request
+        if let Some(stored_key) = settings.api_key.as_ref().filter(|value| !value.is_empty()) {
+            let api_key = self
+                .secrets
+                .decrypt(stored_key)
+                .context("failed to decrypt the stored API key")?;
             request = request.bearer_auth(api_key);
         }
 
@@ -1,16 +1,27 @@
+pub mod audit;
 pub mod client;
 
+use std::{collections::HashMap, path::Path, time::Instant};
+
 use anyhow::{anyhow, Context, Error};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::{
-    ai::client::AiClient,
-    db::{get_ai_settings, models::{ActivityLog, AiReportRow}},
+    ai::{
+        audit::{CompletedRequest, Usage},
+        client::AiClient,
+    },
+    db::{crypto, get_ai_settings, get_redaction_settings, models::{ActivityLog, AiReportRow, EditorEvent}, time},
 };
 use sqlx::SqlitePool;
 
-use crate::sanitizer::sanitize_json;
+use crate::sanitizer::{
+    normalize_home_path,
+    pseudonym::Pseudonymizer,
+    rules::{self, RuleSet},
+    sanitize_json, sanitize_text,
+};
 
 const SYSTEM_PROMPT: &str = r#"
 **ROLE:**
@@ -99,6 +110,8 @@ struct ChatRequest {
 #[derive(Deserialize)]
 struct ChatResponse {
     choices: Vec<Choice>,
+    #[serde(default)]
+    usage: Option<Usage>,
 }
 
 #[derive(Deserialize)]
@@ -111,11 +124,17 @@ struct ChoiceMessage {
     content: String,
 }
 
+/// A summary together with the audit record of the request that produced it
+pub struct GeneratedSummary {
+    pub summary: String,
+    pub request_id: i64,
+}
+
 pub async fn generate_summary(
     pool: &SqlitePool,
     ai_client: &AiClient,
     logs: Vec<ActivityLog>,
-) -> Result<String, Error> {
+) -> Result<GeneratedSummary, Error> {
     if logs.is_empty() {
         return Err(anyhow!("no logs provided for summary generation"));
     }
@@ -136,7 +155,12 @@ pub async fn generate_summary(
         "local-model"
     };
 
-    let formatted_logs = format_logs(logs)?;
+    let pseudonymize_hostnames = get_redaction_settings(pool)
+        .await
+        .map(|settings| settings.pseudonymize_hostnames)
+        .unwrap_or(false);
+
+    let formatted_logs = format_logs(logs, &rules::current(), pseudonymize_hostnames)?;
     
     // Build context prefix with recent summaries
     let context_prefix = if !recent_context.is_empty() {
@@ -167,18 +191,46 @@ pub async fn generate_summary(
         temperature,
     };
 
-    let response = ai_client
-        .send_chat_completion(&ai_settings, &payload)
-        .await?;
-
-    let response = response
-        .error_for_status()
-        .context("AI provider returned an error status")?;
+    // reqwest serializes the payload the same way, so this is byte for byte the body that is sent
+    let body = serde_json::to_string(&payload).context("failed to serialize AI request")?;
+
+    let started = Instant::now();
+    let mut http_status = None;
+    let result: Result<ChatResponse, Error> = async {
+        let response = ai_client
+            .send_chat_completion(&ai_settings, &payload)
+            .await?;
+        http_status = Some(response.status().as_u16());
+
+        let response = response
+            .error_for_status()
+            .context("AI provider returned an error status")?;
+
+        response
+            .json()
+            .await
+            .context("failed to parse AI provider response")
+    }
+    .await;
+
+    // Failed calls are recorded too: the prompt may have reached the provider either way
+    let request_id = audit::record(
+        pool,
+        CompletedRequest {
+            model: model_name,
+            provider_url: &ai_settings.provider_url,
+            body: &body,
+            store_prompt: ai_settings.audit_store_prompts,
+            usage: result.as_ref().ok().and_then(|response| response.usage).unwrap_or_default(),
+            latency_ms: started.elapsed().as_millis() as i64,
+            http_status,
+            error: result.as_ref().err().map(|err| format!("{err:#}")),
+        },
+    )
+    .await
+    .context("failed to record AI request")?;
 
-    let chat_response: ChatResponse = response
-        .json()
-        .await
-        .context("failed to parse AI provider response")?;
+    let chat_response = result?;
 
     let summary = chat_response
         .choices
@@ -193,7 +245,7 @@ pub async fn generate_summary(
         })
         .ok_or_else(|| anyhow!("AI provider response did not contain a summary"))?;
 
-    Ok(summary)
+    Ok(GeneratedSummary { summary, request_id })
 }
 
 /// Fetch recent summaries for context
@@ -211,26 +263,40 @@ async fn fetch_recent_summaries(pool: &SqlitePool, limit: i64) -> Result<Vec<Str
     let summaries: Vec<String> = rows
         .into_iter()
         .map(|row| {
+            let summary = crypto::open(&row.summary)?;
             // Extract first 500 chars of summary to avoid token bloat
-            let summary_preview = if row.summary.len() > 500 {
-                format!("{}...", &row.summary[..500])
+            let summary_preview = if summary.len() > 500 {
+                format!("{}...", &summary[..500])
             } else {
-                row.summary
+                summary
             };
-            format!("**{}**\n{}", row.generated_at, summary_preview)
+            Ok(format!("**{}**\n{}", row.generated_at, summary_preview))
         })
-        .collect();
+        .collect::<Result<_, Error>>()?;
 
     Ok(summaries)
 }
 
-fn format_logs(mut logs: Vec<ActivityLog>) -> Result<String, Error> {
+fn format_logs(mut logs: Vec<ActivityLog>, rules: &RuleSet, pseudonymize_hostnames: bool) -> Result<String, Error> {
     logs.sort_by_key(|log| log.timestamp);
+    // Local times let the model tell morning from evening work
+    let zone = time::current();
+
+    // One pseudonymizer for the whole prompt so a host keeps the same placeholder throughout the report
+    let mut pseudonyms = Pseudonymizer::default();
+    let mut anonymize = |text: &str| {
+        if pseudonymize_hostnames {
+            pseudonyms.pseudonymize_text(text)
+        } else {
+            text.to_string()
+        }
+    };
 
     // Group logs by source for better readability
     let mut terminal_logs = Vec::new();
     let mut browser_logs = Vec::new();
     let mut vscode_logs = Vec::new();
+    let mut git_logs = Vec::new();
     let mut other_logs = Vec::new();
 
     for log in logs {
@@ -238,20 +304,35 @@ fn format_logs(mut logs: Vec<ActivityLog>) -> Result<String, Error> {
             "terminal" => terminal_logs.push(log),
             "browser" => browser_logs.push(log),
             "vscode" => vscode_logs.push(log),
+            "git" => git_logs.push(log),
             _ => other_logs.push(log),
         }
     }
 
+    // Map absolute file paths to the commits that touched them, so edits can be paired with commits
+    let mut committed_files: HashMap<String, String> = HashMap::new();
+    for log in git_logs.iter().filter(|log| log.log_type.as_deref() == Some("commit")) {
+        let repository = log.payload.get("repository").and_then(Value::as_str).unwrap_or("");
+        let hash = short_hash(&log.payload);
+        let files = log.payload.get("files").and_then(Value::as_array);
+        for file in files.into_iter().flatten() {
+            if let Some(path) = file.get("path").and_then(Value::as_str) {
+                let absolute = Path::new(repository).join(path).to_string_lossy().into_owned();
+                committed_files.insert(normalize_home_path(&absolute), hash.clone());
+            }
+        }
+    }
+
     let mut formatted = String::from("## Activity Logs by Source\n\n");
 
     if !terminal_logs.is_empty() {
         formatted.push_str("### Terminal Commands\n");
         for log in terminal_logs {
             if let Some(cmd) = &log.command {
-                formatted.push_str(&format!("- `{}` at {}\n", cmd, log.timestamp.to_rfc3339()));
+                formatted.push_str(&format!("- `{}` at {}\n", anonymize(cmd), zone.to_local(log.timestamp).to_rfc3339()));
             } else {
-                let payload = format_payload(&log.payload)?;
-                formatted.push_str(&format!("- {}\n", payload));
+                let payload = format_payload(&log, rules)?;
+                formatted.push_str(&format!("- {}\n", anonymize(&payload)));
             }
         }
         formatted.push('\n');
@@ -260,9 +341,9 @@ fn format_logs(mut logs: Vec<ActivityLog>) -> Result<String, Error> {
     if !browser_logs.is_empty() {
         formatted.push_str("### Browser Activity\n");
         for log in browser_logs {
-            let url = log.url.as_deref().unwrap_or("unknown");
-            let title = log.title.as_deref().unwrap_or("");
-            formatted.push_str(&format!("- {} - {} at {}\n", url, title, log.timestamp.to_rfc3339()));
+            let url = anonymize(log.url.as_deref().unwrap_or("unknown"));
+            let title = anonymize(log.title.as_deref().unwrap_or(""));
+            formatted.push_str(&format!("- {} - {} at {}\n", url, title, zone.to_local(log.timestamp).to_rfc3339()));
         }
         formatted.push('\n');
     }
@@ -270,10 +351,29 @@ fn format_logs(mut logs: Vec<ActivityLog>) -> Result<String, Error> {
     if !vscode_logs.is_empty() {
         formatted.push_str("### Code Editor Activity\n");
         for log in vscode_logs {
-            if let Some(file) = &log.file_path {
-                let lang = log.title.as_deref().unwrap_or("unknown");
-                formatted.push_str(&format!("- {} ({}) at {}\n", file, lang, log.timestamp.to_rfc3339()));
-            }
+            let Some(event) = log.editor_event() else {
+                continue;
+            };
+            let commit_note = event
+                .file_path()
+                .and_then(|file| committed_files.get(&normalize_home_path(file)))
+                .map(|hash| format!(" → committed in `{}`", hash))
+                .unwrap_or_default();
+            formatted.push_str(&format!(
+                "- {} at {}{}\n",
+                anonymize(&format_editor_event(&event)),
+                zone.to_local(log.timestamp).to_rfc3339(),
+                commit_note
+            ));
+        }
+        formatted.push('\n');
+    }
+
+    if !git_logs.is_empty() {
+        formatted.push_str("### Git Activity\n");
+        for log in git_logs {
+            let commit = anonymize(&format_git_log(&log, rules));
+            formatted.push_str(&format!("- {} at {}\n", commit, zone.to_local(log.timestamp).to_rfc3339()));
         }
         formatted.push('\n');
     }
@@ -281,15 +381,201 @@ fn format_logs(mut logs: Vec<ActivityLog>) -> Result<String, Error> {
     if !other_logs.is_empty() {
         formatted.push_str("### Other Activity\n");
         for log in other_logs {
-            let payload = format_payload(&log.payload)?;
-            formatted.push_str(&format!("- [{}] {}\n", log.source, payload));
+            let payload = format_payload(&log, rules)?;
+            formatted.push_str(&format!("- [{}] {}\n", log.source, anonymize(&payload)));
         }
     }
 
     Ok(formatted)
 }
 
-fn format_payload(payload: &Value) -> Result<String, Error> {
-    let sanitized = sanitize_json(payload);
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+fn format_editor_event(event: &EditorEvent) -> String {
+    let file_label = |file_path: &str, language: &Option<String>| {
+        format!("{} ({})", normalize_home_path(file_path), language.as_deref().unwrap_or("unknown"))
+    };
+    let quoted = |name: &Option<String>| name.as_deref().map(|name| format!(" `{name}`")).unwrap_or_default();
+    let took = |duration: &Option<u64>| duration.map(|d| format!(" after {}", format_duration(d))).unwrap_or_default();
+
+    match event {
+        EditorEvent::FileOpen { file_path, language } => format!("Opened {}", file_label(file_path, language)),
+        EditorEvent::FileSave { file_path, language, time_spent_sec } => {
+            let spent = time_spent_sec
+                .filter(|seconds| *seconds > 0)
+                .map(|seconds| format!(", {} since opening", format_duration(seconds)))
+                .unwrap_or_default();
+            format!("Saved {}{}", file_label(file_path, language), spent)
+        }
+        EditorEvent::FileFocus { file_path, language } => format!("Switched to {}", file_label(file_path, language)),
+        EditorEvent::FileEdit { file_path, language } => file_label(file_path, language),
+        EditorEvent::DebugStart { name, debug_type } => {
+            let debug_type = debug_type.as_deref().map(|kind| format!(" ({kind})")).unwrap_or_default();
+            format!("Started debugging{}{}", quoted(name), debug_type)
+        }
+        EditorEvent::DebugStop { name, duration_sec } => {
+            format!("Stopped debugging{}{}", quoted(name), took(duration_sec))
+        }
+        EditorEvent::TestRun { name, passed, failed, skipped, exit_code, duration_sec } => {
+            let mut counts = Vec::new();
+            if let Some(failed) = failed.filter(|n| *n > 0) {
+                counts.push(format!("{failed} failed"));
+            }
+            if let Some(passed) = passed {
+                counts.push(format!("{passed} passed"));
+            }
+            if let Some(skipped) = skipped.filter(|n| *n > 0) {
+                counts.push(format!("{skipped} skipped"));
+            }
+            let result = match (counts.is_empty(), exit_code) {
+                (false, _) => counts.join(", "),
+                (true, Some(0)) => "passed".to_string(),
+                (true, Some(code)) => format!("failed (exit {code})"),
+                (true, None) => "no result".to_string(),
+            };
+            format!("Ran tests{}: {}{}", quoted(name), result, took(duration_sec))
+        }
+        EditorEvent::TaskRun { name, exit_code, duration_sec } => {
+            let outcome = match exit_code {
+                Some(0) => " (succeeded)".to_string(),
+                Some(code) => format!(" (failed, exit {code})"),
+                None => String::new(),
+            };
+            format!("Ran task `{}`{}{}", name, outcome, took(duration_sec))
+        }
+        EditorEvent::GitOperation { operation, branch } => {
+            let branch = branch.as_deref().map(|branch| format!(" on {branch}")).unwrap_or_default();
+            format!("Git {} from the editor{}", operation, branch)
+        }
+    }
+}
+
+fn short_hash(payload: &Value) -> String {
+    payload
+        .get("hash")
+        .and_then(Value::as_str)
+        .map(|hash| hash.chars().take(7).collect())
+        .unwrap_or_default()
+}
+
+fn format_git_log(log: &ActivityLog, rules: &RuleSet) -> String {
+    let payload = &log.payload;
+    let text = |key: &str| payload.get(key).and_then(Value::as_str).unwrap_or("unknown").to_string();
+
+    match log.log_type.as_deref() {
+        Some("commit") => {
+            let files: Vec<&str> = payload
+                .get("files")
+                .and_then(Value::as_array)
+                .map(|files| files.iter().filter_map(|file| file.get("path").and_then(Value::as_str)).collect())
+                .unwrap_or_default();
+            let insertions = payload.get("insertions").and_then(Value::as_i64).unwrap_or(0);
+            let deletions = payload.get("deletions").and_then(Value::as_i64).unwrap_or(0);
+            let branch = payload
+                .get("branch")
+                .and_then(Value::as_str)
+                .map(|branch| format!(" on {}", branch))
+                .unwrap_or_default();
+
+            format!(
+                "Committed `{}`{} \"{}\" (+{}/-{}, {} files: {})",
+                short_hash(payload),
+                branch,
+                sanitize_text(rules, &text("message"), Some(&log.source)),
+                insertions,
+                deletions,
+                files.len(),
+                files.join(", ")
+            )
+        }
+        Some("branch_switch") => format!("Switched branch {} → {}", text("from"), text("to")),
+        _ => format!("{:?}", log.log_type),
+    }
+}
+
+fn format_payload(log: &ActivityLog, rules: &RuleSet) -> Result<String, Error> {
+    let sanitized = sanitize_json(rules, &log.payload, Some(&log.source));
     serde_json::to_string_pretty(&sanitized).map_err(|err| anyhow!(err))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::RedactionRule;
+
+    fn log(source: &str, log_type: &str, payload: Value) -> ActivityLog {
+        ActivityLog {
+            id: 1,
+            source: source.to_string(),
+            payload,
+            timestamp: chrono::Utc::now(),
+            log_type: Some(log_type.to_string()),
+            session_id: None,
+            command: None,
+            url: None,
+            domain: None,
+            title: None,
+            file_path: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn prompt_applies_rules_scoped_to_the_log_source() {
+        let rule = RedactionRule {
+            id: 1,
+            name: "vault".to_string(),
+            pattern: r"corp-vault-\d+".to_string(),
+            replacement: "[VAULT]".to_string(),
+            kind: rules::KIND_REDACT.to_string(),
+            source: Some("git".to_string()),
+            field: None,
+            enabled: true,
+        };
+        let (rules, _) = RuleSet::compile(&[rule]);
+
+        let commit = log("git", "commit", serde_json::json!({ "hash": "abc1234", "message": "Rotate corp-vault-3" }));
+        let other = log("jetbrains", "unknown", serde_json::json!({ "note": "corp-vault-3" }));
+        let formatted = format_logs(vec![commit, other], &rules, false).unwrap();
+
+        assert!(formatted.contains("\"Rotate [VAULT]\""), "{formatted}");
+        assert!(formatted.contains("\"note\": \"corp-vault-3\""), "{formatted}");
+    }
+
+    #[test]
+    fn pseudonymizes_hosts_in_browser_titles() {
+        let log = ActivityLog {
+            url: Some("https://grafana.corp.example/d/latency".to_string()),
+            domain: Some("grafana.corp.example".to_string()),
+            title: Some("Latency - grafana.corp.example".to_string()),
+            ..log("browser", "browse", serde_json::json!({}))
+        };
+
+        let formatted = format_logs(vec![log], &RuleSet::default(), true).unwrap();
+        assert!(formatted.contains("https://host-1/d/latency - Latency - host-1 at"), "{formatted}");
+        assert!(!formatted.contains("corp.example"));
+    }
+
+    #[test]
+    fn pseudonymizes_hosts_in_commit_messages() {
+        let visit = ActivityLog {
+            url: Some("https://grafana.corp.example/d/latency".to_string()),
+            ..log("browser", "browse", serde_json::json!({}))
+        };
+        let commit = log(
+            "git",
+            "commit",
+            serde_json::json!({ "hash": "abc1234", "message": "Point https://api.corp.example at grafana.corp.example" }),
+        );
+
+        let formatted = format_logs(vec![visit, commit], &RuleSet::default(), true).unwrap();
+        assert!(formatted.contains("\"Point https://host-2 at host-1\""), "{formatted}");
+        assert!(!formatted.contains("corp.example"), "{formatted}");
+    }
+}
@@ -1,154 +1,169 @@
-use std::convert::TryFrom;
+use std::path::PathBuf;
 
-use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::{sqlite::SqliteQueryResult, SqlitePool};
-use tauri::State;
+use tauri::{AppHandle, Manager, State};
+use tauri_plugin_dialog::DialogExt;
 
 use crate::{
-    ai::{client::AiClient, generate_summary},
+    ai::{
+        audit::{self, AiRequestRecord},
+        client::{self, AiClient},
+        generate_summary,
+    },
     db::{
-        get_ai_settings as load_ai_settings,
+        self, crypto, get_ai_settings as load_ai_settings,
+        quarantine::{self, QuarantinedRow, RepairAction, LOG_COLUMNS},
+        query::{ActivityStats, LogQuery, Page, ReportQuery, TimeRange},
+        time::{self, StoredTimestamp},
         models::{
-            ActivityLog, ActivityLogConversionError, ActivityLogRow, AiReport, AiReportRow,
-            AiSettings,
+            ActivityLog, ActivityLogRow, AiReport, AiReportRow,
+            AiSettings, GitRepository, JournalSettings, RedactionRule, RedactionSettings,
+            RetentionSettings, ServerSettings,
         },
-        upsert_ai_settings,
+        get_journal_settings as load_journal_settings,
+        get_redaction_settings as load_redaction_settings,
+        get_retention_settings as load_retention_settings, upsert_ai_settings,
+        set_ai_local_only, set_audit_store_prompts, set_encrypted_api_key, upsert_journal_settings, upsert_redaction_settings,
+        upsert_retention_settings, upsert_server_settings,
+    },
+    events::{ChronicleEvent, EventBus},
+    export::{self, journal, jsonl, timesheet},
+    git, privacy, retention,
+    sanitizer::{self, rules::CompiledRule, Redactor},
+    secrets::{mask_secret, ApiToken, SecretStore},
+    server::{
+        endpoint::{self, ServerHealth, ServerStatus},
+        payloads,
     },
+    tray,
 };
 
+/// Logs matching `query`, newest first, one page at a time
 #[tauri::command]
-pub async fn get_logs_by_date(
-    date: String,
+pub async fn query_logs(
+    query: LogQuery,
     pool: State<'_, SqlitePool>,
-) -> Result<Vec<ActivityLog>, String> {
-    let parsed_date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
-        .map_err(|err| format!("invalid date format: {err}"))?;
-
-    let start = DateTime::<Utc>::from_naive_utc_and_offset(
-        parsed_date
-            .and_hms_opt(0, 0, 0)
-            .ok_or_else(|| "failed to construct start time".to_string())?,
-        Utc,
-    );
-    let end = start + Duration::days(1);
-
-    let rows = sqlx::query_as::<_, ActivityLogRow>(
-        "SELECT id, source, payload, timestamp, log_type, session_id, command, url, domain, title, file_path FROM activity_logs \
-         WHERE timestamp >= ?1 AND timestamp < ?2 \
-         ORDER BY timestamp DESC",
-    )
-    .bind(start.to_rfc3339())
-    .bind(end.to_rfc3339())
-    .fetch_all(pool.inner())
-    .await
-    .map_err(|err| err.to_string())?;
-
-    rows
-        .into_iter()
-        .map(ActivityLog::try_from)
-        .collect::<Result<Vec<_>, ActivityLogConversionError>>()
-        .map_err(|err| err.0)
+) -> Result<Page<ActivityLog>, String> {
+    db::query::query_logs(pool.inner(), &query)
+        .await
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
 pub async fn get_ai_reports(
     pool: State<'_, SqlitePool>,
 ) -> Result<Vec<AiReport>, String> {
-    sqlx::query_as::<_, AiReportRow>(
+    let rows = sqlx::query_as::<_, AiReportRow>(
         "SELECT id, summary, generated_at, log_count, sources, session_id FROM ai_reports ORDER BY generated_at DESC LIMIT 20",
     )
     .fetch_all(pool.inner())
     .await
-    .map_err(|err| err.to_string())?
-    .into_iter()
-    .map(AiReport::try_from)
-    .collect::<Result<Vec<_>, ActivityLogConversionError>>()
-    .map_err(|err| err.0)
+    .map_err(|err| err.to_string())?;
+
+    Ok(quarantine::load_reports(pool.inner(), rows).await)
 }
 
+/// Reports matching `query`, newest first, one page at a time
 #[tauri::command]
-pub async fn get_reports_by_date(
-    date: String,
+pub async fn query_reports(
+    query: ReportQuery,
     pool: State<'_, SqlitePool>,
-) -> Result<Vec<AiReport>, String> {
-    let parsed_date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
-        .map_err(|err| format!("invalid date format: {err}"))?;
-
-    let start = DateTime::<Utc>::from_naive_utc_and_offset(
-        parsed_date
-            .and_hms_opt(0, 0, 0)
-            .ok_or_else(|| "failed to construct start time".to_string())?,
-        Utc,
-    );
-    let end = start + Duration::days(1);
-
-    sqlx::query_as::<_, AiReportRow>(
-        "SELECT id, summary, generated_at, log_count, sources, session_id FROM ai_reports \
-         WHERE generated_at >= ?1 AND generated_at < ?2 \
-         ORDER BY generated_at DESC",
-    )
-    .bind(start.to_rfc3339())
-    .bind(end.to_rfc3339())
-    .fetch_all(pool.inner())
-    .await
-    .map_err(|err| err.to_string())?
-    .into_iter()
-    .map(AiReport::try_from)
-    .collect::<Result<Vec<_>, ActivityLogConversionError>>()
-    .map_err(|err| err.0)
+) -> Result<Page<AiReport>, String> {
+    db::query::query_reports(pool.inner(), &query)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Log and report counts in `range`, all time when it is empty
+#[tauri::command]
+pub async fn fetch_activity_stats(
+    range: TimeRange,
+    pool: State<'_, SqlitePool>,
+) -> Result<ActivityStats, String> {
+    db::query::activity_stats(pool.inner(), &range)
+        .await
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command]
 pub async fn trigger_manual_summary(
     pool: State<'_, SqlitePool>,
     ai_client: State<'_, AiClient>,
+    events: State<'_, EventBus>,
 ) -> Result<String, String> {
     let end = Utc::now();
     let start = end - Duration::minutes(15);
 
-    let rows = sqlx::query_as::<_, ActivityLogRow>(
-        "SELECT id, source, payload, timestamp, log_type, session_id, command, url, domain, title, file_path FROM activity_logs \
+    let rows = sqlx::query_as::<_, ActivityLogRow>(&format!(
+        "SELECT {LOG_COLUMNS} FROM activity_logs \
          WHERE timestamp >= ?1 AND timestamp <= ?2 \
-         ORDER BY timestamp ASC",
-    )
-    .bind(start.to_rfc3339())
-    .bind(end.to_rfc3339())
+         ORDER BY timestamp ASC"
+    ))
+    .bind(start.timestamp_millis())
+    .bind(end.timestamp_millis())
     .fetch_all(pool.inner())
     .await
     .map_err(|err| err.to_string())?;
 
-    let logs = rows
-        .into_iter()
-        .map(ActivityLog::try_from)
-        .collect::<Result<Vec<_>, ActivityLogConversionError>>()
-        .map_err(|err| err.0)?;
+    let logs = quarantine::load_logs(pool.inner(), rows).await;
 
     if logs.is_empty() {
         return Err("No logs found in the last 15 minutes".to_string());
     }
 
-    let summary = generate_summary(pool.inner(), ai_client.inner(), logs)
+    let generated = match generate_summary(pool.inner(), ai_client.inner(), logs).await {
+        Ok(generated) => generated,
+        Err(err) => {
+            events.publish(ChronicleEvent::SummaryFailed {
+                error: err.to_string(),
+                at: Utc::now(),
+            });
+            return Err(err.to_string());
+        }
+    };
+
+    let report = insert_ai_report(pool.inner(), &generated.summary, end)
         .await
         .map_err(|err| err.to_string())?;
 
-    insert_ai_report(pool.inner(), &summary, end)
+    audit::link_report(pool.inner(), generated.request_id, report.last_insert_rowid())
         .await
         .map_err(|err| err.to_string())?;
 
-    Ok(summary)
+    events.publish(ChronicleEvent::ReportCreated {
+        report: AiReport {
+            id: report.last_insert_rowid(),
+            summary: generated.summary.clone(),
+            generated_at: end,
+            log_count: None,
+            sources: None,
+            session_id: None,
+        },
+    });
+
+    Ok(generated.summary)
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SaveAiSettingsPayload {
     pub provider_url: String,
+    /// New API key. Leaving it empty keeps the stored key.
     pub api_key: Option<String>,
+    #[serde(default)]
+    pub clear_api_key: bool,
     pub model_name: String,
+    /// Turn local-only mode on or off. Leaving it out keeps the current mode.
+    pub local_only: Option<bool>,
+    /// Keep full prompts in the request audit trail (`false` keeps only their hashes)
+    pub audit_store_prompts: Option<bool>,
 }
 
 #[tauri::command]
 pub async fn save_ai_settings(
     pool: State<'_, SqlitePool>,
+    secrets: State<'_, SecretStore>,
     settings: SaveAiSettingsPayload,
 ) -> Result<(), String> {
     let provider_url = settings.provider_url.trim();
@@ -173,32 +188,105 @@ pub async fn save_ai_settings(
         return Err("Model name must not be empty".to_string());
     }
 
-    upsert_ai_settings(pool.inner(), provider_url, api_key.as_deref(), model_name)
+    let local_only = match settings.local_only {
+        Some(local_only) => local_only,
+        None => {
+            load_ai_settings(pool.inner())
+                .await
+                .map_err(|err| err.to_string())?
+                .local_only
+        }
+    };
+    if local_only {
+        client::resolve_local_provider(provider_url)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    upsert_ai_settings(pool.inner(), provider_url, model_name)
         .await
-        .map_err(|err| err.to_string())
+        .map_err(|err| err.to_string())?;
+
+    set_ai_local_only(pool.inner(), local_only)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    if let Some(store_prompts) = settings.audit_store_prompts {
+        set_audit_store_prompts(pool.inner(), store_prompts)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    if settings.clear_api_key {
+        set_encrypted_api_key(pool.inner(), None)
+            .await
+            .map_err(|err| err.to_string())?;
+    } else if let Some(api_key) = api_key {
+        // A re-key rewrites the stored API key as well
+        let _writes = crypto::write_access().await;
+        let encrypted = secrets.encrypt(&api_key).map_err(|err| err.to_string())?;
+        set_encrypted_api_key(pool.inner(), Some(&encrypted))
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
 }
 
+/// AI settings as shown to the frontend. The API key itself never leaves the backend.
 #[derive(Debug, Serialize)]
 pub struct AiSettingsResponse {
     pub provider_url: String,
-    pub api_key: Option<String>,
+    pub has_api_key: bool,
+    pub api_key_hint: Option<String>,
     pub model_name: String,
+    pub local_only: bool,
+    pub local_only_changed_at: Option<StoredTimestamp>,
+    pub audit_store_prompts: bool,
 }
 
 #[tauri::command]
 pub async fn fetch_ai_settings(
     pool: State<'_, SqlitePool>,
+    secrets: State<'_, SecretStore>,
 ) -> Result<AiSettingsResponse, String> {
     let settings: AiSettings = load_ai_settings(pool.inner())
         .await
         .map_err(|err| err.to_string())?;
 
+    let stored_key = settings.api_key.filter(|key| !key.is_empty());
+    let api_key_hint = match &stored_key {
+        Some(key) => Some(
+            secrets
+                .decrypt(key)
+                .map(|key| mask_secret(&key))
+                .unwrap_or_else(|_| "unreadable".to_string()),
+        ),
+        None => None,
+    };
+
     Ok(AiSettingsResponse {
         provider_url: settings.provider_url,
-        api_key: settings.api_key,
+        has_api_key: stored_key.is_some(),
+        api_key_hint,
         model_name: settings.model_name,
+        local_only: settings.local_only,
+        local_only_changed_at: settings.local_only_changed_at,
+        audit_store_prompts: settings.audit_store_prompts,
     })
 }
+
+/// The exact request a report was generated from, for compliance reviews and debugging summaries
+#[tauri::command]
+pub async fn get_report_prompt(
+    pool: State<'_, SqlitePool>,
+    report_id: i64,
+) -> Result<Option<AiRequestRecord>, String> {
+    audit::for_report(pool.inner(), report_id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
 #[tauri::command]
 pub async fn test_ai_connection(
     pool: State<'_, SqlitePool>,
@@ -262,16 +350,587 @@ pub async fn test_ai_connection(
     }
 }
 
+#[tauri::command]
+pub async fn list_git_repositories(
+    pool: State<'_, SqlitePool>,
+) -> Result<Vec<GitRepository>, String> {
+    git::list_repositories(pool.inner())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn add_git_repository(
+    path: String,
+    pool: State<'_, SqlitePool>,
+) -> Result<String, String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("Repository path must not be empty".to_string());
+    }
+
+    git::add_repository(pool.inner(), path)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn remove_git_repository(
+    id: i64,
+    pool: State<'_, SqlitePool>,
+) -> Result<(), String> {
+    git::remove_repository(pool.inner(), id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Ask the user where to save a file. Returns `None` when the dialog is cancelled.
+async fn pick_save_path(
+    app: &AppHandle,
+    file_name: &str,
+    filter_name: &str,
+    extension: &str,
+) -> Result<Option<PathBuf>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .add_filter(filter_name, &[extension])
+        .set_file_name(file_name)
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    rx.await
+        .map_err(|err| err.to_string())?
+        .map(|path| path.into_path().map_err(|err| err.to_string()))
+        .transpose()
+}
+
+/// Ask the user to pick an existing file. Returns `None` when the dialog is cancelled.
+async fn pick_open_path(
+    app: &AppHandle,
+    filter_name: &str,
+    extension: &str,
+) -> Result<Option<PathBuf>, String> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+
+    app.dialog()
+        .file()
+        .add_filter(filter_name, &[extension])
+        .pick_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    rx.await
+        .map_err(|err| err.to_string())?
+        .map(|path| path.into_path().map_err(|err| err.to_string()))
+        .transpose()
+}
+
+#[tauri::command]
+pub async fn export_timesheet(
+    app: AppHandle,
+    start_date: String,
+    end_date: String,
+    format: String,
+    options: Option<timesheet::TimesheetOptions>,
+    pool: State<'_, SqlitePool>,
+) -> Result<Option<String>, String> {
+    let (start, end) = export::parse_date_range(&start_date, &end_date).map_err(|err| err.to_string())?;
+
+    let logs = export::fetch_logs_between(pool.inner(), start, end)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let entries = timesheet::build_time_entries(logs, &options.unwrap_or_default());
+
+    let (contents, filter_name, extension) = match format.as_str() {
+        "csv" => (timesheet::to_csv(&entries), "CSV", "csv"),
+        "ics" => (timesheet::to_ics(&entries), "iCalendar", "ics"),
+        other => return Err(format!("unsupported timesheet format: {other}")),
+    };
+
+    let file_name = format!("timesheet_{start_date}_{end_date}.{extension}");
+    let Some(path) = pick_save_path(&app, &file_name, filter_name, extension).await? else {
+        return Ok(None);
+    };
+
+    tokio::fs::write(&path, contents)
+        .await
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+
+    Ok(Some(path.to_string_lossy().into_owned()))
+}
+
+#[tauri::command]
+pub async fn fetch_journal_settings(
+    pool: State<'_, SqlitePool>,
+) -> Result<JournalSettings, String> {
+    load_journal_settings(pool.inner())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn save_journal_settings(
+    pool: State<'_, SqlitePool>,
+    settings: JournalSettings,
+) -> Result<(), String> {
+    let vault_dir = settings
+        .vault_dir
+        .as_deref()
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty());
+
+    if settings.auto_export && vault_dir.is_none() {
+        return Err("Choose a vault directory before enabling auto-export".to_string());
+    }
+
+    upsert_journal_settings(pool.inner(), vault_dir, settings.auto_export)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn export_journal(
+    full: Option<bool>,
+    pool: State<'_, SqlitePool>,
+) -> Result<journal::JournalExportResult, String> {
+    let settings = load_journal_settings(pool.inner())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let vault_dir = settings
+        .vault_dir
+        .filter(|dir| !dir.is_empty())
+        .ok_or_else(|| "No journal vault directory configured".to_string())?;
+
+    journal::export_journal(pool.inner(), std::path::Path::new(&vault_dir), full.unwrap_or(false))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn export_chronicle(
+    app: AppHandle,
+    pool: State<'_, SqlitePool>,
+) -> Result<Option<jsonl::ExportSummary>, String> {
+    let file_name = format!("devchronicle_{}.jsonl", Utc::now().format("%Y%m%d_%H%M%S"));
+    let Some(path) = pick_save_path(&app, &file_name, "JSON Lines", "jsonl").await? else {
+        return Ok(None);
+    };
+
+    jsonl::export_chronicle(pool.inner(), &path)
+        .await
+        .map(Some)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn import_chronicle(
+    app: AppHandle,
+    apply_settings: Option<bool>,
+    pool: State<'_, SqlitePool>,
+) -> Result<Option<jsonl::ImportSummary>, String> {
+    let Some(path) = pick_open_path(&app, "JSON Lines", "jsonl").await? else {
+        return Ok(None);
+    };
+
+    jsonl::import_chronicle(pool.inner(), &path, apply_settings.unwrap_or(false))
+        .await
+        .map(Some)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn fetch_retention_settings(
+    pool: State<'_, SqlitePool>,
+) -> Result<RetentionSettings, String> {
+    load_retention_settings(pool.inner())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn save_retention_settings(
+    pool: State<'_, SqlitePool>,
+    settings: RetentionSettings,
+) -> Result<(), String> {
+    let invalid = [settings.raw_log_days, settings.report_days, settings.vacuum_interval_hours]
+        .into_iter()
+        .flatten()
+        .any(|value| value < 0);
+    if invalid {
+        return Err("Retention periods must not be negative".to_string());
+    }
+
+    upsert_retention_settings(pool.inner(), &settings)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Dry run of the retention policy: what would be deleted and roughly how much space that frees
+#[tauri::command]
+pub async fn preview_retention(
+    pool: State<'_, SqlitePool>,
+) -> Result<retention::RetentionReport, String> {
+    retention::preview(pool.inner(), Utc::now())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn fetch_redaction_settings(
+    pool: State<'_, SqlitePool>,
+) -> Result<RedactionSettings, String> {
+    load_redaction_settings(pool.inner())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn save_redaction_settings(
+    pool: State<'_, SqlitePool>,
+    settings: RedactionSettings,
+) -> Result<(), String> {
+    upsert_redaction_settings(pool.inner(), &settings)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn list_redaction_rules(
+    pool: State<'_, SqlitePool>,
+) -> Result<Vec<RedactionRule>, String> {
+    db::list_redaction_rules(pool.inner())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Create (id 0) or update a redaction rule. Invalid patterns are rejected before anything is stored.
+#[tauri::command]
+pub async fn save_redaction_rule(
+    pool: State<'_, SqlitePool>,
+    rule: RedactionRule,
+) -> Result<RedactionRule, String> {
+    CompiledRule::compile(&rule)?;
+
+    let id = db::save_redaction_rule(pool.inner(), &rule)
+        .await
+        .map_err(|err| err.to_string())?;
+    sanitizer::rules::reload(pool.inner())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    Ok(RedactionRule { id, ..rule })
+}
+
+#[tauri::command]
+pub async fn delete_redaction_rule(
+    id: i64,
+    pool: State<'_, SqlitePool>,
+) -> Result<(), String> {
+    db::delete_redaction_rule(pool.inner(), id)
+        .await
+        .map_err(|err| err.to_string())?;
+    sanitizer::rules::reload(pool.inner())
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[derive(Serialize)]
+pub struct RedactionPreview {
+    pub redacted: String,
+    pub redactions: usize,
+}
+
+/// Preview how `sample` would be stored if it arrived from `source` under payload key `field`.
+/// An unsaved `rule` can be passed to try it out alongside the active rules.
+#[tauri::command]
+pub async fn test_redaction(
+    pool: State<'_, SqlitePool>,
+    sample: String,
+    source: Option<String>,
+    field: Option<String>,
+    rule: Option<RedactionRule>,
+) -> Result<RedactionPreview, String> {
+    let settings = load_redaction_settings(pool.inner())
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let current = sanitizer::rules::current();
+    let rules = match rule {
+        Some(rule) => current.with_rule(CompiledRule::compile(&rule)?),
+        None => current.as_ref().clone(),
+    };
+
+    let (redacted, redactions) = Redactor::new(settings.options(), &rules, source.as_deref())
+        .redact_text(&sample, field.as_deref());
+
+    Ok(RedactionPreview { redacted, redactions })
+}
+
+#[tauri::command]
+pub async fn fetch_privacy_status(
+    pool: State<'_, SqlitePool>,
+) -> Result<privacy::PrivacyStatus, String> {
+    privacy::status(pool.inner(), Utc::now())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Rows skipped by the timeline and summaries because they couldn't be read
+#[tauri::command]
+pub async fn list_quarantined_rows(
+    pool: State<'_, SqlitePool>,
+) -> Result<Vec<QuarantinedRow>, String> {
+    quarantine::list(pool.inner())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn repair_quarantined_row(
+    pool: State<'_, SqlitePool>,
+    id: i64,
+    action: RepairAction,
+) -> Result<(), String> {
+    quarantine::repair(pool.inner(), id, action)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// The time zone setting, the offset it currently resolves to and today's date there
+#[derive(Debug, Serialize)]
+pub struct TimeSettingsResponse {
+    pub timezone: String,
+    pub utc_offset: String,
+    pub today: String,
+}
+
+fn time_settings_response(zone: time::DayZone) -> TimeSettingsResponse {
+    let now = zone.to_local(Utc::now());
+    TimeSettingsResponse {
+        timezone: zone.to_string(),
+        utc_offset: now.offset().to_string(),
+        today: now.date_naive().to_string(),
+    }
+}
+
+#[tauri::command]
+pub async fn fetch_time_settings() -> Result<TimeSettingsResponse, String> {
+    Ok(time_settings_response(time::current()))
+}
+
+/// Set where days begin: "system", "UTC" or a fixed offset like "-07:00"
+#[tauri::command]
+pub async fn save_time_settings(
+    pool: State<'_, SqlitePool>,
+    timezone: String,
+) -> Result<TimeSettingsResponse, String> {
+    time::set_timezone(pool.inner(), &timezone)
+        .await
+        .map(time_settings_response)
+        .map_err(|err| err.to_string())
+}
+
+/// How many incoming events failed schema validation, today and overall
+#[tauri::command]
+pub async fn fetch_rejected_events(
+    pool: State<'_, SqlitePool>,
+) -> Result<payloads::RejectedEvents, String> {
+    payloads::rejected_events(pool.inner(), Utc::now())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Stop recording for `minutes`. Events arriving meanwhile are counted and discarded.
+#[tauri::command]
+pub async fn pause_recording(
+    app: AppHandle,
+    pool: State<'_, SqlitePool>,
+    minutes: i64,
+) -> Result<privacy::PrivacyStatus, String> {
+    privacy::pause(pool.inner(), Some(minutes), Utc::now())
+        .await
+        .map_err(|err| err.to_string())?;
+    tray::sync_pause_toggle(&app);
+
+    privacy::status(pool.inner(), Utc::now())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn resume_recording(
+    app: AppHandle,
+    pool: State<'_, SqlitePool>,
+) -> Result<privacy::PrivacyStatus, String> {
+    privacy::pause(pool.inner(), None, Utc::now())
+        .await
+        .map_err(|err| err.to_string())?;
+    tray::sync_pause_toggle(&app);
+
+    privacy::status(pool.inner(), Utc::now())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn list_privacy_exclusions(
+    pool: State<'_, SqlitePool>,
+) -> Result<Vec<privacy::PrivacyExclusion>, String> {
+    privacy::list_exclusions(pool.inner())
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Exclude events by `kind`: "domain", "url" (regex), "path" (glob, `~` allowed) or "command" (prefix)
+#[tauri::command]
+pub async fn add_privacy_exclusion(
+    pool: State<'_, SqlitePool>,
+    kind: String,
+    pattern: String,
+) -> Result<privacy::PrivacyExclusion, String> {
+    privacy::add_exclusion(pool.inner(), &kind, &pattern)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn remove_privacy_exclusion(
+    id: i64,
+    pool: State<'_, SqlitePool>,
+) -> Result<(), String> {
+    privacy::remove_exclusion(pool.inner(), id)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn fetch_encryption_status(
+    pool: State<'_, SqlitePool>,
+) -> Result<crypto::EncryptionStatus, String> {
+    crypto::status(pool.inner()).await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn enable_database_encryption(
+    pool: State<'_, SqlitePool>,
+) -> Result<crypto::EncryptionStatus, String> {
+    crypto::enable(pool.inner()).await.map_err(|err| err.to_string())?;
+    crypto::status(pool.inner()).await.map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub async fn disable_database_encryption(
+    pool: State<'_, SqlitePool>,
+) -> Result<crypto::EncryptionStatus, String> {
+    crypto::disable(pool.inner()).await.map_err(|err| err.to_string())?;
+    crypto::status(pool.inner()).await.map_err(|err| err.to_string())
+}
+
+/// Replace the key file with a freshly generated key and re-encrypt all stored data with it
+#[tauri::command]
+pub async fn rekey_database(
+    app: AppHandle,
+    pool: State<'_, SqlitePool>,
+    secrets: State<'_, SecretStore>,
+) -> Result<crypto::EncryptionStatus, String> {
+    let data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|_| "failed to resolve app data directory".to_string())?;
+    let passphrase = std::env::var(crate::secrets::PASSPHRASE_ENV).ok();
+
+    crypto::rekey(pool.inner(), secrets.inner(), &data_dir, passphrase.as_deref())
+        .await
+        .map_err(|err| err.to_string())?;
+    crypto::status(pool.inner()).await.map_err(|err| err.to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiTokenResponse {
+    pub token: String,
+    /// File scripts can read the token from
+    pub path: PathBuf,
+}
+
+impl From<&ApiToken> for ApiTokenResponse {
+    fn from(token: &ApiToken) -> Self {
+        Self {
+            token: token.current(),
+            path: token.path().to_path_buf(),
+        }
+    }
+}
+
+/// Token for the local read API (`/api/*`)
+#[tauri::command]
+pub async fn fetch_api_token(api_token: State<'_, ApiToken>) -> Result<ApiTokenResponse, String> {
+    Ok(ApiTokenResponse::from(api_token.inner()))
+}
+
+/// Issue a new API token. Clients using the old one get 401 from then on.
+#[tauri::command]
+pub async fn rotate_api_token(api_token: State<'_, ApiToken>) -> Result<ApiTokenResponse, String> {
+    api_token.rotate().map_err(|err| err.to_string())?;
+    Ok(ApiTokenResponse::from(api_token.inner()))
+}
+
+/// Whether the ingestion server is running, and where
+#[tauri::command]
+pub async fn fetch_server_status(health: State<'_, ServerHealth>) -> Result<ServerStatus, String> {
+    Ok(health.current())
+}
+
+/// The stored server settings and which of them environment variables override
+#[derive(Debug, Serialize)]
+pub struct ServerSettingsResponse {
+    #[serde(flatten)]
+    pub settings: ServerSettings,
+    pub env_overrides: Vec<&'static str>,
+}
+
+#[tauri::command]
+pub async fn fetch_server_settings(pool: State<'_, SqlitePool>) -> Result<ServerSettingsResponse, String> {
+    let settings = db::get_server_settings(pool.inner())
+        .await
+        .map_err(|err| err.to_string())?;
+    Ok(ServerSettingsResponse {
+        settings,
+        env_overrides: endpoint::env_overrides(),
+    })
+}
+
+/// Store where the server listens. Takes effect on the next start.
+#[tauri::command]
+pub async fn save_server_settings(
+    pool: State<'_, SqlitePool>,
+    mut settings: ServerSettings,
+) -> Result<(), String> {
+    settings.host = settings.host.trim().to_string();
+    endpoint::validate(&settings).map_err(|err| err.to_string())?;
+    upsert_server_settings(pool.inner(), &settings)
+        .await
+        .map_err(|err| err.to_string())
+}
+
 async fn insert_ai_report(
     pool: &SqlitePool,
     summary: &str,
     generated_at: DateTime<Utc>,
-) -> Result<SqliteQueryResult, sqlx::Error> {
-    sqlx::query(
+) -> Result<SqliteQueryResult, anyhow::Error> {
+    let _writes = crypto::write_access().await;
+    let result = sqlx::query(
         "INSERT INTO ai_reports (summary, generated_at) VALUES (?1, ?2)",
     )
-    .bind(summary)
-    .bind(generated_at.to_rfc3339())
+    .bind(crypto::seal(summary)?)
+    .bind(generated_at.timestamp_millis())
     .execute(pool)
-    .await
+    .await?;
+
+    Ok(result)
 }
@@ -0,0 +1,487 @@
+mod output;
+mod source;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error};
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use serde_json::json;
+
+use crate::{
+    ai::{self, client::AiClient},
+    db::{
+        models::ActivityLog,
+        query::{LogQuery, Page, ReportQuery},
+        time,
+    },
+    engine::{default_data_dir, lock_data_dir},
+    export::jsonl,
+    sanitizer::expand_home,
+    DB_FILENAME,
+};
+use source::{Access, Source};
+
+const USAGE: &str = "Usage:
+  devchronicle today [--source <name>] [--limit <n>]
+  devchronicle search <text> [--source <name>] [--limit <n>]
+  devchronicle summarize --last <duration>
+  devchronicle export (--today | --week | --from <date> --to <date>) [--format md|json]
+  devchronicle export-jsonl <file>
+  devchronicle import-jsonl <file> [--with-settings]
+
+Options:
+  --json         Print JSON instead of text
+  --db <path>    Database file, defaults to the app's
+  --api          Read through the local API of the running app or daemon
+
+Durations look like 30m, 1h or 2d. Dates are YYYY-MM-DD in the configured time zone.";
+
+/// Events listed by `today` and `search` unless `--limit` says otherwise
+const DEFAULT_LIMIT: i64 = 50;
+
+enum Period {
+    Today,
+    /// Monday through Sunday of the current week
+    Week,
+    Days(NaiveDate, NaiveDate),
+}
+
+enum Command {
+    Today { source: Option<String>, limit: i64 },
+    Search { text: String, source: Option<String>, limit: i64 },
+    Summarize { last: Duration },
+    Export { period: Period },
+    /// Back up the whole chronicle as JSON Lines
+    ExportJsonl { file: PathBuf },
+    ImportJsonl { file: PathBuf, with_settings: bool },
+}
+
+struct CliArgs {
+    command: Command,
+    json: bool,
+    db_path: Option<PathBuf>,
+    api: bool,
+}
+
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{value}', expected e.g. 30m, 1h or 2d");
+    let (split, _) = value.char_indices().last().ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let duration = match unit {
+        "m" if amount > 0 => Duration::try_minutes(amount),
+        "h" if amount > 0 => Duration::try_hours(amount),
+        "d" if amount > 0 => Duration::try_days(amount),
+        _ => return Err(invalid()),
+    };
+
+    // Durations reach back from now, so they must not go past the earliest representable time
+    duration
+        .filter(|duration| Utc::now().checked_sub_signed(*duration).is_some())
+        .ok_or_else(|| format!("duration '{value}' is too long"))
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| format!("invalid date '{value}', expected YYYY-MM-DD"))
+}
+
+fn parse_args(args: &[String]) -> Result<CliArgs, String> {
+    let command = args.first().map(String::as_str).unwrap_or_default();
+    let mut json = false;
+    let mut db_path = None;
+    let mut api = false;
+    let mut source = None;
+    let mut limit = DEFAULT_LIMIT;
+    let mut last = None;
+    let mut period = None;
+    let mut from = None;
+    let mut to = None;
+    let mut text = None;
+    let mut with_settings = false;
+
+    let mut rest = args.iter().skip(1);
+    while let Some(arg) = rest.next() {
+        let mut value = || rest.next().map(String::as_str).ok_or(format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--json" => json = true,
+            "--format" => match value()? {
+                "json" => json = true,
+                "md" | "markdown" => json = false,
+                other => return Err(format!("unknown format '{other}', expected md or json")),
+            },
+            "--db" => db_path = Some(PathBuf::from(expand_home(value()?))),
+            "--api" => api = true,
+            "--source" => source = Some(value()?.to_string()),
+            "--limit" => {
+                limit = value()?
+                    .parse()
+                    .ok()
+                    .filter(|limit| *limit > 0)
+                    .ok_or("--limit needs a positive number")?
+            }
+            "--last" => last = Some(parse_duration(value()?)?),
+            "--today" => period = Some(Period::Today),
+            "--week" => period = Some(Period::Week),
+            "--from" => from = Some(parse_date(value()?)?),
+            "--to" => to = Some(parse_date(value()?)?),
+            "--with-settings" => with_settings = true,
+            positional if text.is_none() && !positional.starts_with("--") => text = Some(positional.to_string()),
+            other => return Err(format!("unexpected argument: {other}")),
+        }
+    }
+
+    let command = match command {
+        "today" => Command::Today { source, limit },
+        "search" => Command::Search {
+            text: text.take().ok_or("missing search text")?,
+            source,
+            limit,
+        },
+        "summarize" => Command::Summarize {
+            last: last.ok_or("summarize needs --last, e.g. --last 1h")?,
+        },
+        "export" => {
+            let period = match (period, from, to) {
+                (Some(period), None, None) => period,
+                (None, Some(from), Some(to)) if from <= to => Period::Days(from, to),
+                (None, Some(_), Some(_)) => return Err("--from must not be after --to".to_string()),
+                _ => return Err("export needs one of --today, --week or --from with --to".to_string()),
+            };
+            Command::Export { period }
+        }
+        "export-jsonl" => Command::ExportJsonl {
+            file: PathBuf::from(expand_home(&text.take().ok_or("missing file argument")?)),
+        },
+        "import-jsonl" => Command::ImportJsonl {
+            file: PathBuf::from(expand_home(&text.take().ok_or("missing file argument")?)),
+            with_settings,
+        },
+        "" => return Err("missing command".to_string()),
+        other => return Err(format!("unknown command: {other}")),
+    };
+
+    if let Some(text) = text {
+        return Err(format!("unexpected argument: {text}"));
+    }
+
+    Ok(CliArgs {
+        command,
+        json,
+        db_path,
+        api,
+    })
+}
+
+fn to_json(value: &impl serde::Serialize) -> Result<String, Error> {
+    Ok(serde_json::to_string_pretty(value)?)
+}
+
+/// Human output of `today` and `search`: the page oldest first, and how many more matched
+fn page_text(page: &Page<ActivityLog>, with_date: bool, empty: &str) -> String {
+    if page.items.is_empty() {
+        return empty.to_string();
+    }
+
+    let mut text = output::log_lines(time::current(), &page.items, with_date);
+    let hidden = page.total - page.items.len() as i64;
+    if hidden > 0 {
+        text.push_str(&format!(
+            "\n\n{} of {} events shown, use --limit to see more",
+            page.items.len(),
+            page.total
+        ));
+    }
+    text
+}
+
+async fn run_command(args: CliArgs, source: &Source) -> Result<String, Error> {
+    match args.command {
+        Command::Today { source: log_source, limit } => {
+            let today = time::current().local_date(Utc::now());
+            let query = LogQuery {
+                from_date: Some(today),
+                to_date: Some(today),
+                source: log_source,
+                limit: Some(limit),
+                ..Default::default()
+            };
+            let mut page = source.logs(&query).await?;
+            page.items = page.items.into_iter().map(output::redact).collect();
+
+            if args.json {
+                return to_json(&page);
+            }
+            Ok(page_text(&page, false, "No activity recorded today."))
+        }
+        Command::Search { text, source: log_source, limit } => {
+            let query = LogQuery {
+                text: Some(text),
+                source: log_source,
+                limit: Some(limit),
+                ..Default::default()
+            };
+            let mut page = source.logs(&query).await?;
+            page.items = page.items.into_iter().map(output::redact).collect();
+
+            if args.json {
+                return to_json(&page);
+            }
+            Ok(page_text(&page, true, "No matching events."))
+        }
+        Command::Summarize { last } => {
+            let Source::Database {
+                pool,
+                secret_store: Some(secret_store),
+                ..
+            } = source
+            else {
+                return Err(anyhow!("summarize needs the database, it can't run with --api"));
+            };
+
+            let end = Utc::now();
+            let start = end
+                .checked_sub_signed(last)
+                .ok_or_else(|| anyhow!("--last reaches back too far"))?;
+            let logs = source
+                .all_logs(LogQuery {
+                    start: Some(start),
+                    end: Some(end),
+                    ..Default::default()
+                })
+                .await?;
+            let log_count = logs.len();
+
+            // Sanitized and pseudonymized like the app's summaries, and recorded in the audit trail
+            let summary = if logs.is_empty() {
+                None
+            } else {
+                let ai_client = AiClient::new(secret_store.clone());
+                Some(ai::generate_summary(pool, &ai_client, logs).await?)
+            };
+
+            if args.json {
+                return to_json(&json!({
+                    "summary": summary.as_ref().map(|summary| &summary.summary),
+                    "request_id": summary.as_ref().map(|summary| summary.request_id),
+                    "log_count": log_count,
+                    "start": start,
+                    "end": end,
+                }));
+            }
+            Ok(match summary {
+                Some(summary) => format!(
+                    "{}\n\n{}",
+                    output::summary_header(time::current(), log_count, start),
+                    summary.summary.trim()
+                ),
+                None => "No activity to summarize.".to_string(),
+            })
+        }
+        Command::Export { period } => {
+            let zone = time::current();
+            let today = zone.local_date(Utc::now());
+            let (first, last) = match period {
+                Period::Today => (today, today),
+                Period::Week => {
+                    let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                    (monday, monday + Duration::days(6))
+                }
+                Period::Days(first, last) => (first, last),
+            };
+
+            let mut logs: Vec<ActivityLog> = source
+                .all_logs(LogQuery {
+                    from_date: Some(first),
+                    to_date: Some(last),
+                    ..Default::default()
+                })
+                .await?
+                .into_iter()
+                .map(output::redact)
+                .collect();
+            logs.reverse();
+            let mut reports = source
+                .all_reports(ReportQuery {
+                    from_date: Some(first),
+                    to_date: Some(last),
+                    ..Default::default()
+                })
+                .await?;
+            reports.reverse();
+
+            if args.json {
+                return to_json(&json!({
+                    "from": first,
+                    "to": last,
+                    "logs": logs,
+                    "reports": reports,
+                }));
+            }
+            Ok(output::markdown(zone, first, last, &logs, &reports))
+        }
+        Command::ExportJsonl { file } => {
+            let Source::Database { pool, .. } = source else {
+                return Err(anyhow!("export-jsonl needs the database, it can't run with --api"));
+            };
+
+            let summary = jsonl::export_chronicle(pool, &file).await?;
+            if args.json {
+                return to_json(&summary);
+            }
+            Ok(format!(
+                "Exported {} events, {} reports and {} repositories to {}",
+                summary.logs,
+                summary.reports,
+                summary.git_repositories,
+                file.display()
+            ))
+        }
+        Command::ImportJsonl { file, with_settings } => {
+            let Source::Database { pool, .. } = source else {
+                return Err(anyhow!("import-jsonl needs the database, it can't run with --api"));
+            };
+
+            let summary = jsonl::import_chronicle(pool, &file, with_settings).await?;
+            if args.json {
+                return to_json(&summary);
+            }
+            let mut text = format!(
+                "Imported {} events, {} reports and {} repositories, skipped {} events and {} reports already present",
+                summary.logs_imported,
+                summary.reports_imported,
+                summary.git_repositories_imported,
+                summary.logs_skipped,
+                summary.reports_skipped
+            );
+            if summary.settings_applied {
+                text.push_str("\nSettings were replaced by the backup's");
+            }
+            Ok(text)
+        }
+    }
+}
+
+async fn run(args: CliArgs) -> Result<String, Error> {
+    let db_path = match &args.db_path {
+        Some(path) => path.clone(),
+        None => default_data_dir()
+            .ok_or_else(|| anyhow!("could not determine the app data directory, pass --db"))?
+            .join(DB_FILENAME),
+    };
+
+    let data_dir = db_path.parent().unwrap_or_else(|| Path::new("."));
+    let mut lock = None;
+    let source = if args.api {
+        Source::open_api(data_dir)?
+    } else {
+        // Summaries are recorded in the audit trail, everything else but imports only reads
+        let access = match args.command {
+            Command::Summarize { .. } => Access::Write,
+            Command::ImportJsonl { .. } => {
+                // Restoring on a new machine starts without a data directory
+                std::fs::create_dir_all(data_dir)
+                    .with_context(|| format!("failed to create {}", data_dir.display()))?;
+                // Held until the import is done, so the app can't start on a half-imported database
+                lock = Some(
+                    lock_data_dir(data_dir)
+                        .context("quit the app or stop the daemon before importing, or import from the app's settings")?,
+                );
+                Access::Exclusive
+            }
+            _ => Access::Read,
+        };
+        Source::open_database(&db_path, access).await?
+    };
+
+    let output = run_command(args, &source).await;
+    source.close().await;
+    drop(lock);
+    output
+}
+
+/// Entry point of the `devchronicle` command. Returns the process exit code.
+pub fn run_console(args: &[String]) -> i32 {
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return if args.is_empty() { 2 } else { 0 };
+    }
+
+    let parsed = match parse_args(args) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            eprintln!("❌ Failed to start runtime: {err}");
+            return 1;
+        }
+    };
+
+    match runtime.block_on(run(parsed)) {
+        Ok(output) => {
+            println!("{output}");
+            0
+        }
+        Err(err) => {
+            eprintln!("❌ {err:#}");
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30m"), Ok(Duration::minutes(30)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::days(2)));
+
+        for invalid in ["", "h", "0h", "-1h", "1w", "1µ", "5é", "ñ"] {
+            assert!(parse_duration(invalid).is_err(), "{invalid}");
+        }
+
+        // Too long to represent, or to subtract from now
+        for too_long in ["99999999999d", "9223372036854775807m", "3000000000h"] {
+            assert_eq!(parse_duration(too_long), Err(format!("duration '{too_long}' is too long")));
+        }
+        assert!(parse_args(&["summarize".to_string(), "--last".to_string(), "99999999999d".to_string()]).is_err());
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_backup_commands() {
+        let parsed = parse_args(&args(&["import-jsonl", "backup.jsonl", "--with-settings"])).unwrap();
+        assert!(matches!(
+            parsed.command,
+            Command::ImportJsonl { file, with_settings: true } if file == Path::new("backup.jsonl")
+        ));
+        let parsed = parse_args(&args(&["export-jsonl", "backup.jsonl"])).unwrap();
+        assert!(matches!(parsed.command, Command::ExportJsonl { file } if file == Path::new("backup.jsonl")));
+
+        assert_eq!(parse_args(&args(&["export-jsonl"])).err().unwrap(), "missing file argument");
+        assert!(parse_args(&args(&["import-jsonl", "a.jsonl", "b.jsonl"])).is_err());
+    }
+
+    #[tokio::test]
+    async fn import_refuses_while_the_app_is_running() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join(DB_FILENAME);
+        let _app = lock_data_dir(dir.path()).unwrap();
+
+        let mut parsed = parse_args(&args(&["import-jsonl", "backup.jsonl"])).unwrap();
+        parsed.db_path = Some(db_path.clone());
+        let err = run(parsed).await.unwrap_err();
+        assert!(format!("{err:#}").contains("already using"), "{err:#}");
+        assert!(!db_path.exists());
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+
+use crate::{
+    db::{
+        models::{ActivityLog, AiReport},
+        time::DayZone,
+    },
+    export::{demote_headings, detect_projects},
+    sanitizer::{normalize_home_path, rules, sanitize_json, sanitize_text},
+};
+
+/// Longest description printed for one log
+const MAX_DESCRIPTION_CHARS: usize = 120;
+
+/// Redact a log again with the current rules, which may have been added after it was stored
+pub fn redact(mut log: ActivityLog) -> ActivityLog {
+    let rules = rules::current();
+    let source = log.source.clone();
+    let redact_text = |text: Option<String>| text.map(|text| sanitize_text(&rules, &text, Some(&source)));
+    log.payload = sanitize_json(&rules, &log.payload, Some(&source));
+    log.command = redact_text(log.command);
+    log.url = redact_text(log.url);
+    log.title = redact_text(log.title);
+    log.file_path = redact_text(log.file_path);
+    log
+}
+
+fn truncate(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(MAX_DESCRIPTION_CHARS) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+/// One line saying what happened, e.g. `$ cargo test (exit 101)` or `save ~/src/main.rs`
+pub fn describe(log: &ActivityLog) -> String {
+    let exit_code = log.payload.get("exit_code").and_then(Value::as_i64).filter(|code| *code != 0);
+
+    let description = if let Some(command) = &log.command {
+        match exit_code {
+            Some(code) => format!("$ {command} (exit {code})"),
+            None => format!("$ {command}"),
+        }
+    } else if log.title.is_some() || log.url.is_some() {
+        [log.title.as_deref(), log.url.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" — ")
+    } else if let Some(file_path) = &log.file_path {
+        let path = normalize_home_path(file_path);
+        match &log.log_type {
+            Some(log_type) => format!("{log_type} {path}"),
+            None => path,
+        }
+    } else {
+        log.log_type.clone().unwrap_or_else(|| log.payload.to_string())
+    };
+
+    truncate(&description)
+}
+
+/// Logs oldest first, one per line. `with_date` adds the local day for results spanning days.
+pub fn log_lines(zone: DayZone, logs: &[ActivityLog], with_date: bool) -> String {
+    let format = if with_date { "%Y-%m-%d %H:%M" } else { "%H:%M:%S" };
+    let mut logs: Vec<&ActivityLog> = logs.iter().collect();
+    logs.sort_by_key(|log| log.timestamp);
+
+    logs.iter()
+        .map(|log| {
+            format!(
+                "{}  {:<8}  {}",
+                zone.to_local(log.timestamp).format(format),
+                log.source,
+                describe(log)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// E.g. `14 events: terminal 8, vscode 6`
+fn count_line(logs: &[ActivityLog]) -> String {
+    let mut by_source: BTreeMap<&str, usize> = BTreeMap::new();
+    for log in logs {
+        *by_source.entry(log.source.as_str()).or_default() += 1;
+    }
+    let sources: Vec<String> = by_source.iter().map(|(source, count)| format!("{source} {count}")).collect();
+    format!("{} events: {}", logs.len(), sources.join(", "))
+}
+
+/// Markdown with a section per day that has activity: counts, projects and the day's reports
+pub fn markdown(
+    zone: DayZone,
+    first: NaiveDate,
+    last: NaiveDate,
+    logs: &[ActivityLog],
+    reports: &[AiReport],
+) -> String {
+    let mut logs_by_day: BTreeMap<NaiveDate, Vec<ActivityLog>> = BTreeMap::new();
+    for log in logs {
+        logs_by_day.entry(zone.local_date(log.timestamp)).or_default().push(log.clone());
+    }
+    let mut reports_by_day: BTreeMap<NaiveDate, Vec<&AiReport>> = BTreeMap::new();
+    for report in reports {
+        reports_by_day.entry(zone.local_date(report.generated_at)).or_default().push(report);
+    }
+
+    let mut markdown = if first == last {
+        format!("# Activity {first}\n\n")
+    } else {
+        format!("# Activity {first} – {last}\n\n")
+    };
+
+    let days: BTreeSet<NaiveDate> = logs_by_day.keys().chain(reports_by_day.keys()).copied().collect();
+    if days.is_empty() {
+        markdown.push_str("No activity recorded.");
+        return markdown;
+    }
+
+    for day in days {
+        markdown.push_str(&format!("## {}\n\n", day.format("%A, %Y-%m-%d")));
+
+        let day_logs = logs_by_day.remove(&day).unwrap_or_default();
+        if !day_logs.is_empty() {
+            markdown.push_str(&count_line(&day_logs));
+            markdown.push_str("\n\n");
+
+            let projects = detect_projects(&day_logs);
+            if !projects.is_empty() {
+                markdown.push_str(&format!("Projects: {}\n\n", projects.join(", ")));
+            }
+        }
+
+        let mut day_reports = reports_by_day.remove(&day).unwrap_or_default();
+        day_reports.sort_by_key(|report| report.generated_at);
+        for report in day_reports {
+            markdown.push_str(&format!("### {}\n\n", zone.to_local(report.generated_at).format("%H:%M")));
+            markdown.push_str(&demote_headings(report.summary.trim(), 2));
+            markdown.push_str("\n\n");
+        }
+    }
+
+    markdown.trim_end().to_string()
+}
+
+/// Header line of a summary, e.g. `Summary of 42 events since 2025-03-01 14:05`
+pub fn summary_header(zone: DayZone, log_count: usize, start: DateTime<Utc>) -> String {
+    format!("Summary of {log_count} events since {}", zone.to_local(start).format("%Y-%m-%d %H:%M"))
+}
@@ -0,0 +1,181 @@
+use std::{env, fs, path::Path};
+
+use anyhow::{anyhow, Context, Error};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+
+use crate::{
+    db::{
+        self, crypto,
+        models::{ActivityLog, AiReport},
+        query::{self, LogQuery, Page, ReportQuery, MAX_PAGE_SIZE},
+    },
+    engine::data_dir_in_use,
+    sanitizer,
+    secrets::{self, SecretStore},
+    server::endpoint::{Discovery, DISCOVERY_FILENAME},
+};
+
+/// How a command uses the database
+#[derive(Clone, Copy, PartialEq)]
+pub enum Access {
+    /// Read-only while the app is running
+    Read,
+    /// Writable even while the app is running, which has already migrated the database
+    Write,
+    /// Writable and migrated, for commands holding the data directory's lock themselves
+    Exclusive,
+}
+
+/// Where the CLI reads activity from
+pub enum Source {
+    Database {
+        pool: SqlitePool,
+        /// Only there when the key file exists or the database was opened for writing
+        secret_store: Option<SecretStore>,
+    },
+    /// The local API of the running app or daemon
+    Api {
+        client: reqwest::Client,
+        base_url: String,
+        token: String,
+    },
+}
+
+impl Source {
+    /// Open the database at `db_path`. With `Access::Read` it is opened read-only while the app
+    /// is running, so the CLI never competes with it for writes or migrations, and it is only
+    /// migrated with `init_db` when the app isn't running.
+    pub async fn open_database(db_path: &Path, access: Access) -> Result<Self, Error> {
+        let data_dir = db_path.parent().unwrap_or_else(|| Path::new("."));
+        let app_running = access != Access::Exclusive && data_dir_in_use(data_dir);
+        let read_only = access == Access::Read && app_running;
+
+        let pool = if read_only {
+            db::connect_pool_read_only(db_path)
+                .await
+                .with_context(|| format!("failed to open {} read-only", db_path.display()))?
+        } else {
+            let pool = db::connect_pool(&format!("sqlite://{}", db_path.to_string_lossy())).await?;
+            if !app_running {
+                db::init_db(&pool).await?;
+            }
+            pool
+        };
+
+        // Encrypted fields can only be read with the key file stored next to the database
+        let secret_store = if !read_only || data_dir.join(secrets::KEY_FILENAME).exists() {
+            let passphrase = env::var(secrets::PASSPHRASE_ENV).ok();
+            Some(SecretStore::load_or_create(data_dir, passphrase.as_deref())?)
+        } else {
+            None
+        };
+        if let Some(store) = &secret_store {
+            crypto::install(store.clone(), crypto::load_enabled_flag(&pool).await?);
+        }
+
+        if let Err(err) = sanitizer::rules::reload(&pool).await {
+            eprintln!("⚠️  Failed to load redaction rules: {err}");
+        }
+        if let Err(err) = db::time::reload(&pool).await {
+            eprintln!("⚠️  Failed to load time zone setting: {err}");
+        }
+
+        Ok(Source::Database { pool, secret_store })
+    }
+
+    /// Use the local API of the server running for `data_dir`, with the token stored there
+    pub fn open_api(data_dir: &Path) -> Result<Self, Error> {
+        if !data_dir_in_use(data_dir) {
+            return Err(anyhow!("neither the app nor the daemon is running on {}", data_dir.display()));
+        }
+        let discovery = Discovery::read(data_dir).ok_or_else(|| {
+            anyhow!(
+                "the app is running but its server isn't, {} is missing",
+                data_dir.join(DISCOVERY_FILENAME).display()
+            )
+        })?;
+        let path = data_dir.join(secrets::API_TOKEN_FILENAME);
+        let token = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read the API token from {}", path.display()))?;
+
+        Ok(Source::Api {
+            client: reqwest::Client::new(),
+            base_url: discovery.url,
+            token: token.trim().to_string(),
+        })
+    }
+
+    pub async fn close(self) {
+        if let Source::Database { pool, .. } = self {
+            pool.close().await;
+        }
+    }
+
+    pub async fn logs(&self, query: &LogQuery) -> Result<Page<ActivityLog>, Error> {
+        match self {
+            Source::Database { pool, .. } => query::query_logs(pool, query).await,
+            Source::Api { .. } => self.get("/api/logs", query).await,
+        }
+    }
+
+    pub async fn reports(&self, query: &ReportQuery) -> Result<Page<AiReport>, Error> {
+        match self {
+            Source::Database { pool, .. } => query::query_reports(pool, query).await,
+            Source::Api { .. } => self.get("/api/reports", query).await,
+        }
+    }
+
+    /// Every log matching `query` across all pages, newest first
+    pub async fn all_logs(&self, mut query: LogQuery) -> Result<Vec<ActivityLog>, Error> {
+        query.limit = Some(MAX_PAGE_SIZE);
+        let mut logs = Vec::new();
+        loop {
+            let page = self.logs(&query).await?;
+            logs.extend(page.items);
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return Ok(logs),
+            }
+        }
+    }
+
+    /// Every report matching `query` across all pages, newest first
+    pub async fn all_reports(&self, mut query: ReportQuery) -> Result<Vec<AiReport>, Error> {
+        query.limit = Some(MAX_PAGE_SIZE);
+        let mut reports = Vec::new();
+        loop {
+            let page = self.reports(&query).await?;
+            reports.extend(page.items);
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => return Ok(reports),
+            }
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &impl Serialize) -> Result<T, Error> {
+        let Source::Api { client, base_url, token } = self else {
+            return Err(anyhow!("not connected to the API"));
+        };
+
+        let response = client
+            .get(format!("{base_url}{path}"))
+            .bearer_auth(token)
+            .query(query)
+            .send()
+            .await
+            .with_context(|| format!("failed to reach the local API at {base_url}"))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            // Error bodies are `{"error": "..."}`
+            let body: Value = response.json().await.unwrap_or_default();
+            let message = body.get("error").and_then(Value::as_str).unwrap_or("no details");
+            return Err(anyhow!("local API returned {status}: {message}"));
+        }
+
+        response.json().await.context("failed to parse the local API response")
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Error};
+use serde::Deserialize;
+
+use crate::{
+    engine::{default_data_dir, Engine},
+    sanitizer::expand_home,
+};
+
+/// Overrides the config file location
+pub const CONFIG_ENV: &str = "DEVCHRONICLE_CONFIG";
+
+const CONFIG_FILENAME: &str = "daemon.toml";
+
+const USAGE: &str = "Usage:
+  devchronicle-daemon [--config <path>]
+
+Runs the ingestion server and the summary scheduler without the desktop window.
+The config file defaults to $DEVCHRONICLE_CONFIG or ~/.config/devchronicle/daemon.toml.";
+
+/// Settings of the headless daemon. Everything else is stored in the database, as in the app.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DaemonConfig {
+    /// Where the database and key files live. `~` is expanded. Defaults to the desktop app's
+    /// data directory, so both share one database.
+    data_dir: Option<String>,
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    base.map(|base| base.join("devchronicle").join(CONFIG_FILENAME))
+}
+
+/// Read the config file. A missing file at the default location means defaults,
+/// one that was asked for explicitly must exist.
+fn load_config(path: &Path, explicit: bool) -> Result<DaemonConfig, Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).with_context(|| format!("invalid config file {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && !explicit => Ok(DaemonConfig::default()),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Option<PathBuf>, String> {
+    let mut config = None;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--config" => config = Some(PathBuf::from(rest.next().ok_or("--config needs a path")?)),
+            other => return Err(format!("unexpected argument: {other}")),
+        }
+    }
+    Ok(config)
+}
+
+/// Resolves once SIGTERM or Ctrl+C arrives
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            eprintln!("⚠️  Failed to listen for Ctrl+C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(err) => {
+                eprintln!("⚠️  Failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+    println!("🛑 Shutting down");
+}
+
+async fn run(config_path: Option<PathBuf>) -> Result<(), Error> {
+    let explicit = config_path.is_some() || env::var_os(CONFIG_ENV).is_some();
+    let config_path = config_path
+        .or_else(|| env::var_os(CONFIG_ENV).map(PathBuf::from))
+        .or_else(default_config_path)
+        .ok_or_else(|| anyhow!("could not determine the config directory, pass --config"))?;
+    let config = load_config(&config_path, explicit)?;
+
+    let data_dir = match config.data_dir {
+        Some(dir) => PathBuf::from(expand_home(&dir)),
+        None => default_data_dir().ok_or_else(|| anyhow!("could not determine the data directory, set data_dir"))?,
+    };
+    println!("📂 Using data directory {}", data_dir.display());
+
+    let engine = Engine::open(&data_dir).await?;
+    let listener = engine.bind().await?;
+    engine.run(Some(listener), shutdown_signal()).await
+}
+
+/// Entry point of `devchronicle-daemon`. Returns the process exit code.
+pub fn run_daemon(args: &[String]) -> i32 {
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return 0;
+    }
+
+    let config_path = match parse_args(args) {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    let runtime = match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            eprintln!("❌ Failed to start runtime: {err}");
+            return 1;
+        }
+    };
+
+    match runtime.block_on(run(config_path)) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("❌ {err:#}");
+            1
+        }
+    }
+}
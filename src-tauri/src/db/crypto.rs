@@ -0,0 +1,511 @@
+use std::path::Path;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Context, Error};
+use serde::Serialize;
+use sqlx::{SqliteConnection, SqlitePool};
+use tokio::sync::RwLockReadGuard;
+
+use crate::secrets::{SecretStore, KEY_FILENAME, PENDING_KEY_FILENAME};
+
+/// Field-level encryption of the sensitive columns listed in [`ENCRYPTED_COLUMNS`]. Values are
+/// sealed on write while encryption is enabled and opened on read whenever they carry the
+/// encryption prefix, so mixed tables keep working.
+struct FieldCipher {
+    store: SecretStore,
+    enabled: bool,
+}
+
+static FIELD_CIPHER: RwLock<Option<FieldCipher>> = RwLock::new(None);
+
+/// Held shared by every write that seals values, and exclusively by [`enable`], [`disable`] and
+/// [`rekey`], so no row is sealed with a key or setting that is being replaced
+static WRITE_GATE: tokio::sync::RwLock<()> = tokio::sync::RwLock::const_new(());
+
+/// Columns whose values are sealed, by table
+pub const ENCRYPTED_COLUMNS: &[(&str, &str)] = &[
+    ("activity_logs", "payload"),
+    ("activity_logs", "command"),
+    ("activity_logs", "cwd"),
+    ("activity_logs", "url"),
+    ("activity_logs", "title"),
+    ("activity_logs", "domain"),
+    ("activity_logs", "file_path"),
+    ("activity_logs", "project"),
+    ("ai_reports", "summary"),
+    ("ai_requests", "prompt"),
+];
+
+/// Put in front of plain-text values that start like an encrypted or escaped value, so a stored
+/// value's prefix always tells how to read it. Only written while encryption is off.
+const ESCAPED_PREFIX: &str = "raw:v1:";
+
+const ENCRYPTED_PATTERN: &str = "enc:v1:%";
+
+const REENCRYPT_BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Default, Serialize)]
+pub struct EncryptionStatus {
+    pub enabled: bool,
+    /// Logs whose sensitive columns are all encrypted
+    pub encrypted_logs: i64,
+    /// Logs with at least one sensitive column in plain text
+    pub plaintext_logs: i64,
+    pub encrypted_reports: i64,
+    pub plaintext_reports: i64,
+    /// Every encrypted column, NULLs left out
+    pub columns: Vec<ColumnStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ColumnStatus {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub encrypted: i64,
+    pub plaintext: i64,
+}
+
+/// Install the key used for field encryption. Called once at startup after the key file is unlocked.
+pub fn install(store: SecretStore, enabled: bool) {
+    *FIELD_CIPHER.write().unwrap_or_else(|poisoned| poisoned.into_inner()) =
+        Some(FieldCipher { store, enabled });
+}
+
+fn set_enabled(enabled: bool) {
+    if let Some(cipher) = FIELD_CIPHER
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_mut()
+    {
+        cipher.enabled = enabled;
+    }
+}
+
+fn installed_store() -> Option<SecretStore> {
+    FIELD_CIPHER
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .map(|cipher| cipher.store.clone())
+}
+
+pub fn is_enabled() -> bool {
+    FIELD_CIPHER
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .is_some_and(|cipher| cipher.enabled)
+}
+
+/// Hold while sealing values and writing them, until the write is committed
+pub async fn write_access() -> RwLockReadGuard<'static, ()> {
+    WRITE_GATE.read().await
+}
+
+fn escape(value: &str) -> String {
+    if SecretStore::is_encrypted(value) || value.starts_with(ESCAPED_PREFIX) {
+        format!("{ESCAPED_PREFIX}{value}")
+    } else {
+        value.to_string()
+    }
+}
+
+/// Prepare `value` for storage: encrypted if encryption is enabled, otherwise plain text,
+/// escaped if it would read as encrypted
+pub fn seal(value: &str) -> Result<String, Error> {
+    match FIELD_CIPHER.read().unwrap_or_else(|poisoned| poisoned.into_inner()).as_ref() {
+        Some(cipher) if cipher.enabled => cipher.store.encrypt(value),
+        _ => Ok(escape(value)),
+    }
+}
+
+pub fn seal_opt(value: Option<&str>) -> Result<Option<String>, Error> {
+    value.map(seal).transpose()
+}
+
+/// Read a stored value written by [`seal`]
+pub fn open(stored: &str) -> Result<String, Error> {
+    if let Some(escaped) = stored.strip_prefix(ESCAPED_PREFIX) {
+        return Ok(escaped.to_string());
+    }
+    if !SecretStore::is_encrypted(stored) {
+        return Ok(stored.to_string());
+    }
+
+    match FIELD_CIPHER.read().unwrap_or_else(|poisoned| poisoned.into_inner()).as_ref() {
+        Some(cipher) => cipher.store.decrypt(stored),
+        None => Err(anyhow!("encrypted data found but no key is unlocked")),
+    }
+}
+
+pub fn open_opt(stored: Option<String>) -> Result<Option<String>, Error> {
+    stored.as_deref().map(open).transpose()
+}
+
+pub async fn load_enabled_flag(pool: &SqlitePool) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT enabled FROM encryption_settings WHERE id = 1")
+        .fetch_one(pool)
+        .await
+}
+
+async fn store_enabled_flag(connection: &mut SqliteConnection, enabled: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE encryption_settings SET enabled = ?1 WHERE id = 1")
+        .bind(enabled)
+        .execute(connection)
+        .await?;
+    Ok(())
+}
+
+type Transform<'a> = &'a dyn Fn(&str) -> Result<String, Error>;
+
+/// Rewrite every encrypted column through `transform`, leaving NULLs alone
+async fn rewrite_fields(connection: &mut SqliteConnection, transform: Transform<'_>) -> Result<(), Error> {
+    for (table, column) in ENCRYPTED_COLUMNS {
+        let select = format!(
+            "SELECT id, {column} FROM {table} WHERE id > ?1 AND {column} IS NOT NULL ORDER BY id ASC LIMIT ?2"
+        );
+        let update = format!("UPDATE {table} SET {column} = ?1 WHERE id = ?2");
+
+        let mut last_id = 0;
+        loop {
+            let rows: Vec<(i64, String)> = sqlx::query_as(&select)
+                .bind(last_id)
+                .bind(REENCRYPT_BATCH_SIZE)
+                .fetch_all(&mut *connection)
+                .await?;
+
+            let Some(last) = rows.last() else {
+                break;
+            };
+            last_id = last.0;
+
+            for (id, value) in rows {
+                sqlx::query(&update)
+                    .bind(transform(&value)?)
+                    .bind(id)
+                    .execute(&mut *connection)
+                    .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Rebuild the database file so no old plain-text pages survive in free space or the WAL
+async fn compact(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(pool).await?;
+    sqlx::query("VACUUM").execute(pool).await?;
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(pool).await?;
+    Ok(())
+}
+
+/// Turn encryption on and encrypt everything that is still stored in plain text.
+/// Writes wait until all rows are rewritten, which happens in a single transaction.
+pub async fn enable(pool: &SqlitePool) -> Result<(), Error> {
+    let _writes = WRITE_GATE.write().await;
+    let store = installed_store().ok_or_else(|| anyhow!("no encryption key is unlocked"))?;
+
+    let encrypt = |stored: &str| -> Result<String, Error> {
+        if SecretStore::is_encrypted(stored) {
+            Ok(stored.to_string())
+        } else {
+            store.encrypt(&open(stored)?)
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+    rewrite_fields(&mut tx, &encrypt).await?;
+    store_enabled_flag(&mut tx, true).await?;
+    tx.commit().await?;
+    set_enabled(true);
+
+    compact(pool).await?;
+    Ok(())
+}
+
+/// Encrypt values still in plain text while encryption is on, e.g. in columns that became encrypted
+/// after it was enabled. Compacts the database when anything was sealed. Returns how many values were.
+pub async fn seal_leftovers(pool: &SqlitePool) -> Result<u64, Error> {
+    let _writes = WRITE_GATE.write().await;
+    let Some(store) = installed_store().filter(|_| is_enabled()) else {
+        return Ok(0);
+    };
+
+    let mut sealed = 0;
+    let mut tx = pool.begin().await?;
+    for (table, column) in ENCRYPTED_COLUMNS {
+        let select = format!(
+            "SELECT id, {column} FROM {table} WHERE id > ?1 AND {column} NOT LIKE ?3 ORDER BY id ASC LIMIT ?2"
+        );
+        let update = format!("UPDATE {table} SET {column} = ?1 WHERE id = ?2");
+
+        let mut last_id = 0;
+        loop {
+            let rows: Vec<(i64, String)> = sqlx::query_as(&select)
+                .bind(last_id)
+                .bind(REENCRYPT_BATCH_SIZE)
+                .bind(ENCRYPTED_PATTERN)
+                .fetch_all(&mut *tx)
+                .await?;
+            let Some(last) = rows.last() else {
+                break;
+            };
+            last_id = last.0;
+
+            for (id, value) in rows {
+                sqlx::query(&update)
+                    .bind(store.encrypt(&open(&value)?)?)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+                sealed += 1;
+            }
+        }
+    }
+    tx.commit().await?;
+
+    if sealed > 0 {
+        compact(pool).await?;
+    }
+    Ok(sealed)
+}
+
+/// Turn encryption off and decrypt all stored data
+pub async fn disable(pool: &SqlitePool) -> Result<(), Error> {
+    let _writes = WRITE_GATE.write().await;
+
+    let decrypt = |stored: &str| -> Result<String, Error> { Ok(escape(&open(stored)?)) };
+
+    let mut tx = pool.begin().await?;
+    rewrite_fields(&mut tx, &decrypt).await?;
+    store_enabled_flag(&mut tx, false).await?;
+    tx.commit().await?;
+    set_enabled(false);
+
+    compact(pool).await?;
+    Ok(())
+}
+
+/// Generate a new key and re-encrypt every encrypted value with it, including the stored API key.
+///
+/// Writes wait until the new key is in use. The data is re-encrypted in a single transaction and the
+/// new key is written next to the current one first, only replacing it once that transaction is
+/// committed, so an interruption never leaves data that no key file can open.
+pub async fn rekey(pool: &SqlitePool, store: &SecretStore, data_dir: &Path, passphrase: Option<&str>) -> Result<(), Error> {
+    let _writes = WRITE_GATE.write().await;
+
+    let pending_path = data_dir.join(PENDING_KEY_FILENAME);
+    let new_store = SecretStore::create_at(&pending_path, passphrase)?;
+
+    let reencrypt = |stored: &str| -> Result<String, Error> {
+        if SecretStore::is_encrypted(stored) {
+            new_store.encrypt(&store.decrypt(stored)?)
+        } else {
+            Ok(stored.to_string())
+        }
+    };
+
+    let mut tx = pool.begin().await?;
+    rewrite_fields(&mut tx, &reencrypt).await?;
+
+    let api_key: Option<String> = sqlx::query_scalar("SELECT api_key FROM ai_settings WHERE id = 1")
+        .fetch_optional(&mut *tx)
+        .await?
+        .flatten();
+    if let Some(api_key) = api_key {
+        sqlx::query("UPDATE ai_settings SET api_key = ?1 WHERE id = 1")
+            .bind(reencrypt(&api_key)?)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    std::fs::rename(&pending_path, data_dir.join(KEY_FILENAME))
+        .with_context(|| format!("failed to replace {}", KEY_FILENAME))?;
+
+    // Every clone of the store, including the one held by the AI client, picks up the new key
+    store.replace_key(&new_store);
+
+    compact(pool).await?;
+    Ok(())
+}
+
+pub async fn status(pool: &SqlitePool) -> Result<EncryptionStatus, Error> {
+    let mut columns = Vec::new();
+    for (table, column) in ENCRYPTED_COLUMNS {
+        let (encrypted, plaintext): (i64, i64) = sqlx::query_as(&format!(
+            "SELECT COALESCE(SUM({column} LIKE ?1), 0), COALESCE(SUM({column} NOT LIKE ?1), 0) FROM {table}"
+        ))
+        .bind(ENCRYPTED_PATTERN)
+        .fetch_one(pool)
+        .await?;
+        columns.push(ColumnStatus { table, column, encrypted, plaintext });
+    }
+
+    // A log counts as encrypted once none of its sensitive columns is left in plain text
+    let log_checks: Vec<String> = ENCRYPTED_COLUMNS
+        .iter()
+        .filter(|(table, _)| *table == "activity_logs")
+        .map(|(_, column)| format!("({column} IS NULL OR {column} LIKE ?1)"))
+        .collect();
+    let (encrypted_logs, total_logs): (i64, i64) = sqlx::query_as(&format!(
+        "SELECT COALESCE(SUM({}), 0), COUNT(*) FROM activity_logs",
+        log_checks.join(" AND ")
+    ))
+    .bind(ENCRYPTED_PATTERN)
+    .fetch_one(pool)
+    .await?;
+
+    let (encrypted_reports, plaintext_reports) = columns
+        .iter()
+        .find(|status| status.table == "ai_reports")
+        .map(|status| (status.encrypted, status.plaintext))
+        .unwrap_or_default();
+
+    Ok(EncryptionStatus {
+        enabled: is_enabled(),
+        encrypted_logs,
+        plaintext_logs: total_logs - encrypted_logs,
+        encrypted_reports,
+        plaintext_reports,
+        columns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        db::{
+            self,
+            query::{self, LogQuery},
+        },
+        server::handlers,
+    };
+
+    /// Every value stored by the test contains this word
+    const MARKER: &[u8] = b"marmalade";
+
+    fn assert_no_plaintext(dir: &Path) {
+        for name in ["test.db", "test.db-wal"] {
+            let Ok(bytes) = std::fs::read(dir.join(name)) else {
+                continue;
+            };
+            let bytes = bytes.to_ascii_lowercase();
+            assert!(
+                !bytes.windows(MARKER.len()).any(|window| window == MARKER),
+                "plain text left in {name}"
+            );
+        }
+    }
+
+    async fn store(pool: &SqlitePool, source: &str, payload: serde_json::Value) {
+        handlers::store_event(pool, source, payload, Utc::now(), None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn encrypts_every_sensitive_column_and_survives_rekey() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        let key_store = SecretStore::load_or_create(dir.path(), None).unwrap();
+        install(key_store.clone(), false);
+
+        store(&pool, "terminal", json!({ "command": "echo marmalade", "cwd": "/work/marmalade", "exit_code": 0 })).await;
+        store(&pool, "terminal", json!({ "command": "enc:v1:not-a-ciphertext", "cwd": "/work" })).await;
+        store(&pool, "browser", json!({ "url": "https://marmalade.example/docs", "title": "Marmalade docs" })).await;
+        store(&pool, "vscode", json!({ "event": "file_open", "file_path": "/work/marmalade.rs" })).await;
+        sqlx::query("INSERT INTO ai_reports (summary, generated_at) VALUES (?1, 0)")
+            .bind(seal("Made marmalade").unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO ai_requests (created_at, model, provider_host, prompt, prompt_sha256, latency_ms, status) \
+             VALUES (0, 'model', 'localhost', ?1, '', 0, 'ok')",
+        )
+        .bind(seal("Summarize marmalade").unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        enable(&pool).await.unwrap();
+        assert_no_plaintext(dir.path());
+
+        let encrypted = status(&pool).await.unwrap();
+        assert_eq!((encrypted.encrypted_logs, encrypted.plaintext_logs), (4, 0));
+        assert_eq!((encrypted.encrypted_reports, encrypted.plaintext_reports), (1, 0));
+        assert!(encrypted.columns.iter().all(|column| column.plaintext == 0));
+
+        rekey(&pool, &key_store, dir.path(), None).await.unwrap();
+        assert_no_plaintext(dir.path());
+
+        // Domains are matched after decrypting
+        let query = LogQuery { domain: Some("example".to_string()), ..Default::default() };
+        let page = query::query_logs(&pool, &query).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].title.as_deref(), Some("Marmalade docs"));
+
+        // So are projects, which name the working directory here
+        let query = LogQuery { project: Some("marmalade".to_string()), ..Default::default() };
+        let page = query::query_logs(&pool, &query).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].command.as_deref(), Some("echo marmalade"));
+        let stats = query::activity_stats(&pool, &Default::default()).await.unwrap();
+        assert!(stats.projects.iter().any(|project| project.name == "marmalade" && project.count == 1));
+
+        disable(&pool).await.unwrap();
+        let commands: Vec<String> = query::query_logs(&pool, &LogQuery::default())
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .filter_map(|log| log.command)
+            .collect();
+        assert!(commands.contains(&"enc:v1:not-a-ciphertext".to_string()));
+        assert!(status(&pool).await.unwrap().columns.iter().all(|column| column.encrypted == 0));
+    }
+
+    #[test]
+    fn values_that_look_encrypted_round_trip_in_plain_text() {
+        for value in ["enc:v1:abc", "raw:v1:abc", "plain"] {
+            assert_eq!(open(&escape(value)).unwrap(), value);
+        }
+        assert_eq!(escape("plain"), "plain");
+    }
+
+    #[tokio::test]
+    async fn seals_values_left_in_plain_text_while_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        install(SecretStore::load_or_create(dir.path(), None).unwrap(), false);
+        enable(&pool).await.unwrap();
+
+        // Stored by a version that kept project names readable
+        store(&pool, "terminal", json!({ "command": "ls", "cwd": "/work/site" })).await;
+        sqlx::query("INSERT INTO activity_logs (source, payload, timestamp, project) VALUES ('terminal', ?1, 0, 'marmalade')")
+            .bind(seal("{}").unwrap())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(seal_leftovers(&pool).await.unwrap(), 1);
+        assert_no_plaintext(dir.path());
+        assert_eq!(seal_leftovers(&pool).await.unwrap(), 0);
+
+        let projects: Vec<String> = query::query_logs(&pool, &LogQuery::default())
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .filter_map(|log| log.project)
+            .collect();
+        assert_eq!(projects, ["site", "marmalade"]);
+
+        // Other tests expect the cipher off
+        disable(&pool).await.unwrap();
+    }
+}
@@ -1,9 +1,23 @@
-use sqlx::SqlitePool;
-use crate::db::models::{AiSettings, AiSettingsRow};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    SqlitePool,
+};
+use crate::db::models::{
+    AiSettings, AiSettingsRow, JournalSettings, RedactionRule, RedactionSettings, RetentionSettings,
+    ServerSettings,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
 use std::fmt;
 use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
 
+pub mod crypto;
 pub mod models;
+pub mod quarantine;
+pub mod query;
+pub mod time;
 
 const SCHEMA: &str = include_str!("schema.sql");
 
@@ -18,6 +32,46 @@ impl fmt::Display for SimpleError {
 
 impl Error for SimpleError {}
 
+pub async fn connect_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
+    let connect_options = SqliteConnectOptions::from_str(database_url)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal)
+        .busy_timeout(Duration::from_secs(5));
+
+    SqlitePoolOptions::new()
+        .max_connections(5)
+        .connect_with(connect_options)
+        .await
+}
+
+/// Open an existing database without writing to it, e.g. while the app has it open.
+/// Nothing is migrated, so it must have been opened by the app before.
+pub async fn connect_pool_read_only(path: &std::path::Path) -> Result<SqlitePool, sqlx::Error> {
+    let connect_options = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .busy_timeout(Duration::from_secs(5));
+
+    SqlitePoolOptions::new()
+        .max_connections(2)
+        .connect_with(connect_options)
+        .await
+}
+
+/// A migrated database in `dir` for tests. Tests share the global field cipher, so those
+/// using a database run one at a time while they hold the returned guard.
+#[cfg(test)]
+pub async fn test_pool(dir: &std::path::Path) -> (tokio::sync::MutexGuard<'static, ()>, SqlitePool) {
+    static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    let guard = LOCK.lock().await;
+    let pool = connect_pool(&format!("sqlite://{}", dir.join("test.db").display()))
+        .await
+        .unwrap();
+    init_db(&pool).await.unwrap();
+    (guard, pool)
+}
+
 pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
     // Split schema into individual statements and execute them separately
     for statement in SCHEMA.split(';') {
@@ -29,12 +83,93 @@ pub async fn init_db(pool: &SqlitePool) -> Result<(), sqlx::Error> {
         }
     }
 
+    // Columns added after the first release, which CREATE TABLE IF NOT EXISTS won't add to existing databases
+    add_column_if_missing(pool, "activity_logs", "redactions", "INTEGER NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "activity_logs", "project", "TEXT").await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_project ON activity_logs(project, timestamp)")
+        .execute(pool)
+        .await?;
+    add_column_if_missing(pool, "activity_logs", "event_id", "TEXT").await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_event_id ON activity_logs(event_id)")
+        .execute(pool)
+        .await?;
+    if add_column_if_missing(pool, "activity_logs", "payload_stripped", "BOOLEAN NOT NULL DEFAULT 0").await? {
+        // Retention used to replace stripped payloads with an empty object
+        sqlx::query("UPDATE activity_logs SET payload_stripped = 1 WHERE is_processed = 1 AND payload = '{}'")
+            .execute(pool)
+            .await?;
+    }
+    add_column_if_missing(pool, "journal_exports", "fingerprint", "TEXT").await?;
+    add_column_if_missing(pool, "redaction_settings", "pseudonymize_hostnames", "BOOLEAN NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "ai_settings", "local_only", "BOOLEAN NOT NULL DEFAULT 0").await?;
+    add_column_if_missing(pool, "ai_settings", "local_only_changed_at", "INTEGER").await?;
+    add_column_if_missing(pool, "ai_settings", "audit_store_prompts", "BOOLEAN NOT NULL DEFAULT 0").await?;
+
+    // Timestamps used to be RFC 3339 text, which sorts wrongly once offsets differ
+    let mut converted = 0;
+    for (table, column) in [
+        ("activity_logs", "timestamp"),
+        ("ai_reports", "generated_at"),
+        ("ai_reports", "time_range_start"),
+        ("ai_reports", "time_range_end"),
+        ("ai_requests", "created_at"),
+        ("git_repositories", "added_at"),
+        ("git_repositories", "last_scanned_at"),
+        ("journal_exports", "exported_at"),
+        ("retention_settings", "last_vacuum_at"),
+        ("ai_settings", "local_only_changed_at"),
+        ("privacy_settings", "paused_until"),
+        ("quarantined_rows", "detected_at"),
+    ] {
+        converted += time::migrate_to_millis(pool, table, column).await?;
+    }
+    if converted > 0 {
+        println!("🕒 Converted {converted} timestamps to UTC epoch milliseconds");
+    }
+
     Ok(())
 }
 
+/// Stable identity of an event across machines: same source, time and payload means same event.
+/// Stored in `activity_logs.event_id` when the event is inserted, so it survives payload stripping.
+pub fn event_id(source: &str, timestamp: DateTime<Utc>, payload: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(source.as_bytes());
+    hasher.update([0]);
+    hasher.update(timestamp.to_rfc3339_opts(SecondsFormat::Millis, true).as_bytes());
+    hasher.update([0]);
+    hasher.update(payload.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Returns whether the column was added
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool, sqlx::Error> {
+    let exists: bool = sqlx::query_scalar(&format!(
+        "SELECT EXISTS (SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1)"
+    ))
+    .bind(column)
+    .fetch_one(pool)
+    .await?;
+
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(!exists)
+}
+
 pub async fn get_ai_settings(pool: &SqlitePool) -> Result<AiSettings, sqlx::Error> {
     let row = sqlx::query_as::<_, AiSettingsRow>(
-        "SELECT provider_url, api_key, model_name, temperature, batch_size, summary_frequency_min FROM ai_settings WHERE id = 1"
+        "SELECT provider_url, api_key, model_name, temperature, batch_size, summary_frequency_min, local_only, local_only_changed_at,
+                audit_store_prompts
+         FROM ai_settings WHERE id = 1"
     )
     .fetch_one(pool)
     .await?;
@@ -47,19 +182,16 @@ pub async fn get_ai_settings(pool: &SqlitePool) -> Result<AiSettings, sqlx::Erro
 pub async fn upsert_ai_settings(
     pool: &SqlitePool,
     provider_url: &str,
-    api_key: Option<&str>,
     model_name: &str,
 ) -> Result<(), sqlx::Error> {
     sqlx::query(
-        "INSERT INTO ai_settings (id, provider_url, api_key, model_name) 
-         VALUES (1, ?1, ?2, ?3) 
+        "INSERT INTO ai_settings (id, provider_url, model_name) 
+         VALUES (1, ?1, ?2) 
          ON CONFLICT(id) DO UPDATE SET 
             provider_url = excluded.provider_url, 
-            api_key = excluded.api_key,
             model_name = excluded.model_name" 
     )
     .bind(provider_url)
-    .bind(api_key)
     .bind(model_name)  
     .execute(pool)
     .await?;
@@ -67,3 +199,210 @@ pub async fn upsert_ai_settings(
     Ok(())
 }
 
+/// Switch local-only mode, recording when it last changed
+pub async fn set_ai_local_only(pool: &SqlitePool, local_only: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE ai_settings SET local_only = ?1, local_only_changed_at = ?2 WHERE id = 1 AND local_only != ?1")
+        .bind(local_only)
+        .bind(chrono::Utc::now().timestamp_millis())
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Choose whether ai_requests keeps full prompts or only their hashes
+pub async fn set_audit_store_prompts(pool: &SqlitePool, store_prompts: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE ai_settings SET audit_store_prompts = ?1 WHERE id = 1")
+        .bind(store_prompts)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Store an already encrypted API key, or clear it with `None`
+pub async fn set_encrypted_api_key(
+    pool: &SqlitePool,
+    encrypted_api_key: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE ai_settings SET api_key = ?1 WHERE id = 1")
+        .bind(encrypted_api_key)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_journal_settings(pool: &SqlitePool) -> Result<JournalSettings, sqlx::Error> {
+    sqlx::query_as::<_, JournalSettings>(
+        "SELECT vault_dir, auto_export FROM journal_settings WHERE id = 1"
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn upsert_journal_settings(
+    pool: &SqlitePool,
+    vault_dir: Option<&str>,
+    auto_export: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO journal_settings (id, vault_dir, auto_export)
+         VALUES (1, ?1, ?2)
+         ON CONFLICT(id) DO UPDATE SET
+            vault_dir = excluded.vault_dir,
+            auto_export = excluded.auto_export"
+    )
+    .bind(vault_dir)
+    .bind(auto_export)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_retention_settings(pool: &SqlitePool) -> Result<RetentionSettings, sqlx::Error> {
+    sqlx::query_as::<_, RetentionSettings>(
+        "SELECT raw_log_days, report_days, strip_payload_after_summary, vacuum_interval_hours, last_vacuum_at
+         FROM retention_settings WHERE id = 1"
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn upsert_retention_settings(
+    pool: &SqlitePool,
+    settings: &RetentionSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO retention_settings (id, raw_log_days, report_days, strip_payload_after_summary, vacuum_interval_hours)
+         VALUES (1, ?1, ?2, ?3, ?4)
+         ON CONFLICT(id) DO UPDATE SET
+            raw_log_days = excluded.raw_log_days,
+            report_days = excluded.report_days,
+            strip_payload_after_summary = excluded.strip_payload_after_summary,
+            vacuum_interval_hours = excluded.vacuum_interval_hours"
+    )
+    .bind(settings.raw_log_days)
+    .bind(settings.report_days)
+    .bind(settings.strip_payload_after_summary)
+    .bind(settings.vacuum_interval_hours)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_server_settings(pool: &SqlitePool) -> Result<ServerSettings, sqlx::Error> {
+    sqlx::query_as::<_, ServerSettings>("SELECT host, port, fallback_ports FROM server_settings WHERE id = 1")
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn upsert_server_settings(pool: &SqlitePool, settings: &ServerSettings) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO server_settings (id, host, port, fallback_ports)
+         VALUES (1, ?1, ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET
+            host = excluded.host,
+            port = excluded.port,
+            fallback_ports = excluded.fallback_ports"
+    )
+    .bind(&settings.host)
+    .bind(settings.port)
+    .bind(settings.fallback_ports)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_redaction_settings(pool: &SqlitePool) -> Result<RedactionSettings, sqlx::Error> {
+    sqlx::query_as::<_, RedactionSettings>(
+        "SELECT enabled, redact_emails, redact_ip_addresses, pseudonymize_hostnames FROM redaction_settings WHERE id = 1"
+    )
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn upsert_redaction_settings(
+    pool: &SqlitePool,
+    settings: &RedactionSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO redaction_settings (id, enabled, redact_emails, redact_ip_addresses, pseudonymize_hostnames)
+         VALUES (1, ?1, ?2, ?3, ?4)
+         ON CONFLICT(id) DO UPDATE SET
+            enabled = excluded.enabled,
+            redact_emails = excluded.redact_emails,
+            redact_ip_addresses = excluded.redact_ip_addresses,
+            pseudonymize_hostnames = excluded.pseudonymize_hostnames"
+    )
+    .bind(settings.enabled)
+    .bind(settings.redact_emails)
+    .bind(settings.redact_ip_addresses)
+    .bind(settings.pseudonymize_hostnames)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_redaction_rules(pool: &SqlitePool) -> Result<Vec<RedactionRule>, sqlx::Error> {
+    sqlx::query_as::<_, RedactionRule>(
+        "SELECT id, name, pattern, replacement, kind, source, field, enabled FROM redaction_rules ORDER BY id ASC"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Insert `rule` when its id is 0, otherwise update the existing rule. Returns the rule's id.
+pub async fn save_redaction_rule(pool: &SqlitePool, rule: &RedactionRule) -> Result<i64, sqlx::Error> {
+    if rule.id == 0 {
+        let result = sqlx::query(
+            "INSERT INTO redaction_rules (name, pattern, replacement, kind, source, field, enabled)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+        )
+        .bind(&rule.name)
+        .bind(&rule.pattern)
+        .bind(&rule.replacement)
+        .bind(&rule.kind)
+        .bind(&rule.source)
+        .bind(&rule.field)
+        .bind(rule.enabled)
+        .execute(pool)
+        .await?;
+
+        return Ok(result.last_insert_rowid());
+    }
+
+    let result = sqlx::query(
+        "UPDATE redaction_rules SET name = ?1, pattern = ?2, replacement = ?3, kind = ?4, source = ?5, field = ?6, enabled = ?7
+         WHERE id = ?8"
+    )
+    .bind(&rule.name)
+    .bind(&rule.pattern)
+    .bind(&rule.replacement)
+    .bind(&rule.kind)
+    .bind(&rule.source)
+    .bind(&rule.field)
+    .bind(rule.enabled)
+    .bind(rule.id)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound);
+    }
+
+    Ok(rule.id)
+}
+
+pub async fn delete_redaction_rule(pool: &SqlitePool, id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM redaction_rules WHERE id = ?1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
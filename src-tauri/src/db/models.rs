@@ -3,8 +3,12 @@ use std::convert::TryFrom;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use super::{crypto, time::StoredTimestamp};
+use crate::sanitizer::RedactionOptions;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct ActivityLog {
     pub id: i64,
     pub source: String,
@@ -17,6 +21,8 @@ pub struct ActivityLog {
     pub domain: Option<String>,
     pub title: Option<String>,
     pub file_path: Option<String>,
+    /// See `export::event_project`
+    pub project: Option<String>,
 }
 
 #[derive(sqlx::FromRow)]
@@ -24,7 +30,7 @@ pub struct ActivityLogRow {
     pub id: i64,
     pub source: String,
     pub payload: String,
-    pub timestamp: String,
+    pub timestamp: StoredTimestamp,
     pub log_type: Option<String>,
     pub session_id: Option<String>,
     pub command: Option<String>,
@@ -32,6 +38,7 @@ pub struct ActivityLogRow {
     pub domain: Option<String>,
     pub title: Option<String>,
     pub file_path: Option<String>,
+    pub project: Option<String>,
 }
 
 #[derive(Debug)]
@@ -41,29 +48,184 @@ impl TryFrom<ActivityLogRow> for ActivityLog {
     type Error = ActivityLogConversionError;
 
     fn try_from(row: ActivityLogRow) -> Result<Self, Self::Error> {
-        let payload: Value = serde_json::from_str(&row.payload)
-            .map_err(|err| ActivityLogConversionError(err.to_string()))?;
+        let decrypt_error = |err: anyhow::Error| ActivityLogConversionError(err.to_string());
+
+        let payload_text = crypto::open(&row.payload).map_err(decrypt_error)?;
+        let payload: Value = serde_json::from_str(&payload_text)
+            .map_err(|err| ActivityLogConversionError(format!("invalid payload JSON: {err}")))?;
 
-        let parsed_timestamp = DateTime::parse_from_rfc3339(&row.timestamp)
-            .map_err(|err| ActivityLogConversionError(err.to_string()))?;
+        let timestamp = row.timestamp.to_utc().map_err(ActivityLogConversionError)?;
 
         Ok(ActivityLog {
             id: row.id,
             source: row.source,
             payload,
-            timestamp: parsed_timestamp.with_timezone(&Utc),
+            timestamp,
             log_type: row.log_type,
             session_id: row.session_id,
-            command: row.command,
-            url: row.url,
-            domain: row.domain,
-            title: row.title,
-            file_path: row.file_path,
+            command: crypto::open_opt(row.command).map_err(decrypt_error)?,
+            url: crypto::open_opt(row.url).map_err(decrypt_error)?,
+            domain: crypto::open_opt(row.domain).map_err(decrypt_error)?,
+            title: crypto::open_opt(row.title).map_err(decrypt_error)?,
+            file_path: crypto::open_opt(row.file_path).map_err(decrypt_error)?,
+            // Empty for logs that carry no path to attribute them to a project
+            project: crypto::open_opt(row.project)
+                .map_err(decrypt_error)?
+                .filter(|project| !project.is_empty()),
         })
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// What happened in the editor, as sent in the `event` field of vscode payloads.
+/// Stored rows keep the raw payload; `log_type` holds [`EditorEvent::log_type`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EditorEvent {
+    FileOpen {
+        #[serde(alias = "file")]
+        file_path: String,
+        #[serde(default)]
+        language: Option<String>,
+    },
+    FileSave {
+        #[serde(alias = "file")]
+        file_path: String,
+        #[serde(default)]
+        language: Option<String>,
+        #[serde(default)]
+        time_spent_sec: Option<u64>,
+    },
+    /// The file became the active editor
+    FileFocus {
+        #[serde(alias = "file")]
+        file_path: String,
+        #[serde(default)]
+        language: Option<String>,
+    },
+    /// Events from clients that didn't say what happened to the file
+    FileEdit {
+        #[serde(alias = "file")]
+        file_path: String,
+        #[serde(default)]
+        language: Option<String>,
+    },
+    DebugStart {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        debug_type: Option<String>,
+    },
+    DebugStop {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        duration_sec: Option<u64>,
+    },
+    TestRun {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        passed: Option<u32>,
+        #[serde(default)]
+        failed: Option<u32>,
+        #[serde(default)]
+        skipped: Option<u32>,
+        #[serde(default)]
+        exit_code: Option<i32>,
+        #[serde(default)]
+        duration_sec: Option<u64>,
+    },
+    TaskRun {
+        name: String,
+        #[serde(default)]
+        exit_code: Option<i32>,
+        #[serde(default)]
+        duration_sec: Option<u64>,
+    },
+    /// Commit, checkout, push, … triggered from the editor's source control view
+    GitOperation {
+        operation: String,
+        #[serde(default)]
+        branch: Option<String>,
+    },
+}
+
+impl EditorEvent {
+    /// Event names accepted in the `event` field
+    pub const NAMES: &'static [&'static str] = &[
+        "file_open",
+        "file_save",
+        "file_focus",
+        "file_edit",
+        "debug_start",
+        "debug_stop",
+        "test_run",
+        "task_run",
+        "git_operation",
+    ];
+
+    /// Parse a vscode payload. Payloads without an `event` field are treated as `file_edit`.
+    pub fn from_payload(payload: &Value) -> Result<Self, serde_json::Error> {
+        match payload {
+            Value::Object(object) if !object.contains_key("event") => {
+                let mut object = object.clone();
+                object.insert("event".to_string(), Value::from("file_edit"));
+                serde_json::from_value(Value::Object(object))
+            }
+            _ => serde_json::from_value(payload.clone()),
+        }
+    }
+
+    pub fn log_type(&self) -> &'static str {
+        match self {
+            EditorEvent::FileOpen { .. } => "file_open",
+            EditorEvent::FileSave { .. } => "file_save",
+            EditorEvent::FileFocus { .. } => "file_focus",
+            EditorEvent::FileEdit { .. } => "file_edit",
+            EditorEvent::DebugStart { .. } => "debug_start",
+            EditorEvent::DebugStop { .. } => "debug_stop",
+            EditorEvent::TestRun { .. } => "test_run",
+            EditorEvent::TaskRun { .. } => "task_run",
+            EditorEvent::GitOperation { .. } => "git_operation",
+        }
+    }
+
+    pub fn file_path(&self) -> Option<&str> {
+        match self {
+            EditorEvent::FileOpen { file_path, .. }
+            | EditorEvent::FileSave { file_path, .. }
+            | EditorEvent::FileFocus { file_path, .. }
+            | EditorEvent::FileEdit { file_path, .. } => Some(file_path),
+            _ => None,
+        }
+    }
+
+    /// Short label stored in the `title` column: the language for file events, otherwise the session, test or task name
+    pub fn title(&self) -> Option<&str> {
+        match self {
+            EditorEvent::FileOpen { language, .. }
+            | EditorEvent::FileSave { language, .. }
+            | EditorEvent::FileFocus { language, .. }
+            | EditorEvent::FileEdit { language, .. } => language.as_deref(),
+            EditorEvent::DebugStart { name, .. }
+            | EditorEvent::DebugStop { name, .. }
+            | EditorEvent::TestRun { name, .. } => name.as_deref(),
+            EditorEvent::TaskRun { name, .. } => Some(name),
+            EditorEvent::GitOperation { operation, .. } => Some(operation),
+        }
+    }
+}
+
+impl ActivityLog {
+    /// The editor event behind a vscode log, if its payload can be read as one
+    pub fn editor_event(&self) -> Option<EditorEvent> {
+        (self.source == "vscode")
+            .then(|| EditorEvent::from_payload(&self.payload).ok())
+            .flatten()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AiReport {
     pub id: i64,
     pub summary: String,
@@ -77,7 +239,7 @@ pub struct AiReport {
 pub struct AiReportRow {
     pub id: i64,
     pub summary: String,
-    pub generated_at: String,
+    pub generated_at: StoredTimestamp,
     pub log_count: Option<i64>,
     pub sources: Option<String>,
     pub session_id: Option<String>,
@@ -87,13 +249,13 @@ impl TryFrom<AiReportRow> for AiReport {
     type Error = ActivityLogConversionError;
 
     fn try_from(row: AiReportRow) -> Result<Self, Self::Error> {
-        let parsed_timestamp = DateTime::parse_from_rfc3339(&row.generated_at)
-            .map_err(|err| ActivityLogConversionError(err.to_string()))?;
+        let generated_at = row.generated_at.to_utc().map_err(ActivityLogConversionError)?;
 
         Ok(AiReport {
             id: row.id,
-            summary: row.summary,
-            generated_at: parsed_timestamp.with_timezone(&Utc),
+            summary: crypto::open(&row.summary)
+                .map_err(|err| ActivityLogConversionError(err.to_string()))?,
+            generated_at,
             log_count: row.log_count,
             sources: row.sources,
             session_id: row.session_id,
@@ -101,6 +263,92 @@ impl TryFrom<AiReportRow> for AiReport {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitRepository {
+    pub id: i64,
+    pub path: String,
+    pub added_at: DateTime<Utc>,
+    pub last_scanned_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+pub struct GitRepositoryRow {
+    pub id: i64,
+    pub path: String,
+    pub added_at: StoredTimestamp,
+    pub last_scanned_at: Option<StoredTimestamp>,
+}
+
+impl TryFrom<GitRepositoryRow> for GitRepository {
+    type Error = ActivityLogConversionError;
+
+    fn try_from(row: GitRepositoryRow) -> Result<Self, Self::Error> {
+        let parse = |value: &StoredTimestamp| value.to_utc().map_err(ActivityLogConversionError);
+
+        Ok(GitRepository {
+            id: row.id,
+            path: row.path,
+            added_at: parse(&row.added_at)?,
+            last_scanned_at: row.last_scanned_at.as_ref().map(parse).transpose()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct JournalSettings {
+    pub vault_dir: Option<String>,
+    pub auto_export: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RetentionSettings {
+    pub raw_log_days: Option<i64>,
+    pub report_days: Option<i64>,
+    pub strip_payload_after_summary: bool,
+    pub vacuum_interval_hours: Option<i64>,
+    #[serde(default, skip_deserializing)]
+    pub last_vacuum_at: Option<StoredTimestamp>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ServerSettings {
+    pub host: String,
+    pub port: i64,
+    /// How many ports after `port` to try when it is taken
+    pub fallback_ports: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RedactionSettings {
+    pub enabled: bool,
+    pub redact_emails: bool,
+    pub redact_ip_addresses: bool,
+    #[serde(default)]
+    pub pseudonymize_hostnames: bool,
+}
+
+impl RedactionSettings {
+    pub fn options(&self) -> RedactionOptions {
+        RedactionOptions {
+            redact_emails: self.redact_emails,
+            redact_ip_addresses: self.redact_ip_addresses,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RedactionRule {
+    #[serde(default)]
+    pub id: i64,
+    pub name: String,
+    pub pattern: String,
+    pub replacement: String,
+    pub kind: String,
+    pub source: Option<String>,
+    pub field: Option<String>,
+    pub enabled: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AiSettings {
     pub provider_url: String,
@@ -109,6 +357,12 @@ pub struct AiSettings {
     pub temperature: Option<f32>,
     pub batch_size: Option<i64>,
     pub summary_frequency_min: Option<i64>,
+    /// Only loopback and private-network providers may be used
+    pub local_only: bool,
+    /// When local-only mode was last switched on or off
+    pub local_only_changed_at: Option<StoredTimestamp>,
+    /// Keep full prompts in the request audit trail rather than only their hashes
+    pub audit_store_prompts: bool,
 }
 
 #[derive(sqlx::FromRow)]
@@ -119,6 +373,9 @@ pub struct AiSettingsRow {
     pub temperature: Option<f32>,
     pub batch_size: Option<i64>,
     pub summary_frequency_min: Option<i64>,
+    pub local_only: bool,
+    pub local_only_changed_at: Option<StoredTimestamp>,
+    pub audit_store_prompts: bool,
 }
 
 impl From<AiSettingsRow> for AiSettings {
@@ -130,6 +387,9 @@ impl From<AiSettingsRow> for AiSettings {
             temperature: row.temperature,
             batch_size: row.batch_size,
             summary_frequency_min: row.summary_frequency_min,
+            local_only: row.local_only,
+            local_only_changed_at: row.local_only_changed_at,
+            audit_store_prompts: row.audit_store_prompts,
         }
     }
 }
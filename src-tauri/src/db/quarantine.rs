@@ -0,0 +1,199 @@
+use anyhow::{anyhow, Error};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{SqliteExecutor, SqlitePool};
+
+use super::{
+    crypto,
+    models::{ActivityLog, ActivityLogConversionError, ActivityLogRow, AiReport, AiReportRow},
+    time::StoredTimestamp,
+};
+
+pub const TABLE_LOGS: &str = "activity_logs";
+pub const TABLE_REPORTS: &str = "ai_reports";
+
+pub const LOG_COLUMNS: &str =
+    "id, source, payload, timestamp, log_type, session_id, command, url, domain, title, file_path, project";
+pub const REPORT_COLUMNS: &str = "id, summary, generated_at, log_count, sources, session_id";
+
+/// A row that readers skip because it can't be converted, with what is wrong with it
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct QuarantinedRow {
+    pub id: i64,
+    pub table_name: String,
+    pub row_id: i64,
+    pub error: String,
+    pub detected_at: StoredTimestamp,
+    /// The row's stored timestamp (`timestamp` or `generated_at`) as it is
+    pub timestamp: Option<StoredTimestamp>,
+    /// The log payload or report summary, decrypted when possible
+    pub content: Option<String>,
+}
+
+/// How to resolve a quarantined row
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RepairAction {
+    /// Remove the row from its table
+    Delete,
+    /// Replace the timestamp (RFC 3339) and/or content (log payload JSON or report summary). Fields left out are kept.
+    Fix {
+        #[serde(default)]
+        timestamp: Option<String>,
+        #[serde(default)]
+        content: Option<String>,
+    },
+}
+
+pub async fn record(executor: impl SqliteExecutor<'_>, table: &str, row_id: i64, error: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO quarantined_rows (table_name, row_id, error, detected_at) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(table_name, row_id) DO UPDATE SET error = excluded.error",
+    )
+    .bind(table)
+    .bind(row_id)
+    .bind(error)
+    .bind(Utc::now().timestamp_millis())
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// Convert rows into models, skipping the ones that fail and recording them in the quarantine
+/// table, so one bad row doesn't fail a whole timeline or summary
+async fn convert_rows<R, T>(pool: &SqlitePool, table: &str, rows: Vec<R>, row_id: impl Fn(&R) -> i64) -> Vec<T>
+where
+    T: TryFrom<R, Error = ActivityLogConversionError>,
+{
+    let mut converted = Vec::with_capacity(rows.len());
+    for row in rows {
+        let id = row_id(&row);
+        match T::try_from(row) {
+            Ok(model) => converted.push(model),
+            Err(err) => {
+                eprintln!("⚠️  Skipping unreadable {table} row {id}: {}", err.0);
+                if let Err(db_err) = record(pool, table, id, &err.0).await {
+                    eprintln!("⚠️  Failed to quarantine {table} row {id}: {db_err}");
+                }
+            }
+        }
+    }
+    converted
+}
+
+pub async fn load_logs(pool: &SqlitePool, rows: Vec<ActivityLogRow>) -> Vec<ActivityLog> {
+    convert_rows(pool, TABLE_LOGS, rows, |row| row.id).await
+}
+
+pub async fn load_reports(pool: &SqlitePool, rows: Vec<AiReportRow>) -> Vec<AiReport> {
+    convert_rows(pool, TABLE_REPORTS, rows, |row| row.id).await
+}
+
+/// Quarantined rows that still exist, newest first
+pub async fn list(pool: &SqlitePool) -> Result<Vec<QuarantinedRow>, sqlx::Error> {
+    // Rows deleted since, e.g. by retention, need no repair
+    sqlx::query(
+        "DELETE FROM quarantined_rows WHERE
+            (table_name = 'activity_logs' AND row_id NOT IN (SELECT id FROM activity_logs))
+            OR (table_name = 'ai_reports' AND row_id NOT IN (SELECT id FROM ai_reports))",
+    )
+    .execute(pool)
+    .await?;
+
+    let mut rows = sqlx::query_as::<_, QuarantinedRow>(
+        "SELECT q.id, q.table_name, q.row_id, q.error, q.detected_at,
+                COALESCE(l.timestamp, r.generated_at) AS timestamp,
+                COALESCE(l.payload, r.summary) AS content
+         FROM quarantined_rows q
+         LEFT JOIN activity_logs l ON q.table_name = 'activity_logs' AND l.id = q.row_id
+         LEFT JOIN ai_reports r ON q.table_name = 'ai_reports' AND r.id = q.row_id
+         ORDER BY q.detected_at DESC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    // A row may be quarantined because it can't be decrypted, so fall back to the stored text
+    for row in &mut rows {
+        if let Some(content) = row.content.take() {
+            row.content = Some(crypto::open(&content).unwrap_or(content));
+        }
+    }
+
+    Ok(rows)
+}
+
+/// Delete or fix a quarantined row. A fixed row must be readable afterwards, otherwise nothing is changed.
+pub async fn repair(pool: &SqlitePool, id: i64, action: RepairAction) -> Result<(), Error> {
+    let (table, row_id): (String, i64) =
+        sqlx::query_as("SELECT table_name, row_id FROM quarantined_rows WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| anyhow!("no quarantined row with id {id}"))?;
+
+    let (timestamp_column, content_column) = match table.as_str() {
+        TABLE_LOGS => ("timestamp", "payload"),
+        TABLE_REPORTS => ("generated_at", "summary"),
+        other => return Err(anyhow!("unknown quarantined table '{other}'")),
+    };
+
+    let _writes = crypto::write_access().await;
+    let mut tx = pool.begin().await?;
+
+    match action {
+        RepairAction::Delete => {
+            sqlx::query(&format!("DELETE FROM {table} WHERE id = ?1"))
+                .bind(row_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        RepairAction::Fix { timestamp, content } => {
+            let timestamp = timestamp
+                .map(|value| StoredTimestamp::Text(value).to_utc().map(|parsed| parsed.timestamp_millis()))
+                .transpose()
+                .map_err(|err| anyhow!(err))?;
+
+            if table == TABLE_LOGS {
+                if let Some(payload) = &content {
+                    serde_json::from_str::<Value>(payload).map_err(|err| anyhow!("payload is not valid JSON: {err}"))?;
+                }
+            }
+            let content = crypto::seal_opt(content.as_deref())?;
+
+            sqlx::query(&format!(
+                "UPDATE {table} SET {timestamp_column} = COALESCE(?1, {timestamp_column}),
+                 {content_column} = COALESCE(?2, {content_column}) WHERE id = ?3"
+            ))
+            .bind(timestamp)
+            .bind(content)
+            .bind(row_id)
+            .execute(&mut *tx)
+            .await?;
+
+            let check = if table == TABLE_LOGS {
+                let row = sqlx::query_as::<_, ActivityLogRow>(&format!("SELECT {LOG_COLUMNS} FROM {table} WHERE id = ?1"))
+                    .bind(row_id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                ActivityLog::try_from(row).map(|_| ())
+            } else {
+                let row = sqlx::query_as::<_, AiReportRow>(&format!("SELECT {REPORT_COLUMNS} FROM {table} WHERE id = ?1"))
+                    .bind(row_id)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                AiReport::try_from(row).map(|_| ())
+            };
+            check.map_err(|err| anyhow!("row still can't be read: {}", err.0))?;
+        }
+    }
+
+    sqlx::query("DELETE FROM quarantined_rows WHERE id = ?1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
@@ -0,0 +1,627 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::Error;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use utoipa::{IntoParams, ToSchema};
+
+use super::{
+    crypto,
+    models::{ActivityLog, ActivityLogRow, AiReport, AiReportRow},
+    quarantine::{self, LOG_COLUMNS, REPORT_COLUMNS},
+    time::{self, StoredTimestamp},
+};
+
+pub const DEFAULT_PAGE_SIZE: i64 = 100;
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+/// Rows decrypted at a time while searching
+const SEARCH_BATCH_SIZE: i64 = 500;
+/// Entries in each ranking of [`ActivityStats`]
+const TOP_ENTRIES: i64 = 10;
+
+/// Time bounds. Instants and local days can be combined, the narrowest bound wins.
+/// Days are `YYYY-MM-DD` in the configured time zone.
+#[derive(Debug, Default, Clone, Deserialize, IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct TimeRange {
+    /// Inclusive, RFC 3339
+    pub start: Option<DateTime<Utc>>,
+    /// Exclusive, RFC 3339
+    pub end: Option<DateTime<Utc>>,
+    /// First local day
+    pub from_date: Option<NaiveDate>,
+    /// Last local day, covered entirely
+    pub to_date: Option<NaiveDate>,
+}
+
+impl TimeRange {
+    /// Half-open bounds in epoch milliseconds
+    fn bounds(&self) -> (Option<i64>, Option<i64>) {
+        let zone = time::current();
+        let day_start = self.from_date.map(|day| zone.start_of_day(day));
+        let day_end = self.to_date.map(|day| zone.day_range(day, day).1);
+
+        let start = self.start.into_iter().chain(day_start).max();
+        let end = self.end.into_iter().chain(day_end).min();
+        (start.map(|at| at.timestamp_millis()), end.map(|at| at.timestamp_millis()))
+    }
+}
+
+/// Filters for [`query_logs`]. Everything is optional, an empty query pages through all logs.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct LogQuery {
+    /// Inclusive, RFC 3339
+    pub start: Option<DateTime<Utc>>,
+    /// Exclusive, RFC 3339
+    pub end: Option<DateTime<Utc>>,
+    /// First local day
+    pub from_date: Option<NaiveDate>,
+    /// Last local day, covered entirely
+    pub to_date: Option<NaiveDate>,
+    pub source: Option<String>,
+    pub log_type: Option<String>,
+    /// Project name, see `export::event_project`
+    pub project: Option<String>,
+    /// Matches the domain and its subdomains
+    pub domain: Option<String>,
+    pub exit_code: Option<i64>,
+    pub session_id: Option<String>,
+    /// Case-insensitive text in the command, URL, title, file path or payload
+    #[serde(alias = "q")]
+    pub text: Option<String>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    /// Page size, 100 by default and at most 1000
+    pub limit: Option<i64>,
+}
+
+impl LogQuery {
+    pub fn range(&self) -> TimeRange {
+        TimeRange {
+            start: self.start,
+            end: self.end,
+            from_date: self.from_date,
+            to_date: self.to_date,
+        }
+    }
+}
+
+/// Filters for [`query_reports`]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// Inclusive, RFC 3339
+    pub start: Option<DateTime<Utc>>,
+    /// Exclusive, RFC 3339
+    pub end: Option<DateTime<Utc>>,
+    /// First local day
+    pub from_date: Option<NaiveDate>,
+    /// Last local day, covered entirely
+    pub to_date: Option<NaiveDate>,
+    /// Reports that summarized logs from this source
+    pub source: Option<String>,
+    pub session_id: Option<String>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    /// Page size, 100 by default and at most 1000
+    pub limit: Option<i64>,
+}
+
+impl ReportQuery {
+    pub fn range(&self) -> TimeRange {
+        TimeRange {
+            start: self.start,
+            end: self.end,
+            from_date: self.from_date,
+            to_date: self.to_date,
+        }
+    }
+}
+
+/// One page of results, newest first
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[aliases(LogPage = Page<ActivityLog>, ReportPage = Page<AiReport>)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `cursor` to get the next page, `null` on the last page
+    pub next_cursor: Option<String>,
+    /// How many rows match the filters across all pages
+    pub total: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NamedCount {
+    pub name: String,
+    pub count: i64,
+}
+
+/// Activity in a time range
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ActivityStats {
+    pub total_logs: i64,
+    pub total_reports: i64,
+    pub sources: Vec<NamedCount>,
+    pub log_types: Vec<NamedCount>,
+    /// The 10 projects with the most logs
+    pub projects: Vec<NamedCount>,
+    /// The 10 most visited domains
+    pub domains: Vec<NamedCount>,
+    /// Logs per local day (`YYYY-MM-DD`), days without logs left out
+    pub days: Vec<NamedCount>,
+}
+
+/// A `cursor` that wasn't returned as `next_cursor`
+#[derive(Debug)]
+pub struct InvalidCursor(pub String);
+
+impl fmt::Display for InvalidCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cursor '{}'", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCursor {}
+
+/// Position after the last row of a page: its timestamp and id, since timestamps aren't unique
+struct Cursor {
+    timestamp: i64,
+    id: i64,
+}
+
+impl Cursor {
+    fn parse(value: &str) -> Result<Self, Error> {
+        value
+            .split_once(':')
+            .and_then(|(timestamp, id)| Some(Cursor { timestamp: timestamp.parse().ok()?, id: id.parse().ok()? }))
+            .ok_or_else(|| InvalidCursor(value.to_string()).into())
+    }
+
+    fn at(timestamp: &StoredTimestamp, id: i64) -> Option<Self> {
+        match timestamp {
+            StoredTimestamp::Millis(millis) => Some(Cursor { timestamp: *millis, id }),
+            StoredTimestamp::Text(_) => None,
+        }
+    }
+
+    fn of_log(log: &ActivityLog) -> Self {
+        Cursor {
+            timestamp: log.timestamp.timestamp_millis(),
+            id: log.id,
+        }
+    }
+
+    /// Whether a row comes after this position in newest-first order
+    fn precedes(&self, other: &Cursor) -> bool {
+        (other.timestamp, other.id) < (self.timestamp, self.id)
+    }
+
+    fn encode(&self) -> String {
+        format!("{}:{}", self.timestamp, self.id)
+    }
+}
+
+fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Rows whose timestamp is still text are quarantined and left out, see [`time::migrate_to_millis`]
+fn push_range(builder: &mut QueryBuilder<'_, Sqlite>, column: &str, range: &TimeRange) {
+    let (start, end) = range.bounds();
+    builder.push(format!(" WHERE typeof({column}) = 'integer'"));
+    if let Some(start) = start {
+        builder.push(format!(" AND {column} >= ")).push_bind(start);
+    }
+    if let Some(end) = end {
+        builder.push(format!(" AND {column} < ")).push_bind(end);
+    }
+}
+
+fn push_log_filters(builder: &mut QueryBuilder<'_, Sqlite>, query: &LogQuery) {
+    push_range(builder, "timestamp", &query.range());
+    let columns = [
+        ("source", &query.source),
+        ("log_type", &query.log_type),
+        ("session_id", &query.session_id),
+    ];
+    for (column, value) in columns {
+        if let Some(value) = value {
+            builder.push(format!(" AND {column} = ")).push_bind(value.clone());
+        }
+    }
+    if let Some(exit_code) = query.exit_code {
+        builder.push(" AND exit_code = ").push_bind(exit_code);
+    }
+}
+
+fn push_report_filters(builder: &mut QueryBuilder<'_, Sqlite>, query: &ReportQuery) {
+    push_range(builder, "generated_at", &query.range());
+    if let Some(source) = &query.source {
+        builder.push(" AND (',' || sources || ',') LIKE ").push_bind(format!("%,{source},%"));
+    }
+    if let Some(session_id) = &query.session_id {
+        builder.push(" AND session_id = ").push_bind(session_id.clone());
+    }
+}
+
+fn push_cursor(builder: &mut QueryBuilder<'_, Sqlite>, column: &str, cursor: Option<&Cursor>) {
+    if let Some(cursor) = cursor {
+        builder
+            .push(format!(" AND ({column} < "))
+            .push_bind(cursor.timestamp)
+            .push(format!(" OR ({column} = "))
+            .push_bind(cursor.timestamp)
+            .push(" AND id < ")
+            .push_bind(cursor.id)
+            .push("))");
+    }
+    builder.push(format!(" ORDER BY {column} DESC, id DESC"));
+}
+
+pub async fn query_logs(pool: &SqlitePool, query: &LogQuery) -> Result<Page<ActivityLog>, Error> {
+    let limit = page_size(query.limit);
+    let cursor = query.cursor.as_deref().filter(|cursor| !cursor.is_empty()).map(Cursor::parse).transpose()?;
+
+    // Projects, domains and text may be encrypted, so these filters are applied after decrypting
+    let text = query.text.as_deref().map(str::trim).filter(|text| !text.is_empty()).map(str::to_lowercase);
+    let domain = query.domain.as_deref().map(|domain| domain.trim().trim_start_matches("www.").to_lowercase());
+    if text.is_some() || domain.is_some() || query.project.is_some() {
+        let matches = |log: &ActivityLog| {
+            text.as_deref().is_none_or(|needle| matches_text(log, needle))
+                && domain.as_deref().is_none_or(|domain| matches_domain(log, domain))
+                && query.project.as_ref().is_none_or(|project| log.project.as_ref() == Some(project))
+        };
+        return scan_logs(pool, query, &matches, cursor, limit).await;
+    }
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM activity_logs");
+    push_log_filters(&mut count, query);
+    let total: i64 = count.build_query_scalar().fetch_one(pool).await?;
+
+    let mut select = QueryBuilder::new(format!("SELECT {LOG_COLUMNS} FROM activity_logs"));
+    push_log_filters(&mut select, query);
+    push_cursor(&mut select, "timestamp", cursor.as_ref());
+    select.push(" LIMIT ").push_bind(limit + 1);
+    let mut rows: Vec<ActivityLogRow> = select.build_query_as().fetch_all(pool).await?;
+
+    // The cursor comes from the last row fetched, so rows skipped as unreadable don't end paging early
+    let next_cursor = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().and_then(|row| Cursor::at(&row.timestamp, row.id)).map(|cursor| cursor.encode())
+    } else {
+        None
+    };
+
+    Ok(Page {
+        items: quarantine::load_logs(pool, rows).await,
+        next_cursor,
+        total,
+    })
+}
+
+fn matches_text(log: &ActivityLog, needle: &str) -> bool {
+    let fields = [&log.command, &log.url, &log.title, &log.file_path, &log.domain];
+    fields
+        .into_iter()
+        .flatten()
+        .any(|value| value.to_lowercase().contains(needle))
+        || log.payload.to_string().to_lowercase().contains(needle)
+}
+
+/// The domain or one of its subdomains
+fn matches_domain(log: &ActivityLog, domain: &str) -> bool {
+    log.domain
+        .as_deref()
+        .map(str::to_lowercase)
+        .is_some_and(|value| value == domain || value.ends_with(&format!(".{domain}")))
+}
+
+/// Page through the logs passing the SQL filters and `matches`, which sees them decrypted.
+/// All filtered rows are scanned to count the matches.
+async fn scan_logs(
+    pool: &SqlitePool,
+    query: &LogQuery,
+    matches: &(dyn Fn(&ActivityLog) -> bool + Sync),
+    cursor: Option<Cursor>,
+    limit: i64,
+) -> Result<Page<ActivityLog>, Error> {
+    let mut items = Vec::new();
+    let mut next_cursor = None;
+    let mut total = 0;
+    let mut position: Option<Cursor> = None;
+
+    loop {
+        let mut select = QueryBuilder::new(format!("SELECT {LOG_COLUMNS} FROM activity_logs"));
+        push_log_filters(&mut select, query);
+        push_cursor(&mut select, "timestamp", position.as_ref());
+        select.push(" LIMIT ").push_bind(SEARCH_BATCH_SIZE);
+        let rows: Vec<ActivityLogRow> = select.build_query_as().fetch_all(pool).await?;
+
+        let Some(last) = rows.last() else {
+            break;
+        };
+        position = Cursor::at(&last.timestamp, last.id);
+
+        for log in quarantine::load_logs(pool, rows).await {
+            if !matches(&log) {
+                continue;
+            }
+            total += 1;
+
+            let current = Cursor::of_log(&log);
+            if cursor.as_ref().is_some_and(|cursor| !cursor.precedes(&current)) {
+                continue;
+            }
+            if (items.len() as i64) < limit {
+                items.push(log);
+            } else if next_cursor.is_none() {
+                next_cursor = items.last().map(|last| Cursor::of_log(last).encode());
+            }
+        }
+
+        if position.is_none() {
+            break;
+        }
+    }
+
+    Ok(Page {
+        items,
+        next_cursor,
+        total,
+    })
+}
+
+pub async fn query_reports(pool: &SqlitePool, query: &ReportQuery) -> Result<Page<AiReport>, Error> {
+    let limit = page_size(query.limit);
+    let cursor = query.cursor.as_deref().filter(|cursor| !cursor.is_empty()).map(Cursor::parse).transpose()?;
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM ai_reports");
+    push_report_filters(&mut count, query);
+    let total: i64 = count.build_query_scalar().fetch_one(pool).await?;
+
+    let mut select = QueryBuilder::new(format!("SELECT {REPORT_COLUMNS} FROM ai_reports"));
+    push_report_filters(&mut select, query);
+    push_cursor(&mut select, "generated_at", cursor.as_ref());
+    select.push(" LIMIT ").push_bind(limit + 1);
+    let mut rows: Vec<AiReportRow> = select.build_query_as().fetch_all(pool).await?;
+
+    let next_cursor = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().and_then(|row| Cursor::at(&row.generated_at, row.id)).map(|cursor| cursor.encode())
+    } else {
+        None
+    };
+
+    Ok(Page {
+        items: quarantine::load_reports(pool, rows).await,
+        next_cursor,
+        total,
+    })
+}
+
+/// The most frequent values of an encrypted column, counted after decrypting. Empty values are left out,
+/// and so are values that can't be decrypted, whose rows are quarantined.
+async fn top_decrypted(pool: &SqlitePool, range: &TimeRange, column: &str) -> Result<Vec<NamedCount>, Error> {
+    let mut select = QueryBuilder::new(format!("SELECT id, {column} FROM activity_logs"));
+    push_range(&mut select, "timestamp", range);
+    select.push(format!(" AND COALESCE({column}, '') != ''"));
+    let stored: Vec<(i64, String)> = select.build_query_as().fetch_all(pool).await?;
+
+    let mut counts: BTreeMap<String, i64> = BTreeMap::new();
+    for (id, value) in stored {
+        match crypto::open(&value) {
+            Ok(value) if value.is_empty() => {}
+            Ok(value) => *counts.entry(value).or_insert(0) += 1,
+            Err(err) => {
+                eprintln!("⚠️  Skipping unreadable {column} of log {id}: {err}");
+                quarantine::record(pool, quarantine::TABLE_LOGS, id, &err.to_string()).await?;
+            }
+        }
+    }
+
+    let mut top: Vec<NamedCount> = counts.into_iter().map(|(name, count)| NamedCount { name, count }).collect();
+    // Stable, so equal counts stay in name order
+    top.sort_by_key(|entry| std::cmp::Reverse(entry.count));
+    top.truncate(TOP_ENTRIES as usize);
+    Ok(top)
+}
+
+/// Log counts grouped by `column`, most frequent first. Empty values are left out.
+async fn count_by(pool: &SqlitePool, range: &TimeRange, column: &str) -> Result<Vec<NamedCount>, Error> {
+    let mut select = QueryBuilder::new(format!("SELECT {column}, COUNT(*) FROM activity_logs"));
+    push_range(&mut select, "timestamp", range);
+    select.push(format!(" AND COALESCE({column}, '') != '' GROUP BY {column} ORDER BY COUNT(*) DESC, {column} ASC"));
+
+    let counts: Vec<(String, i64)> = select.build_query_as().fetch_all(pool).await?;
+    Ok(counts.into_iter().map(|(name, count)| NamedCount { name, count }).collect())
+}
+
+pub async fn activity_stats(pool: &SqlitePool, range: &TimeRange) -> Result<ActivityStats, Error> {
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM activity_logs");
+    push_range(&mut count, "timestamp", range);
+    let total_logs: i64 = count.build_query_scalar().fetch_one(pool).await?;
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM ai_reports");
+    push_range(&mut count, "generated_at", range);
+    let total_reports: i64 = count.build_query_scalar().fetch_one(pool).await?;
+
+    // Days are cut in the configured zone, which SQLite doesn't know about
+    let mut select = QueryBuilder::new("SELECT timestamp FROM activity_logs");
+    push_range(&mut select, "timestamp", range);
+    let timestamps: Vec<i64> = select.build_query_scalar().fetch_all(pool).await?;
+    let zone = time::current();
+    let mut days = BTreeMap::new();
+    for at in timestamps.into_iter().filter_map(DateTime::from_timestamp_millis) {
+        *days.entry(zone.local_date(at)).or_insert(0) += 1;
+    }
+
+    Ok(ActivityStats {
+        total_logs,
+        total_reports,
+        sources: count_by(pool, range, "source").await?,
+        log_types: count_by(pool, range, "log_type").await?,
+        projects: top_decrypted(pool, range, "project").await?,
+        domains: top_decrypted(pool, range, "domain").await?,
+        days: days
+            .into_iter()
+            .map(|(day, count)| NamedCount { name: day.to_string(), count })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{db, server::handlers};
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_714_550_400, 0).unwrap() + Duration::minutes(minute)
+    }
+
+    async fn store(pool: &SqlitePool, source: &str, minute: i64, payload: Value) -> i64 {
+        match handlers::store_event(pool, source, payload, at(minute), None).await.unwrap() {
+            handlers::StoreOutcome::Stored(log) => log.id,
+            handlers::StoreOutcome::Dropped(reason) => panic!("dropped: {}", reason.as_str()),
+        }
+    }
+
+    /// Every page of `query`, following the cursors
+    async fn all_pages(pool: &SqlitePool, mut query: LogQuery) -> Vec<Page<ActivityLog>> {
+        let mut pages = Vec::new();
+        loop {
+            let page = query_logs(pool, &query).await.unwrap();
+            query.cursor = page.next_cursor.clone();
+            pages.push(page);
+            if query.cursor.is_none() {
+                return pages;
+            }
+        }
+    }
+
+    fn ids(pages: &[Page<ActivityLog>]) -> Vec<i64> {
+        pages.iter().flat_map(|page| page.items.iter().map(|log| log.id)).collect()
+    }
+
+    #[tokio::test]
+    async fn cursors_page_through_equal_timestamps_without_gaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        let mut stored = Vec::new();
+        for minute in [0, 1, 1, 1, 2] {
+            stored.push(store(&pool, "terminal", minute, json!({ "command": format!("echo {minute}") })).await);
+        }
+
+        let pages = all_pages(&pool, LogQuery { limit: Some(2), ..Default::default() }).await;
+
+        // Newest first, ties broken by id
+        let expected = [stored[4], stored[3], stored[2], stored[1], stored[0]];
+        assert_eq!(ids(&pages), expected);
+        assert_eq!(pages.len(), 3);
+        assert!(pages.iter().all(|page| page.total == 5));
+
+        // Scanning for text pages the same way
+        let query = LogQuery { text: Some("ECHO".to_string()), limit: Some(2), ..Default::default() };
+        let pages = all_pages(&pool, query).await;
+        assert_eq!(ids(&pages), expected);
+        assert!(pages.iter().all(|page| page.total == 5));
+    }
+
+    #[tokio::test]
+    async fn filters_narrow_items_and_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        store(&pool, "terminal", 0, json!({ "command": "cargo test", "exit_code": 101, "cwd": "/nowhere/app" })).await;
+        store(&pool, "terminal", 10, json!({ "command": "cargo build", "exit_code": 0, "cwd": "/nowhere/app" })).await;
+        store(&pool, "terminal", 20, json!({ "command": "ls", "exit_code": 0, "cwd": "/nowhere/site" })).await;
+        store(&pool, "browser", 30, json!({ "url": "https://docs.rs/sqlx", "title": "sqlx docs" })).await;
+        store(&pool, "browser", 40, json!({ "url": "https://notdocs.rs/", "title": "Elsewhere" })).await;
+
+        let count = |query: LogQuery| {
+            let pool = pool.clone();
+            async move {
+                let page = query_logs(&pool, &query).await.unwrap();
+                assert_eq!(page.items.len() as i64, page.total);
+                page.total
+            }
+        };
+
+        assert_eq!(count(LogQuery::default()).await, 5);
+        assert_eq!(count(LogQuery { source: Some("browser".to_string()), ..Default::default() }).await, 2);
+        assert_eq!(count(LogQuery { log_type: Some("command".to_string()), ..Default::default() }).await, 3);
+        assert_eq!(count(LogQuery { exit_code: Some(0), ..Default::default() }).await, 2);
+        assert_eq!(count(LogQuery { project: Some("app".to_string()), ..Default::default() }).await, 2);
+        assert_eq!(count(LogQuery { domain: Some("docs.rs".to_string()), ..Default::default() }).await, 1);
+        assert_eq!(count(LogQuery { text: Some("sqlx".to_string()), ..Default::default() }).await, 1);
+        // Start is inclusive, end exclusive
+        assert_eq!(count(LogQuery { start: Some(at(10)), end: Some(at(30)), ..Default::default() }).await, 2);
+        assert_eq!(
+            count(LogQuery {
+                project: Some("app".to_string()),
+                exit_code: Some(0),
+                start: Some(at(5)),
+                ..Default::default()
+            })
+            .await,
+            1
+        );
+
+        let reports = [("terminal,browser", 0), ("vscode", 10), ("terminal", 20)];
+        for (sources, minute) in reports {
+            sqlx::query("INSERT INTO ai_reports (summary, generated_at, log_count, sources) VALUES ('Summary', ?1, 1, ?2)")
+                .bind(at(minute).timestamp_millis())
+                .bind(sources)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let query = ReportQuery { source: Some("terminal".to_string()), limit: Some(1), ..Default::default() };
+        let first = query_reports(&pool, &query).await.unwrap();
+        assert_eq!((first.total, first.items[0].generated_at), (2, at(20)));
+        let query = ReportQuery { cursor: first.next_cursor, ..query };
+        let second = query_reports(&pool, &query).await.unwrap();
+        assert_eq!((second.items[0].generated_at, second.next_cursor), (at(0), None));
+    }
+
+    #[tokio::test]
+    async fn rejects_cursors_it_did_not_issue() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+
+        let query = LogQuery { cursor: Some("yesterday".to_string()), ..Default::default() };
+        let err = query_logs(&pool, &query).await.unwrap_err();
+        assert!(err.downcast_ref::<InvalidCursor>().is_some());
+    }
+
+    #[tokio::test]
+    async fn stats_skip_and_quarantine_undecryptable_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        store(&pool, "browser", 0, json!({ "url": "https://docs.rs/", "title": "Docs" })).await;
+        store(&pool, "browser", 1, json!({ "url": "https://docs.rs/sqlx", "title": "sqlx" })).await;
+        let broken = store(&pool, "browser", 2, json!({ "url": "https://crates.io/", "title": "Crates" })).await;
+        sqlx::query("UPDATE activity_logs SET domain = 'enc:v1:not-a-ciphertext' WHERE id = ?1")
+            .bind(broken)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let stats = activity_stats(&pool, &TimeRange::default()).await.unwrap();
+        assert_eq!(stats.total_logs, 3);
+        let domains: Vec<(&str, i64)> = stats.domains.iter().map(|domain| (domain.name.as_str(), domain.count)).collect();
+        assert_eq!(domains, [("docs.rs", 2)]);
+
+        let quarantined: Vec<i64> = sqlx::query_scalar("SELECT row_id FROM quarantined_rows")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(quarantined, [broken]);
+    }
+}
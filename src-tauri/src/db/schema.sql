@@ -3,7 +3,7 @@ CREATE TABLE IF NOT EXISTS activity_logs (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     source TEXT NOT NULL,
     payload TEXT NOT NULL,
-    timestamp DATETIME NOT NULL,
+    timestamp INTEGER NOT NULL,             -- UTC epoch milliseconds
     is_processed BOOLEAN DEFAULT 0,
     
     -- Normalized fields extracted from payload
@@ -27,6 +27,10 @@ CREATE TABLE IF NOT EXISTS activity_logs (
     language TEXT,
     
     -- Common metadata
+    project TEXT,                           -- Project name, '' when unknown, NULL until extracted
+    redactions INTEGER NOT NULL DEFAULT 0,  -- Sensitive values replaced at ingestion
+    event_id TEXT,                          -- Stable identity for deduplication, see db::event_id
+    payload_stripped BOOLEAN NOT NULL DEFAULT 0,  -- Payload reduced to its identifying keys by retention
     created_at DATETIME DEFAULT CURRENT_TIMESTAMP
 );
 
@@ -35,19 +39,20 @@ CREATE INDEX IF NOT EXISTS idx_unprocessed ON activity_logs(is_processed, timest
 CREATE INDEX IF NOT EXISTS idx_source_timestamp ON activity_logs(source, timestamp);
 CREATE INDEX IF NOT EXISTS idx_session_id ON activity_logs(session_id, timestamp);
 CREATE INDEX IF NOT EXISTS idx_domain ON activity_logs(domain, timestamp);
+CREATE INDEX IF NOT EXISTS idx_timestamp ON activity_logs(timestamp DESC, id DESC);
 
 -- AI reports table with metadata linking to source logs
 CREATE TABLE IF NOT EXISTS ai_reports (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     summary TEXT NOT NULL,
-    generated_at DATETIME NOT NULL,
+    generated_at INTEGER NOT NULL,          -- UTC epoch milliseconds
     
     -- Metadata about the report
     log_ids TEXT,                           -- JSON array of source log IDs
     log_count INTEGER DEFAULT 0,
     sources TEXT,                           -- Comma-separated: 'terminal,browser,vscode'
-    time_range_start DATETIME,
-    time_range_end DATETIME,
+    time_range_start INTEGER,
+    time_range_end INTEGER,
     session_id TEXT,                        -- If tied to a specific session
     
     -- Generation context
@@ -59,6 +64,68 @@ CREATE TABLE IF NOT EXISTS ai_reports (
 CREATE INDEX IF NOT EXISTS idx_reports_generated ON ai_reports(generated_at DESC);
 CREATE INDEX IF NOT EXISTS idx_reports_session ON ai_reports(session_id, generated_at DESC);
 
+-- One row per request sent to the AI provider, for auditing what left the machine
+CREATE TABLE IF NOT EXISTS ai_requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    report_id INTEGER,                      -- ai_reports.id of the resulting report, if one was saved
+    created_at INTEGER NOT NULL,            -- UTC epoch milliseconds
+    model TEXT NOT NULL,
+    provider_host TEXT NOT NULL,
+    prompt TEXT,                            -- Exact request body after sanitization, NULL in hash-only mode
+    prompt_sha256 TEXT NOT NULL,
+    prompt_tokens INTEGER,                  -- From the response's `usage`, when the provider reports it
+    completion_tokens INTEGER,
+    total_tokens INTEGER,
+    latency_ms INTEGER NOT NULL,
+    http_status INTEGER,
+    status TEXT NOT NULL,                   -- 'ok' or 'error'
+    error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_ai_requests_report ON ai_requests(report_id);
+CREATE INDEX IF NOT EXISTS idx_ai_requests_created ON ai_requests(created_at);
+
+-- Local git repositories scanned for commits and branch switches
+CREATE TABLE IF NOT EXISTS git_repositories (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE,              -- Repository top-level directory
+    added_at INTEGER NOT NULL,              -- UTC epoch milliseconds. History before this is not imported.
+    last_scanned_at INTEGER                 -- UTC epoch milliseconds
+);
+
+-- Markdown journal export settings (single row)
+CREATE TABLE IF NOT EXISTS journal_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    vault_dir TEXT,                         -- Target directory, e.g. an Obsidian vault folder
+    auto_export BOOLEAN DEFAULT 0           -- Export after each scheduler run
+);
+
+INSERT INTO journal_settings (id, vault_dir, auto_export)
+SELECT 1, NULL, 0
+WHERE NOT EXISTS (SELECT 1 FROM journal_settings WHERE id = 1);
+
+-- One row per exported journal day, used to skip days whose content has not changed
+CREATE TABLE IF NOT EXISTS journal_exports (
+    day TEXT PRIMARY KEY,                   -- YYYY-MM-DD
+    content_hash TEXT NOT NULL,
+    exported_at INTEGER NOT NULL,           -- UTC epoch milliseconds
+    fingerprint TEXT                        -- log/report counts and max ids of the day when exported
+);
+
+-- Data retention policy (single row). NULL day counts mean keep forever.
+CREATE TABLE IF NOT EXISTS retention_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    raw_log_days INTEGER,                   -- Delete activity logs older than this
+    report_days INTEGER,                    -- Delete AI reports older than this
+    strip_payload_after_summary BOOLEAN DEFAULT 0,  -- Drop raw payload JSON once summarized
+    vacuum_interval_hours INTEGER DEFAULT 24,
+    last_vacuum_at INTEGER                  -- UTC epoch milliseconds
+);
+
+INSERT INTO retention_settings (id, raw_log_days, report_days, strip_payload_after_summary, vacuum_interval_hours)
+SELECT 1, NULL, NULL, 0, 24
+WHERE NOT EXISTS (SELECT 1 FROM retention_settings WHERE id = 1);
+
 -- AI settings table (unchanged)
 CREATE TABLE IF NOT EXISTS ai_settings (
     id INTEGER PRIMARY KEY CHECK (id = 1),
@@ -70,10 +137,124 @@ CREATE TABLE IF NOT EXISTS ai_settings (
     temperature REAL DEFAULT 0.2,
     batch_size INTEGER DEFAULT 100,
     summary_frequency_min INTEGER DEFAULT 10,
-    max_summary_tokens INTEGER DEFAULT 2000
+    max_summary_tokens INTEGER DEFAULT 2000,
+
+    -- Refuse providers that resolve outside loopback and private networks
+    local_only BOOLEAN NOT NULL DEFAULT 0,
+    local_only_changed_at INTEGER,          -- UTC epoch milliseconds
+
+    -- Keep the full prompt in ai_requests, when off only its SHA-256 is kept
+    audit_store_prompts BOOLEAN NOT NULL DEFAULT 0
 );
 
 -- Insert default settings only if table is empty
 INSERT INTO ai_settings (id, provider_url, api_key, model_name, temperature, batch_size, summary_frequency_min, max_summary_tokens)
 SELECT 1, 'http://localhost:1234/v1', NULL, 'gpt-4o-mini', 0.2, 100, 10, 2000
 WHERE NOT EXISTS (SELECT 1 FROM ai_settings WHERE id = 1);
+
+-- Time zone used to cut days for date-based views and rollups (single row).
+-- 'system' follows the OS zone, otherwise a fixed offset such as '-07:00' or 'UTC'
+CREATE TABLE IF NOT EXISTS time_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    timezone TEXT NOT NULL DEFAULT 'system'
+);
+
+INSERT INTO time_settings (id, timezone)
+SELECT 1, 'system'
+WHERE NOT EXISTS (SELECT 1 FROM time_settings WHERE id = 1);
+
+-- Where the ingestion server listens (single row). DEVCHRONICLE_HOST / DEVCHRONICLE_PORT override it.
+-- When the port is taken the next `fallback_ports` ports are tried in order
+CREATE TABLE IF NOT EXISTS server_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    host TEXT NOT NULL DEFAULT '127.0.0.1',
+    port INTEGER NOT NULL DEFAULT 3030,
+    fallback_ports INTEGER NOT NULL DEFAULT 10
+);
+
+INSERT INTO server_settings (id, host, port, fallback_ports)
+SELECT 1, '127.0.0.1', 3030, 10
+WHERE NOT EXISTS (SELECT 1 FROM server_settings WHERE id = 1);
+
+-- Encryption of stored activity data (single row). The key itself lives in secret.key.
+CREATE TABLE IF NOT EXISTS encryption_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO encryption_settings (id, enabled)
+SELECT 1, 0
+WHERE NOT EXISTS (SELECT 1 FROM encryption_settings WHERE id = 1);
+
+-- Redaction applied to incoming events before they are stored (single row)
+CREATE TABLE IF NOT EXISTS redaction_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    redact_emails BOOLEAN NOT NULL DEFAULT 1,
+    redact_ip_addresses BOOLEAN NOT NULL DEFAULT 1,
+    pseudonymize_hostnames BOOLEAN NOT NULL DEFAULT 0  -- Replace hostnames with host-N in AI prompts
+);
+
+INSERT INTO redaction_settings (id, enabled, redact_emails, redact_ip_addresses, pseudonymize_hostnames)
+SELECT 1, 1, 1, 1, 0
+WHERE NOT EXISTS (SELECT 1 FROM redaction_settings WHERE id = 1);
+
+-- User-defined redaction rules. 'redact' rules replace matches with `replacement`, 'allow' rules
+-- protect matching text from all redaction. `source` / `field` limit a rule to one event source or payload key.
+CREATE TABLE IF NOT EXISTS redaction_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    pattern TEXT NOT NULL,
+    replacement TEXT NOT NULL DEFAULT '[REDACTED]',
+    kind TEXT NOT NULL DEFAULT 'redact',   -- 'redact' or 'allow'
+    source TEXT,                           -- NULL applies to every source
+    field TEXT,                            -- NULL applies to every payload key
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Private mode: recording is paused until this time (single row)
+CREATE TABLE IF NOT EXISTS privacy_settings (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    paused_until INTEGER                    -- UTC epoch milliseconds
+);
+
+INSERT INTO privacy_settings (id, paused_until)
+SELECT 1, NULL
+WHERE NOT EXISTS (SELECT 1 FROM privacy_settings WHERE id = 1);
+
+-- Events matching these are dropped at ingestion. kind: 'domain', 'url' (regex), 'path' (glob), 'command' (prefix)
+CREATE TABLE IF NOT EXISTS privacy_exclusions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    pattern TEXT NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (kind, pattern)
+);
+
+-- Number of dropped events. Only counts are kept, never content.
+CREATE TABLE IF NOT EXISTS dropped_events (
+    day TEXT NOT NULL,
+    source TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, source, reason)
+);
+
+-- Rows that readers skip because they can't be converted (bad timestamp, payload or encryption)
+CREATE TABLE IF NOT EXISTS quarantined_rows (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    table_name TEXT NOT NULL,               -- 'activity_logs' or 'ai_reports'
+    row_id INTEGER NOT NULL,
+    error TEXT NOT NULL,
+    detected_at INTEGER NOT NULL,           -- UTC epoch milliseconds
+    UNIQUE (table_name, row_id)
+);
+
+-- Number of events rejected for not matching their payload schema
+CREATE TABLE IF NOT EXISTS rejected_events (
+    day TEXT NOT NULL,
+    source TEXT NOT NULL,
+    count INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, source)
+);
@@ -0,0 +1,388 @@
+use std::fmt;
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Duration, FixedOffset, Local, LocalResult, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    sqlite::{SqliteArgumentValue, SqliteTypeInfo, SqliteValueRef},
+    Decode, Encode, Sqlite, SqlitePool, Type, TypeInfo, ValueRef,
+};
+
+use super::quarantine;
+
+const MIGRATION_BATCH_SIZE: i64 = 500;
+
+/// Value of the timezone setting that follows the operating system's zone, including DST changes
+pub const SYSTEM_TIMEZONE: &str = "system";
+
+/// A timestamp column as stored: UTC epoch milliseconds, or text for rows written before
+/// timestamps were normalized that could not be converted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoredTimestamp {
+    Millis(i64),
+    Text(String),
+}
+
+/// Parse the text forms timestamps were stored in: RFC 3339, or SQLite's `YYYY-MM-DD HH:MM:SS` in UTC
+fn parse_text(value: &str) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    DateTime::parse_from_rfc3339(value)
+        .map(|parsed| parsed.with_timezone(&Utc))
+        .or_else(|err| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S%.f")
+                .map(|naive| naive.and_utc())
+                .map_err(|_| format!("invalid timestamp '{value}': {err}"))
+        })
+}
+
+impl StoredTimestamp {
+    pub fn to_utc(&self) -> Result<DateTime<Utc>, String> {
+        match self {
+            StoredTimestamp::Millis(millis) => DateTime::from_timestamp_millis(*millis)
+                .ok_or_else(|| format!("invalid timestamp {millis}: out of range")),
+            StoredTimestamp::Text(text) => parse_text(text),
+        }
+    }
+}
+
+impl From<DateTime<Utc>> for StoredTimestamp {
+    fn from(value: DateTime<Utc>) -> Self {
+        StoredTimestamp::Millis(value.timestamp_millis())
+    }
+}
+
+impl fmt::Display for StoredTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_utc() {
+            Ok(value) => write!(f, "{}", value.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            Err(_) => match self {
+                StoredTimestamp::Millis(millis) => write!(f, "{millis}"),
+                StoredTimestamp::Text(text) => write!(f, "{text}"),
+            },
+        }
+    }
+}
+
+/// Serialized as RFC 3339 so exports and API responses stay readable, unconvertible text as it is
+impl Serialize for StoredTimestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for StoredTimestamp {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Millis(i64),
+            Text(String),
+        }
+
+        Ok(match Raw::deserialize(deserializer)? {
+            Raw::Millis(millis) => StoredTimestamp::Millis(millis),
+            Raw::Text(text) => match parse_text(&text) {
+                Ok(value) => value.into(),
+                Err(_) => StoredTimestamp::Text(text),
+            },
+        })
+    }
+}
+
+impl Type<Sqlite> for StoredTimestamp {
+    fn type_info() -> SqliteTypeInfo {
+        <i64 as Type<Sqlite>>::type_info()
+    }
+
+    fn compatible(ty: &SqliteTypeInfo) -> bool {
+        <i64 as Type<Sqlite>>::compatible(ty) || <String as Type<Sqlite>>::compatible(ty)
+    }
+}
+
+impl<'r> Decode<'r, Sqlite> for StoredTimestamp {
+    fn decode(value: SqliteValueRef<'r>) -> Result<Self, BoxDynError> {
+        if value.type_info().name() == "TEXT" {
+            Ok(StoredTimestamp::Text(<String as Decode<Sqlite>>::decode(value)?))
+        } else {
+            Ok(StoredTimestamp::Millis(<i64 as Decode<Sqlite>>::decode(value)?))
+        }
+    }
+}
+
+impl<'q> Encode<'q, Sqlite> for StoredTimestamp {
+    fn encode_by_ref(&self, args: &mut Vec<SqliteArgumentValue<'q>>) -> IsNull {
+        match self {
+            StoredTimestamp::Millis(millis) => <i64 as Encode<Sqlite>>::encode_by_ref(millis, args),
+            StoredTimestamp::Text(text) => <String as Encode<Sqlite>>::encode_by_ref(text, args),
+        }
+    }
+}
+
+/// Convert text timestamps in `table.column` to epoch milliseconds. Log and report rows that can't
+/// be parsed keep their text and are quarantined right away, since range queries on milliseconds
+/// no longer reach them. Returns how many rows were converted.
+pub async fn migrate_to_millis(pool: &SqlitePool, table: &str, column: &str) -> Result<u64, sqlx::Error> {
+    let select = format!(
+        "SELECT rowid, {column} FROM {table} WHERE typeof({column}) = 'text' AND rowid > ?1 ORDER BY rowid ASC LIMIT ?2"
+    );
+    let update = format!("UPDATE {table} SET {column} = ?1 WHERE rowid = ?2");
+    let quarantined = matches!(
+        (table, column),
+        (quarantine::TABLE_LOGS, "timestamp") | (quarantine::TABLE_REPORTS, "generated_at")
+    );
+    let mut converted = 0;
+    let mut last_id = 0;
+
+    loop {
+        let batch: Vec<(i64, String)> = sqlx::query_as(&select)
+            .bind(last_id)
+            .bind(MIGRATION_BATCH_SIZE)
+            .fetch_all(pool)
+            .await?;
+        let Some((id, _)) = batch.last() else {
+            return Ok(converted);
+        };
+        last_id = *id;
+
+        let mut tx = pool.begin().await?;
+        for (id, text) in batch {
+            match parse_text(&text) {
+                Ok(value) => {
+                    sqlx::query(&update)
+                        .bind(value.timestamp_millis())
+                        .bind(id)
+                        .execute(&mut *tx)
+                        .await?;
+                    converted += 1;
+                }
+                Err(err) if quarantined => quarantine::record(&mut *tx, table, id, &err).await?,
+                Err(_) => {}
+            }
+        }
+        tx.commit().await?;
+    }
+}
+
+/// Where calendar days begin: the system's time zone, an IANA zone like `Europe/Berlin` or a fixed UTC offset.
+/// Date-based queries and daily rollups cut days at local midnight in this zone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayZone {
+    System,
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+/// The first instant of the local day starting at `midnight` in `zone`. Where a DST change skips
+/// midnight, the day starts at the first valid local time.
+fn first_instant<Z: TimeZone>(zone: &Z, midnight: NaiveDateTime) -> DateTime<Utc> {
+    (0..=24)
+        .map(|half_hours| midnight + Duration::minutes(30 * half_hours))
+        .find_map(|local| match zone.from_local_datetime(&local) {
+            LocalResult::Single(start) | LocalResult::Ambiguous(start, _) => Some(start.with_timezone(&Utc)),
+            LocalResult::None => None,
+        })
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+impl DayZone {
+    /// Parse the stored setting: `system`, `UTC`, an IANA zone name or an offset like `-07:00`
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        let value = value.trim();
+        if value.eq_ignore_ascii_case(SYSTEM_TIMEZONE) || value.is_empty() {
+            return Ok(DayZone::System);
+        }
+        if value.eq_ignore_ascii_case("utc") || value == "Z" {
+            return Ok(DayZone::Fixed(FixedOffset::east_opt(0).expect("zero offset is valid")));
+        }
+
+        if let Ok(offset) = value.parse::<FixedOffset>() {
+            return Ok(DayZone::Fixed(offset));
+        }
+
+        value.parse::<Tz>().map(DayZone::Named).map_err(|_| {
+            anyhow!("invalid timezone '{value}', expected 'system', 'UTC', a zone like 'Europe/Berlin' or an offset like '-07:00'")
+        })
+    }
+
+    pub fn to_local(self, at: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            DayZone::System => at.with_timezone(&Local).fixed_offset(),
+            DayZone::Named(zone) => at.with_timezone(&zone).fixed_offset(),
+            DayZone::Fixed(offset) => at.with_timezone(&offset),
+        }
+    }
+
+    pub fn local_date(&self, at: DateTime<Utc>) -> NaiveDate {
+        self.to_local(at).date_naive()
+    }
+
+    /// The first instant of `day`. Where a DST change skips midnight, the day starts at the first valid local time.
+    pub fn start_of_day(&self, day: NaiveDate) -> DateTime<Utc> {
+        let midnight = day.and_time(Default::default());
+        match self {
+            DayZone::Fixed(offset) => (midnight - *offset).and_utc(),
+            DayZone::Named(zone) => first_instant(zone, midnight),
+            DayZone::System => first_instant(&Local, midnight),
+        }
+    }
+
+    /// Half-open UTC interval covering the local days `first` through `last`
+    pub fn day_range(&self, first: NaiveDate, last: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let after_last = last.succ_opt().unwrap_or(last);
+        (self.start_of_day(first), self.start_of_day(after_last))
+    }
+}
+
+impl fmt::Display for DayZone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DayZone::System => write!(f, "{SYSTEM_TIMEZONE}"),
+            DayZone::Named(zone) => write!(f, "{}", zone.name()),
+            DayZone::Fixed(offset) if offset.local_minus_utc() == 0 => write!(f, "UTC"),
+            DayZone::Fixed(offset) => write!(f, "{offset}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref ZONE: RwLock<DayZone> = RwLock::new(DayZone::System);
+}
+
+/// The zone days are currently cut in
+pub fn current() -> DayZone {
+    *ZONE.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+pub async fn reload(pool: &SqlitePool) -> Result<DayZone, Error> {
+    let stored: String = sqlx::query_scalar("SELECT timezone FROM time_settings WHERE id = 1")
+        .fetch_one(pool)
+        .await?;
+    let zone = DayZone::parse(&stored)?;
+
+    *ZONE.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = zone;
+    Ok(zone)
+}
+
+pub async fn set_timezone(pool: &SqlitePool, value: &str) -> Result<DayZone, Error> {
+    let zone = DayZone::parse(value)?;
+
+    sqlx::query("UPDATE time_settings SET timezone = ?1 WHERE id = 1")
+        .bind(zone.to_string())
+        .execute(pool)
+        .await?;
+
+    reload(pool).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        parse_text(value).unwrap()
+    }
+
+    #[test]
+    fn parses_settings_and_writes_them_back() {
+        for (value, stored) in [
+            ("system", "system"),
+            ("", "system"),
+            ("utc", "UTC"),
+            ("Z", "UTC"),
+            ("-07:00", "-07:00"),
+            ("Europe/Berlin", "Europe/Berlin"),
+            (" America/Sao_Paulo ", "America/Sao_Paulo"),
+        ] {
+            assert_eq!(DayZone::parse(value).unwrap().to_string(), stored, "{value}");
+        }
+        assert!(DayZone::parse("Mars/Olympus_Mons").is_err());
+        assert!(DayZone::parse("+25:00").is_err());
+    }
+
+    #[test]
+    fn day_ranges_follow_fixed_offsets() {
+        let zone = DayZone::parse("-07:00").unwrap();
+
+        assert_eq!(
+            zone.day_range(date("2024-05-01"), date("2024-05-02")),
+            (utc("2024-05-01T07:00:00Z"), utc("2024-05-03T07:00:00Z"))
+        );
+        assert_eq!(zone.local_date(utc("2024-05-02T06:59:59Z")), date("2024-05-01"));
+    }
+
+    #[test]
+    fn day_ranges_follow_daylight_saving_in_named_zones() {
+        let berlin = DayZone::parse("Europe/Berlin").unwrap();
+        let length = |day: &str| {
+            let (start, end) = berlin.day_range(date(day), date(day));
+            (end - start).num_hours()
+        };
+
+        assert_eq!(berlin.start_of_day(date("2024-01-15")), utc("2024-01-14T23:00:00Z"));
+        assert_eq!(berlin.start_of_day(date("2024-07-15")), utc("2024-07-14T22:00:00Z"));
+        assert_eq!(length("2024-03-31"), 23);
+        assert_eq!(length("2024-10-27"), 25);
+
+        // Clocks went from midnight straight to 01:00 here, so the day starts at 01:00 local time
+        let sao_paulo = DayZone::parse("America/Sao_Paulo").unwrap();
+        assert_eq!(sao_paulo.start_of_day(date("2018-11-04")), utc("2018-11-04T03:00:00Z"));
+    }
+
+    #[tokio::test]
+    async fn migrate_to_millis_converts_text_and_quarantines_unreadable_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+
+        for timestamp in ["2024-05-01T10:00:00+02:00", "2024-05-01 08:00:01", "yesterday"] {
+            sqlx::query("INSERT INTO activity_logs (source, payload, timestamp) VALUES ('terminal', '{}', ?1)")
+                .bind(timestamp)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("INSERT INTO journal_exports (day, content_hash, exported_at) VALUES ('2024-05-01', 'hash', ?1)")
+            .bind("2024-05-02T00:00:00Z")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(migrate_to_millis(&pool, quarantine::TABLE_LOGS, "timestamp").await.unwrap(), 2);
+        assert_eq!(migrate_to_millis(&pool, "journal_exports", "exported_at").await.unwrap(), 1);
+        // Already converted rows are left alone
+        assert_eq!(migrate_to_millis(&pool, quarantine::TABLE_LOGS, "timestamp").await.unwrap(), 0);
+
+        let timestamps: Vec<StoredTimestamp> = sqlx::query_scalar("SELECT timestamp FROM activity_logs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            timestamps,
+            [
+                utc("2024-05-01T08:00:00Z").into(),
+                utc("2024-05-01T08:00:01Z").into(),
+                StoredTimestamp::Text("yesterday".to_string()),
+            ]
+        );
+        let exported_at: StoredTimestamp = sqlx::query_scalar("SELECT exported_at FROM journal_exports")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(exported_at, utc("2024-05-02T00:00:00Z").into());
+
+        let quarantined: Vec<(String, i64)> = sqlx::query_as("SELECT table_name, row_id FROM quarantined_rows")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(quarantined, [(quarantine::TABLE_LOGS.to_string(), 3)]);
+    }
+}
@@ -0,0 +1,425 @@
+use std::{
+    env,
+    fs::{File, TryLockError},
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{Context, Error};
+use chrono::Utc;
+use sqlx::SqlitePool;
+use tokio::{net::TcpListener, sync::watch};
+
+use crate::{
+    ai::{self, client::AiClient},
+    db::{
+        self, crypto,
+        models::{ActivityLogRow, AiReport},
+        quarantine::{self, LOG_COLUMNS},
+    },
+    events::{ChronicleEvent, EventBus},
+    export, git, privacy, retention, sanitizer,
+    secrets::{self, ApiToken, SecretStore},
+    server::{
+        self,
+        endpoint::{self, BindConfig, Discovery, ServerHealth},
+    },
+    DB_FILENAME,
+};
+
+/// Must match `identifier` in tauri.conf.json so the CLI and the daemon find the app's database
+pub const APP_IDENTIFIER: &str = "com.devchronicle.app";
+
+/// Held by the running app or daemon, so only one engine summarizes and serves a data directory
+const LOCK_FILENAME: &str = "engine.lock";
+
+/// How often new logs are summarized, along with the git scan and retention
+const SUMMARY_INTERVAL_MINUTES: i64 = 10;
+
+/// How long open connections, such as event streams, may delay shutdown
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Same location Tauri resolves for `app_data_dir()`
+pub fn default_data_dir() -> Option<PathBuf> {
+    let base = if cfg!(target_os = "windows") {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+
+    base.map(|base| base.join(APP_IDENTIFIER))
+}
+
+/// Take the data directory's lock file, failing when another app or daemon holds it.
+/// The lock is released when the returned file is closed, including when the process dies.
+pub fn lock_data_dir(data_dir: &Path) -> Result<File, Error> {
+    let path = data_dir.join(LOCK_FILENAME);
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(anyhow::anyhow!(
+            "another DevChronicle app or daemon is already using {}",
+            data_dir.display()
+        )),
+        Err(TryLockError::Error(err)) => Err(Error::from(err).context(format!("failed to lock {}", path.display()))),
+    }
+}
+
+/// Whether an app or daemon is running on `data_dir`, i.e. holds its lock file
+pub fn data_dir_in_use(data_dir: &Path) -> bool {
+    // Opened for reading only, so checking never creates the file
+    File::open(data_dir.join(LOCK_FILENAME))
+        .is_ok_and(|file| matches!(file.try_lock_shared(), Err(TryLockError::WouldBlock)))
+}
+
+/// Storage, secrets, the ingestion server and the summary scheduler, shared by the desktop app
+/// and the headless daemon. Clones share the same pool and state.
+#[derive(Clone)]
+pub struct Engine {
+    pub data_dir: PathBuf,
+    pub pool: SqlitePool,
+    pub secret_store: SecretStore,
+    pub api_token: ApiToken,
+    pub ai_client: AiClient,
+    pub events: EventBus,
+    pub health: ServerHealth,
+    /// Released when the last clone is dropped
+    _lock: Arc<File>,
+}
+
+impl Engine {
+    /// Open the database in `data_dir`, migrating it if needed, and load keys and settings.
+    /// Fails when another app or daemon already runs on `data_dir`.
+    pub async fn open(data_dir: &Path) -> Result<Self, Error> {
+        std::fs::create_dir_all(data_dir)
+            .with_context(|| format!("failed to create {}", data_dir.display()))?;
+        let lock = lock_data_dir(data_dir)?;
+
+        let db_path = data_dir.join(DB_FILENAME);
+        let pool = db::connect_pool(&format!("sqlite://{}", db_path.to_string_lossy())).await?;
+        db::init_db(&pool).await?;
+
+        let passphrase = env::var(secrets::PASSPHRASE_ENV).ok();
+        let secret_store = SecretStore::load_or_create(data_dir, passphrase.as_deref())?;
+
+        match secrets::migrate_plaintext_api_key(&pool, &secret_store).await {
+            Ok(true) => println!("🔐 Encrypted previously stored plain-text API key"),
+            Ok(false) => {}
+            Err(err) => eprintln!("⚠️  Failed to migrate stored API key: {err}"),
+        }
+
+        let api_token = ApiToken::load_or_create(data_dir)?;
+
+        crypto::install(secret_store.clone(), crypto::load_enabled_flag(&pool).await?);
+
+        if let Err(err) = sanitizer::rules::reload(&pool).await {
+            eprintln!("⚠️  Failed to load redaction rules: {err}");
+        }
+
+        if let Err(err) = privacy::reload(&pool).await {
+            eprintln!("⚠️  Failed to load privacy settings: {err}");
+        }
+
+        if let Err(err) = db::time::reload(&pool).await {
+            eprintln!("⚠️  Failed to load time zone setting: {err}");
+        }
+
+        // Payloads may be encrypted, so this runs once the key is installed
+        let pool_for_backfill = pool.clone();
+        tokio::spawn(async move {
+            match crypto::seal_leftovers(&pool_for_backfill).await {
+                Ok(0) => {}
+                Ok(sealed) => println!("🔐 Encrypted {sealed} values stored in plain text"),
+                Err(err) => eprintln!("⚠️  Failed to encrypt plain-text values: {err}"),
+            }
+            match server::handlers::backfill_fields(&pool_for_backfill).await {
+                Ok(0) => {}
+                Ok(updated) => println!("🗂️  Extracted project, exit code and event id for {updated} older logs"),
+                Err(err) => eprintln!("⚠️  Failed to backfill log fields: {err}"),
+            }
+        });
+
+        let events = EventBus::new();
+        Ok(Self {
+            data_dir: data_dir.to_path_buf(),
+            pool,
+            ai_client: AiClient::new(secret_store.clone()),
+            secret_store,
+            api_token,
+            health: ServerHealth::new(events.clone()),
+            events,
+            _lock: Arc::new(lock),
+        })
+    }
+
+    /// Bind the ingestion server where the settings and environment say, or to the next free
+    /// fallback port, and publish its address in the discovery file
+    pub async fn bind(&self) -> Result<TcpListener, Error> {
+        let config = db::get_server_settings(&self.pool)
+            .await
+            .map_err(Error::from)
+            .and_then(|settings| BindConfig::resolve(&settings))
+            .inspect_err(|err| self.health.failed(None, err))?;
+        let listener = endpoint::bind(&config)
+            .await
+            .inspect_err(|err| self.health.failed(Some(config.port), err))?;
+
+        if !config.host.is_loopback() {
+            eprintln!("⚠️  Listening on {}: events from other machines need the API token", config.host);
+        }
+
+        let discovery = Discovery::new(listener.local_addr()?);
+        if let Err(err) = discovery.write(&self.data_dir) {
+            eprintln!("⚠️  Failed to write the discovery file: {err:#}");
+        }
+        if discovery.address.port() != config.port {
+            eprintln!("⚠️  Port {} is in use, using {} instead", config.port, discovery.address.port());
+        }
+        println!("✅ Ingestion server listening on {}", discovery.url);
+
+        self.health.running(config.port, &discovery);
+        Ok(listener)
+    }
+
+    /// Serve ingestion and the API on `listener`, if there is one, and run the scheduler until
+    /// `shutdown` completes. Then the scheduler finishes the run in progress and the server the
+    /// requests in flight.
+    pub async fn run(self, listener: Option<TcpListener>, shutdown: impl Future<Output = ()>) -> Result<(), Error> {
+        let (stop, stopped) = watch::channel(false);
+        let mut server = listener.map(|listener| {
+            let router = server::build_router(self.pool.clone(), self.api_token.clone(), self.events.clone());
+            let mut server_stopped = stopped.clone();
+            tokio::spawn(async move {
+                axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+                    .with_graceful_shutdown(async move {
+                        let _ = server_stopped.wait_for(|stop| *stop).await;
+                    })
+                    .await
+            })
+        });
+        let scheduler = tokio::spawn(self.clone().run_scheduler(stopped));
+
+        let server_exited = async {
+            match server.as_mut() {
+                Some(server) => server.await,
+                None => std::future::pending().await,
+            }
+        };
+        let server_result = tokio::select! {
+            _ = shutdown => None,
+            result = server_exited => Some(result),
+        };
+        self.events.close();
+        let _ = stop.send(true);
+
+        scheduler.await?;
+        let server_result = match (server_result, server) {
+            (Some(result), _) => Some(result),
+            (None, Some(mut server)) => match tokio::time::timeout(SHUTDOWN_GRACE, &mut server).await {
+                Ok(result) => Some(result),
+                Err(_) => {
+                    server.abort();
+                    Some(Ok(Ok(())))
+                }
+            },
+            (None, None) => None,
+        };
+        self.pool.close().await;
+
+        let Some(server_result) = server_result else {
+            return Ok(());
+        };
+        Discovery::remove(&self.data_dir);
+        match server_result?.context("ingestion server failed") {
+            Ok(()) => {
+                self.health.stopped();
+                Ok(())
+            }
+            Err(err) => {
+                self.health.failed(None, &err);
+                Err(err)
+            }
+        }
+    }
+
+    async fn run_scheduler(self, mut stopped: watch::Receiver<bool>) {
+        let minutes = SUMMARY_INTERVAL_MINUTES as u64;
+        let mut interval_timer = tokio::time::interval(Duration::from_secs(minutes * 60));
+
+        loop {
+            tokio::select! {
+                _ = interval_timer.tick() => {}
+                _ = stopped.wait_for(|stop| *stop) => break,
+            }
+
+            self.run_scheduled_tasks().await;
+        }
+    }
+
+    async fn run_scheduled_tasks(&self) {
+        let pool = &self.pool;
+
+        // Pull in new commits first so they land in this summary window
+        if let Err(e) = git::scan_repositories(pool).await {
+            eprintln!("⚠️  Git scan error: {}", e);
+        }
+
+        let generated = match auto_process_logs(pool, &self.ai_client, &self.events).await {
+            Ok(summary) => {
+                if !summary.is_empty() {
+                    println!("✅ Auto-generated summary at {}", Utc::now());
+                }
+                !summary.is_empty()
+            }
+            Err(e) => {
+                eprintln!("⚠️  Auto-processing error: {}", e);
+                self.events.publish(ChronicleEvent::SummaryFailed {
+                    error: e.to_string(),
+                    at: Utc::now(),
+                });
+                false
+            }
+        };
+
+        if generated {
+            if let Err(e) = export::journal::run_auto_export(pool).await {
+                eprintln!("⚠️  Journal auto-export error: {}", e);
+            }
+        }
+
+        match retention::enforce(pool, Utc::now()).await {
+            Ok(report) if report.logs_deleted + report.reports_deleted + report.payloads_stripped > 0 => {
+                println!(
+                    "🧹 Retention: deleted {} logs and {} reports, stripped {} payloads",
+                    report.logs_deleted, report.reports_deleted, report.payloads_stripped
+                );
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("⚠️  Retention error: {}", e),
+        }
+    }
+}
+
+/// Summarize logs of the last interval that haven't been summarized yet. Returns the summary,
+/// empty when there was nothing to summarize.
+async fn auto_process_logs(pool: &SqlitePool, ai_client: &AiClient, events: &EventBus) -> Result<String, Error> {
+    let end = Utc::now();
+    let start = end - chrono::Duration::minutes(SUMMARY_INTERVAL_MINUTES);
+
+    // Fetch unprocessed logs with all normalized fields
+    let rows = sqlx::query_as::<_, ActivityLogRow>(&format!(
+        "SELECT {LOG_COLUMNS} FROM activity_logs \
+         WHERE is_processed = 0 AND timestamp >= ?1 AND timestamp <= ?2 \
+         ORDER BY timestamp ASC"
+    ))
+    .bind(start.timestamp_millis())
+    .bind(end.timestamp_millis())
+    .fetch_all(pool)
+    .await?;
+
+    if rows.is_empty() {
+        return Ok(String::new());
+    }
+
+    let logs = quarantine::load_logs(pool, rows).await;
+    if logs.is_empty() {
+        return Ok(String::new());
+    }
+
+    // Collect log IDs and metadata before generating summary
+    let log_ids: Vec<i64> = logs.iter().map(|l| l.id).collect();
+    let sources_set: std::collections::HashSet<String> = logs.iter().map(|l| l.source.clone()).collect();
+    let sources_vec: Vec<String> = sources_set.into_iter().collect();
+
+    let ai::GeneratedSummary { summary, request_id } = ai::generate_summary(pool, ai_client, logs).await?;
+
+    // Save the summary with metadata
+    let log_ids_json = serde_json::to_string(&log_ids).unwrap_or_default();
+    let sources_str = sources_vec.join(",");
+
+    let writes = crypto::write_access().await;
+    let stored_summary = crypto::seal(&summary)?;
+
+    let report = sqlx::query(
+        "INSERT INTO ai_reports (summary, generated_at, log_ids, log_count, sources, time_range_start, time_range_end) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
+    )
+    .bind(&stored_summary)
+    .bind(end.timestamp_millis())
+    .bind(&log_ids_json)
+    .bind(log_ids.len() as i64)
+    .bind(&sources_str)
+    .bind(start.timestamp_millis())
+    .bind(end.timestamp_millis())
+    .execute(pool)
+    .await?;
+    drop(writes);
+
+    ai::audit::link_report(pool, request_id, report.last_insert_rowid()).await?;
+
+    events.publish(ChronicleEvent::ReportCreated {
+        report: AiReport {
+            id: report.last_insert_rowid(),
+            summary: summary.clone(),
+            generated_at: end,
+            log_count: Some(log_ids.len() as i64),
+            sources: Some(sources_str),
+            session_id: None,
+        },
+    });
+
+    // Mark logs as processed
+    sqlx::query("UPDATE activity_logs SET is_processed = 1 WHERE timestamp >= ?1 AND timestamp <= ?2")
+        .bind(start.timestamp_millis())
+        .bind(end.timestamp_millis())
+        .execute(pool)
+        .await?;
+
+    Ok(summary)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_dir_lock_is_exclusive_until_released() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let lock = lock_data_dir(dir.path()).unwrap();
+        let err = lock_data_dir(dir.path()).unwrap_err();
+        assert!(err.to_string().contains("already using"), "{err}");
+
+        drop(lock);
+        lock_data_dir(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn data_dir_is_in_use_while_locked() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!data_dir_in_use(dir.path()));
+        assert!(!dir.path().join(LOCK_FILENAME).exists());
+
+        let lock = lock_data_dir(dir.path()).unwrap();
+        assert!(data_dir_in_use(dir.path()));
+
+        // A stale lock file left by a crash doesn't count
+        drop(lock);
+        assert!(!data_dir_in_use(dir.path()));
+        lock_data_dir(dir.path()).unwrap();
+    }
+}
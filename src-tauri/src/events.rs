@@ -0,0 +1,79 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::{broadcast, watch};
+use utoipa::ToSchema;
+
+use crate::{
+    db::models::{ActivityLog, AiReport},
+    server::endpoint::ServerStatus,
+};
+
+/// Events buffered per subscriber. One that falls further behind misses the oldest.
+const CAPACITY: usize = 256;
+
+/// Something the dashboard and connected tools may want to show right away
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChronicleEvent {
+    /// An ingested event was stored, with its payload as redacted
+    LogCreated { log: ActivityLog },
+    ReportCreated { report: AiReport },
+    /// Generating a summary failed, e.g. because the AI provider was unreachable
+    SummaryFailed { error: String, at: DateTime<Utc> },
+    /// The ingestion server started, moved to a fallback port, failed or stopped
+    ServerStatus { status: ServerStatus },
+}
+
+impl ChronicleEvent {
+    /// Name of the Tauri event and the SSE event type
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChronicleEvent::LogCreated { .. } => "log_created",
+            ChronicleEvent::ReportCreated { .. } => "report_created",
+            ChronicleEvent::SummaryFailed { .. } => "summary_failed",
+            ChronicleEvent::ServerStatus { .. } => "server_status",
+        }
+    }
+}
+
+/// Fan-out of [`ChronicleEvent`]s to the UI and API subscribers. Clones publish to the same subscribers.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChronicleEvent>,
+    closed: watch::Sender<bool>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self {
+            sender,
+            closed: watch::Sender::new(false),
+        }
+    }
+
+    /// Send to everyone subscribed right now. Nobody listening is not an error.
+    pub fn publish(&self, event: ChronicleEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ChronicleEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Tell long-lived subscribers like SSE streams to finish, e.g. on shutdown
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    /// Resolves once [`EventBus::close`] was called
+    pub async fn closed(&self) {
+        let _ = self.closed.subscribe().wait_for(|closed| *closed).await;
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+use anyhow::{Context, Error};
+use chrono::{NaiveDate, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use super::{demote_headings, detect_projects, fetch_logs_between};
+use crate::db::{
+    get_journal_settings,
+    models::{AiReport, AiReportRow},
+    quarantine::{self, REPORT_COLUMNS},
+    time::{self, DayZone, StoredTimestamp},
+};
+
+#[derive(Debug, Default, Serialize)]
+pub struct JournalExportResult {
+    pub written: usize,
+    pub unchanged: usize,
+}
+
+#[derive(sqlx::FromRow)]
+struct JournalExportRow {
+    day: String,
+    content_hash: String,
+    fingerprint: Option<String>,
+}
+
+/// What a day's file was rendered from. Any new or deleted log or report changes it.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct DayFingerprint {
+    log_count: usize,
+    max_log_id: i64,
+    report_count: usize,
+    max_report_id: i64,
+}
+
+impl DayFingerprint {
+    fn to_key(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.log_count, self.max_log_id, self.report_count, self.max_report_id
+        )
+    }
+}
+
+fn yaml_list(items: &[String]) -> String {
+    if items.is_empty() {
+        return " []".to_string();
+    }
+
+    // JSON strings are valid YAML scalars and take care of quoting
+    items
+        .iter()
+        .map(|item| format!("\n  - {}", serde_json::to_string(item).unwrap_or_default()))
+        .collect()
+}
+
+async fn render_day(
+    pool: &SqlitePool,
+    zone: DayZone,
+    day: NaiveDate,
+    reports: &[AiReport],
+    previous: Option<NaiveDate>,
+    next: Option<NaiveDate>,
+) -> Result<String, Error> {
+    let (start, end) = zone.day_range(day, day);
+    let logs = fetch_logs_between(pool, start, end).await?;
+
+    let projects = detect_projects(&logs);
+    let sources: Vec<String> = logs
+        .iter()
+        .map(|log| log.source.clone())
+        .chain(
+            reports
+                .iter()
+                .filter_map(|report| report.sources.as_deref())
+                .flat_map(|sources| sources.split(','))
+                .filter(|source| !source.is_empty())
+                .map(str::to_string),
+        )
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut markdown = format!(
+        "---\ndate: {}\nprojects:{}\nsources:{}\nlog_count: {}\n---\n\n# {}\n\n",
+        day,
+        yaml_list(&projects),
+        yaml_list(&sources),
+        logs.len(),
+        day
+    );
+
+    let navigation: Vec<String> = [
+        previous.map(|previous| format!("← [[{}]]", previous)),
+        next.map(|next| format!("[[{}]] →", next)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
+    if !navigation.is_empty() {
+        markdown.push_str(&navigation.join(" | "));
+        markdown.push_str("\n\n");
+    }
+
+    if reports.is_empty() {
+        markdown.push_str("_No reports for this day yet._\n\n");
+    }
+
+    for report in reports {
+        markdown.push_str(&format!("## {}\n\n", zone.to_local(report.generated_at).format("%H:%M")));
+        markdown.push_str(&demote_headings(report.summary.trim(), 1));
+        markdown.push_str("\n\n");
+    }
+
+    Ok(markdown)
+}
+
+async fn load_reports_by_day(pool: &SqlitePool, zone: DayZone) -> Result<BTreeMap<NaiveDate, Vec<AiReport>>, Error> {
+    let rows = sqlx::query_as::<_, AiReportRow>(&format!(
+        "SELECT {REPORT_COLUMNS} FROM ai_reports ORDER BY generated_at ASC"
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let mut by_day: BTreeMap<NaiveDate, Vec<AiReport>> = BTreeMap::new();
+    for report in quarantine::load_reports(pool, rows).await {
+        by_day.entry(zone.local_date(report.generated_at)).or_default().push(report);
+    }
+
+    Ok(by_day)
+}
+
+/// Fingerprints of every local day with logs or reports
+async fn load_day_fingerprints(
+    pool: &SqlitePool,
+    zone: DayZone,
+    reports_by_day: &BTreeMap<NaiveDate, Vec<AiReport>>,
+) -> Result<BTreeMap<NaiveDate, DayFingerprint>, Error> {
+    let logs: Vec<(i64, StoredTimestamp)> = sqlx::query_as("SELECT id, timestamp FROM activity_logs")
+        .fetch_all(pool)
+        .await?;
+
+    let mut fingerprints: BTreeMap<NaiveDate, DayFingerprint> = BTreeMap::new();
+    for (id, timestamp) in logs {
+        let Ok(timestamp) = timestamp.to_utc() else {
+            continue;
+        };
+        let fingerprint = fingerprints.entry(zone.local_date(timestamp)).or_default();
+        fingerprint.log_count += 1;
+        fingerprint.max_log_id = fingerprint.max_log_id.max(id);
+    }
+
+    for (day, reports) in reports_by_day {
+        let fingerprint = fingerprints.entry(*day).or_default();
+        fingerprint.report_count = reports.len();
+        fingerprint.max_report_id = reports.iter().map(|report| report.id).max().unwrap_or_default();
+    }
+
+    Ok(fingerprints)
+}
+
+/// Write one Markdown file per day with logs or reports into `vault_dir`.
+///
+/// Unless `full` is set, only days that were never exported, whose logs or reports changed
+/// since their last export, or gained a new neighbouring day are rendered, and files are only
+/// rewritten when their content actually changed.
+pub async fn export_journal(pool: &SqlitePool, vault_dir: &Path, full: bool) -> Result<JournalExportResult, Error> {
+    tokio::fs::create_dir_all(vault_dir)
+        .await
+        .with_context(|| format!("failed to create {}", vault_dir.display()))?;
+
+    let zone = time::current();
+    let reports_by_day = load_reports_by_day(pool, zone).await?;
+    let fingerprints = load_day_fingerprints(pool, zone, &reports_by_day).await?;
+    let days: Vec<NaiveDate> = fingerprints.keys().copied().collect();
+
+    let exported: HashMap<String, JournalExportRow> =
+        sqlx::query_as::<_, JournalExportRow>("SELECT day, content_hash, fingerprint FROM journal_exports")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| (row.day.clone(), row))
+            .collect();
+
+    let mut candidates: BTreeSet<usize> = BTreeSet::new();
+    for (index, day) in days.iter().enumerate() {
+        let needs_export = match exported.get(&day.to_string()) {
+            None => {
+                // A new day changes the wiki-links of the days around it
+                candidates.extend(index.checked_sub(1));
+                if index + 1 < days.len() {
+                    candidates.insert(index + 1);
+                }
+                true
+            }
+            Some(row) => row.fingerprint.as_deref() != Some(fingerprints[day].to_key().as_str()),
+        };
+
+        if full || needs_export {
+            candidates.insert(index);
+        }
+    }
+
+    let mut result = JournalExportResult::default();
+    let now = Utc::now().timestamp_millis();
+
+    for index in candidates {
+        let day = days[index];
+        let previous = index.checked_sub(1).map(|i| days[i]);
+        let next = days.get(index + 1).copied();
+
+        let reports = reports_by_day.get(&day).map(Vec::as_slice).unwrap_or_default();
+        let markdown = render_day(pool, zone, day, reports, previous, next).await?;
+        let content_hash = format!("{:x}", Sha256::digest(markdown.as_bytes()));
+        let path = vault_dir.join(format!("{}.md", day));
+
+        let unchanged = exported
+            .get(&day.to_string())
+            .is_some_and(|row| row.content_hash == content_hash);
+        if unchanged && !full && path.exists() {
+            result.unchanged += 1;
+        } else {
+            tokio::fs::write(&path, markdown)
+                .await
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            result.written += 1;
+        }
+
+        sqlx::query(
+            "INSERT INTO journal_exports (day, content_hash, exported_at, fingerprint) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(day) DO UPDATE SET content_hash = excluded.content_hash, exported_at = excluded.exported_at,
+             fingerprint = excluded.fingerprint",
+        )
+        .bind(day.to_string())
+        .bind(&content_hash)
+        .bind(now)
+        .bind(fingerprints[&day].to_key())
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(result)
+}
+
+/// Incremental export used by the scheduler. Does nothing unless auto-export is enabled and a vault is configured.
+pub async fn run_auto_export(pool: &SqlitePool) -> Result<Option<JournalExportResult>, Error> {
+    let settings = get_journal_settings(pool).await?;
+
+    match settings.vault_dir.as_deref().filter(|dir| !dir.is_empty()) {
+        Some(vault_dir) if settings.auto_export => {
+            export_journal(pool, Path::new(vault_dir), false).await.map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration};
+
+    use super::*;
+    use crate::db;
+
+    async fn insert_log(pool: &SqlitePool, at: DateTime<Utc>) {
+        sqlx::query("INSERT INTO activity_logs (source, payload, timestamp) VALUES ('terminal', '{}', ?1)")
+            .bind(at.timestamp_millis())
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn exports_days_without_reports_and_reexports_on_new_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let noon = time::current().day_range(day, day).0 + Duration::hours(12);
+        insert_log(&pool, noon).await;
+
+        let first = export_journal(&pool, &vault, false).await.unwrap();
+        assert_eq!((first.written, first.unchanged), (1, 0));
+        let markdown = std::fs::read_to_string(vault.join("2024-05-01.md")).unwrap();
+        assert!(markdown.contains("log_count: 1"));
+
+        // Nothing changed, so the day isn't even rendered
+        let second = export_journal(&pool, &vault, false).await.unwrap();
+        assert_eq!((second.written, second.unchanged), (0, 0));
+
+        insert_log(&pool, noon + Duration::minutes(5)).await;
+        let third = export_journal(&pool, &vault, false).await.unwrap();
+        assert_eq!((third.written, third.unchanged), (1, 0));
+        let markdown = std::fs::read_to_string(vault.join("2024-05-01.md")).unwrap();
+        assert!(markdown.contains("log_count: 2"));
+    }
+
+    #[tokio::test]
+    async fn unreadable_reports_are_quarantined_instead_of_failing_the_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let vault = dir.path().join("vault");
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let noon = time::current().day_range(day, day).0 + Duration::hours(12);
+        insert_log(&pool, noon).await;
+        sqlx::query("INSERT INTO ai_reports (summary, generated_at, log_count, sources) VALUES ('Summary', 'not a time', 1, '[]')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let result = export_journal(&pool, &vault, false).await.unwrap();
+        assert_eq!(result.written, 1);
+
+        let quarantined: Vec<String> = sqlx::query_scalar("SELECT table_name FROM quarantined_rows")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(quarantined, [quarantine::TABLE_REPORTS]);
+    }
+}
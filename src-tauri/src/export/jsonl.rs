@@ -0,0 +1,530 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Error};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+
+use crate::db::{self, crypto, get_ai_settings, get_journal_settings, models::JournalSettings, time::StoredTimestamp};
+
+pub const FORMAT_NAME: &str = "devchronicle-jsonl";
+/// Version 2 added `session` records
+pub const FORMAT_VERSION: u32 = 2;
+
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+/// One line of a chronicle backup. The first line is always a `header`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Header(Header),
+    Log(LogRecord),
+    Report(ReportRecord),
+    AiSettings(AiSettingsRecord),
+    JournalSettings(JournalSettings),
+    GitRepository(GitRepositoryRecord),
+    Session(SessionRecord),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Header {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+}
+
+/// A full `activity_logs` row. `payload` is kept as stored so a round trip is lossless, except that
+/// encrypted fields are written decrypted so the backup can be restored with any key. Timestamps are
+/// written as RFC 3339 and stored as epoch milliseconds again on import.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LogRecord {
+    /// Identity used to skip events that already exist on import, see [`db::event_id`]
+    #[serde(default)]
+    pub event_id: Option<String>,
+    pub id: i64,
+    pub source: String,
+    pub payload: String,
+    pub timestamp: StoredTimestamp,
+    pub is_processed: Option<bool>,
+    pub log_type: Option<String>,
+    pub session_id: Option<String>,
+    pub command: Option<String>,
+    pub exit_code: Option<i64>,
+    pub duration_sec: Option<f64>,
+    pub cwd: Option<String>,
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub domain: Option<String>,
+    pub time_on_page_sec: Option<i64>,
+    pub file_path: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub redactions: i64,
+    pub created_at: Option<String>,
+}
+
+impl LogRecord {
+    /// The stored event id, or the one computed the way ingestion does for older rows without one
+    pub fn resolved_event_id(&self) -> Result<String, Error> {
+        if let Some(event_id) = &self.event_id {
+            return Ok(event_id.clone());
+        }
+        let timestamp = self.timestamp.to_utc().map_err(|err| anyhow!(err))?;
+        Ok(db::event_id(&self.source, timestamp, &self.payload))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ReportRecord {
+    pub id: i64,
+    pub summary: String,
+    pub generated_at: StoredTimestamp,
+    pub log_ids: Option<String>,
+    pub log_count: Option<i64>,
+    pub sources: Option<String>,
+    pub time_range_start: Option<StoredTimestamp>,
+    pub time_range_end: Option<StoredTimestamp>,
+    pub session_id: Option<String>,
+    pub model_used: Option<String>,
+    pub temperature: Option<f64>,
+}
+
+/// AI settings without the API key, which never leaves the machine
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AiSettingsRecord {
+    pub provider_url: String,
+    pub model_name: String,
+    pub temperature: Option<f32>,
+    pub batch_size: Option<i64>,
+    pub summary_frequency_min: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct GitRepositoryRecord {
+    pub path: String,
+    pub added_at: StoredTimestamp,
+    pub last_scanned_at: Option<StoredTimestamp>,
+}
+
+/// A work session, derived from the `session_id` of its logs. Logs and reports keep their
+/// `session_id` on import, so these records are informational and not imported.
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+pub struct SessionRecord {
+    pub session_id: String,
+    pub started_at: StoredTimestamp,
+    pub ended_at: StoredTimestamp,
+    pub log_count: i64,
+    /// Comma separated, in alphabetical order
+    pub sources: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ExportSummary {
+    pub logs: usize,
+    pub reports: usize,
+    pub sessions: usize,
+    pub git_repositories: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    pub logs_imported: usize,
+    pub logs_skipped: usize,
+    pub reports_imported: usize,
+    pub reports_skipped: usize,
+    pub git_repositories_imported: usize,
+    pub settings_applied: bool,
+}
+
+async fn write_record<W: AsyncWriteExt + Unpin>(writer: &mut W, record: &Record) -> Result<(), Error> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    Ok(())
+}
+
+/// Write the whole chronicle to `path` as JSON Lines
+pub async fn export_chronicle(pool: &SqlitePool, path: &Path) -> Result<ExportSummary, Error> {
+    let file = tokio::fs::File::create(path)
+        .await
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    let mut summary = ExportSummary::default();
+
+    write_record(
+        &mut writer,
+        &Record::Header(Header {
+            format: FORMAT_NAME.to_string(),
+            version: FORMAT_VERSION,
+            exported_at: Utc::now(),
+        }),
+    )
+    .await?;
+
+    let ai_settings = get_ai_settings(pool).await?;
+    write_record(
+        &mut writer,
+        &Record::AiSettings(AiSettingsRecord {
+            provider_url: ai_settings.provider_url,
+            model_name: ai_settings.model_name,
+            temperature: ai_settings.temperature,
+            batch_size: ai_settings.batch_size,
+            summary_frequency_min: ai_settings.summary_frequency_min,
+        }),
+    )
+    .await?;
+
+    write_record(&mut writer, &Record::JournalSettings(get_journal_settings(pool).await?)).await?;
+
+    let repositories = sqlx::query_as::<_, GitRepositoryRecord>(
+        "SELECT path, added_at, last_scanned_at FROM git_repositories ORDER BY id ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+    for repository in repositories {
+        write_record(&mut writer, &Record::GitRepository(repository)).await?;
+        summary.git_repositories += 1;
+    }
+
+    // Page through logs by id so large chronicles are never loaded at once
+    let mut last_id = 0;
+    loop {
+        let batch = sqlx::query_as::<_, LogRecord>(
+            "SELECT event_id, id, source, payload, timestamp, is_processed, log_type, session_id, command, exit_code, \
+             duration_sec, cwd, url, title, domain, time_on_page_sec, file_path, language, redactions, created_at \
+             FROM activity_logs WHERE id > ?1 ORDER BY id ASC LIMIT ?2",
+        )
+        .bind(last_id)
+        .bind(EXPORT_BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        last_id = last.id;
+
+        for mut log in batch {
+            log.payload = crypto::open(&log.payload)?;
+            log.command = crypto::open_opt(log.command)?;
+            log.cwd = crypto::open_opt(log.cwd)?;
+            log.url = crypto::open_opt(log.url)?;
+            log.title = crypto::open_opt(log.title)?;
+            log.domain = crypto::open_opt(log.domain)?;
+            log.file_path = crypto::open_opt(log.file_path)?;
+            log.event_id = Some(log.resolved_event_id()?);
+            write_record(&mut writer, &Record::Log(log)).await?;
+            summary.logs += 1;
+        }
+    }
+
+    let sessions = sqlx::query_as::<_, SessionRecord>(
+        "SELECT session_id, MIN(timestamp) AS started_at, MAX(timestamp) AS ended_at, COUNT(*) AS log_count, \
+         (SELECT GROUP_CONCAT(source, ',') FROM (SELECT DISTINCT source FROM activity_logs inner_logs \
+          WHERE inner_logs.session_id = activity_logs.session_id ORDER BY source)) AS sources \
+         FROM activity_logs WHERE session_id IS NOT NULL GROUP BY session_id ORDER BY started_at ASC, session_id ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+    for session in sessions {
+        write_record(&mut writer, &Record::Session(session)).await?;
+        summary.sessions += 1;
+    }
+
+    let reports = sqlx::query_as::<_, ReportRecord>(
+        "SELECT id, summary, generated_at, log_ids, log_count, sources, time_range_start, time_range_end, \
+         session_id, model_used, temperature FROM ai_reports ORDER BY id ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+    for mut report in reports {
+        report.summary = crypto::open(&report.summary)?;
+        write_record(&mut writer, &Record::Report(report)).await?;
+        summary.reports += 1;
+    }
+
+    writer.flush().await?;
+    Ok(summary)
+}
+
+async fn import_log(
+    tx: &mut Transaction<'_, Sqlite>,
+    log: &LogRecord,
+) -> Result<(i64, bool), Error> {
+    // Matched by event id, which survives retention stripping the payload
+    let event_id = log.resolved_event_id()?;
+    let existing: Option<i64> = sqlx::query_scalar("SELECT id FROM activity_logs WHERE event_id = ?1 LIMIT 1")
+        .bind(&event_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    if let Some(id) = existing {
+        return Ok((id, false));
+    }
+
+    // Rows from before event ids are backfilled at startup. Until then, compare payloads after decryption.
+    let candidates: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, payload FROM activity_logs WHERE event_id IS NULL AND source = ?1 AND timestamp = ?2",
+    )
+    .bind(&log.source)
+    .bind(&log.timestamp)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    for (id, payload) in candidates {
+        if crypto::open(&payload)? == log.payload {
+            return Ok((id, false));
+        }
+    }
+
+    let result = sqlx::query(
+        "INSERT INTO activity_logs (source, payload, timestamp, is_processed, log_type, session_id, command, exit_code, \
+         duration_sec, cwd, url, title, domain, time_on_page_sec, file_path, language, redactions, created_at, event_id) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, COALESCE(?18, CURRENT_TIMESTAMP), ?19)",
+    )
+    .bind(&log.source)
+    .bind(crypto::seal(&log.payload)?)
+    .bind(&log.timestamp)
+    .bind(log.is_processed.unwrap_or(false))
+    .bind(&log.log_type)
+    .bind(&log.session_id)
+    .bind(crypto::seal_opt(log.command.as_deref())?)
+    .bind(log.exit_code)
+    .bind(log.duration_sec)
+    .bind(crypto::seal_opt(log.cwd.as_deref())?)
+    .bind(crypto::seal_opt(log.url.as_deref())?)
+    .bind(crypto::seal_opt(log.title.as_deref())?)
+    .bind(crypto::seal_opt(log.domain.as_deref())?)
+    .bind(log.time_on_page_sec)
+    .bind(crypto::seal_opt(log.file_path.as_deref())?)
+    .bind(&log.language)
+    .bind(log.redactions)
+    .bind(&log.created_at)
+    .bind(&event_id)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok((result.last_insert_rowid(), true))
+}
+
+/// Rewrite a report's `log_ids` JSON array from the exporting machine's ids to ours
+fn remap_log_ids(log_ids: Option<&str>, id_map: &HashMap<i64, i64>) -> Option<String> {
+    let ids: Vec<i64> = serde_json::from_str(log_ids?).ok()?;
+    let remapped: Vec<i64> = ids.iter().filter_map(|id| id_map.get(id).copied()).collect();
+    serde_json::to_string(&remapped).ok()
+}
+
+async fn import_report(
+    tx: &mut Transaction<'_, Sqlite>,
+    report: &ReportRecord,
+    id_map: &HashMap<i64, i64>,
+) -> Result<bool, Error> {
+    let summaries: Vec<String> = sqlx::query_scalar("SELECT summary FROM ai_reports WHERE generated_at = ?1")
+        .bind(&report.generated_at)
+        .fetch_all(&mut **tx)
+        .await?;
+
+    for summary in summaries {
+        if crypto::open(&summary)? == report.summary {
+            return Ok(false);
+        }
+    }
+
+    sqlx::query(
+        "INSERT INTO ai_reports (summary, generated_at, log_ids, log_count, sources, time_range_start, time_range_end, \
+         session_id, model_used, temperature) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+    )
+    .bind(crypto::seal(&report.summary)?)
+    .bind(&report.generated_at)
+    .bind(remap_log_ids(report.log_ids.as_deref(), id_map))
+    .bind(report.log_count)
+    .bind(&report.sources)
+    .bind(&report.time_range_start)
+    .bind(&report.time_range_end)
+    .bind(&report.session_id)
+    .bind(&report.model_used)
+    .bind(report.temperature)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(true)
+}
+
+/// Merge a JSON Lines backup into the database. Events (matched by `event_id`) and reports that already exist are skipped,
+/// so importing the same file twice is harmless. Settings are only applied when `apply_settings` is set,
+/// and the stored API key is always kept.
+pub async fn import_chronicle(pool: &SqlitePool, path: &Path, apply_settings: bool) -> Result<ImportSummary, Error> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let mut lines = BufReader::new(file).lines();
+
+    let header_line = lines
+        .next_line()
+        .await?
+        .ok_or_else(|| anyhow!("{} is empty", path.display()))?;
+    match serde_json::from_str::<Record>(&header_line) {
+        Ok(Record::Header(header)) if header.format == FORMAT_NAME => {
+            if header.version > FORMAT_VERSION {
+                return Err(anyhow!(
+                    "backup format version {} is newer than supported version {}",
+                    header.version,
+                    FORMAT_VERSION
+                ));
+            }
+        }
+        _ => return Err(anyhow!("{} is not a DevChronicle backup", path.display())),
+    }
+
+    let _writes = crypto::write_access().await;
+    let mut tx = pool.begin().await?;
+    let mut summary = ImportSummary::default();
+    let mut id_map: HashMap<i64, i64> = HashMap::new();
+    let mut line_number = 1;
+
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record: Record = serde_json::from_str(&line)
+            .with_context(|| format!("invalid record on line {line_number}"))?;
+
+        match record {
+            Record::Header(_) => return Err(anyhow!("unexpected header on line {line_number}")),
+            Record::Log(log) => {
+                let (id, inserted) = import_log(&mut tx, &log).await?;
+                id_map.insert(log.id, id);
+                if inserted {
+                    summary.logs_imported += 1;
+                } else {
+                    summary.logs_skipped += 1;
+                }
+            }
+            Record::Report(report) => {
+                if import_report(&mut tx, &report, &id_map).await? {
+                    summary.reports_imported += 1;
+                } else {
+                    summary.reports_skipped += 1;
+                }
+            }
+            Record::GitRepository(repository) => {
+                let result = sqlx::query(
+                    "INSERT INTO git_repositories (path, added_at, last_scanned_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(path) DO NOTHING",
+                )
+                .bind(&repository.path)
+                .bind(&repository.added_at)
+                .bind(&repository.last_scanned_at)
+                .execute(&mut *tx)
+                .await?;
+                summary.git_repositories_imported += result.rows_affected() as usize;
+            }
+            Record::AiSettings(settings) if apply_settings => {
+                sqlx::query(
+                    "UPDATE ai_settings SET provider_url = ?1, model_name = ?2, temperature = ?3, \
+                     batch_size = ?4, summary_frequency_min = ?5 WHERE id = 1",
+                )
+                .bind(&settings.provider_url)
+                .bind(&settings.model_name)
+                .bind(settings.temperature)
+                .bind(settings.batch_size)
+                .bind(settings.summary_frequency_min)
+                .execute(&mut *tx)
+                .await?;
+                summary.settings_applied = true;
+            }
+            Record::JournalSettings(settings) if apply_settings => {
+                sqlx::query("UPDATE journal_settings SET vault_dir = ?1, auto_export = ?2 WHERE id = 1")
+                    .bind(&settings.vault_dir)
+                    .bind(settings.auto_export)
+                    .execute(&mut *tx)
+                    .await?;
+                summary.settings_applied = true;
+            }
+            Record::AiSettings(_) | Record::JournalSettings(_) | Record::Session(_) => {}
+        }
+    }
+
+    tx.commit().await?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A second database next to the one from [`db::test_pool`]
+    async fn open_db(dir: &Path, name: &str) -> SqlitePool {
+        let pool = db::connect_pool(&format!("sqlite://{}", dir.join(name).display()))
+            .await
+            .unwrap();
+        db::init_db(&pool).await.unwrap();
+        pool
+    }
+
+    async fn seed(pool: &SqlitePool) {
+        for (minute, source, payload) in [
+            (0, "terminal", r#"{"command":"cargo test","cwd":"/src/app"}"#),
+            (5, "browser", r#"{"url":"https://docs.rs/","title":"Docs.rs"}"#),
+            (9, "vscode", r#"{"file_path":"/src/app/main.rs"}"#),
+        ] {
+            sqlx::query(
+                "INSERT INTO activity_logs (source, payload, timestamp, session_id, created_at) \
+                 VALUES (?1, ?2, ?3, 'session-1', '2024-05-01 10:00:00')",
+            )
+            .bind(source)
+            .bind(payload)
+            .bind(1_714_557_600_000_i64 + minute * 60_000)
+            .execute(pool)
+            .await
+            .unwrap();
+        }
+        sqlx::query(
+            "INSERT INTO ai_reports (summary, generated_at, log_ids, log_count, sources, session_id) \
+             VALUES ('Ran the tests', 1714558200000, '[1,2,3]', 3, 'terminal,browser,vscode', 'session-1')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    /// The backup without its header, which carries the export time
+    async fn export_body(pool: &SqlitePool, path: &Path) -> Vec<String> {
+        export_chronicle(pool, path).await.unwrap();
+        let contents = std::fs::read_to_string(path).unwrap();
+        contents.lines().skip(1).map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn round_trip_is_lossless_and_reimport_is_idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, source) = db::test_pool(dir.path()).await;
+        seed(&source).await;
+
+        let first_path = dir.path().join("first.jsonl");
+        let first = export_body(&source, &first_path).await;
+        assert!(first.iter().any(|line| line.contains(r#""type":"session""#)));
+
+        let target = open_db(dir.path(), "target.db").await;
+        let imported = import_chronicle(&target, &first_path, true).await.unwrap();
+        assert_eq!((imported.logs_imported, imported.reports_imported), (3, 1));
+
+        let second = export_body(&target, &dir.path().join("second.jsonl")).await;
+        assert_eq!(first, second);
+
+        let again = import_chronicle(&target, &first_path, true).await.unwrap();
+        assert_eq!((again.logs_imported, again.logs_skipped), (0, 3));
+        assert_eq!((again.reports_imported, again.reports_skipped), (0, 1));
+
+        // Retention strips payloads, which must not make old events look new
+        sqlx::query("UPDATE activity_logs SET payload = '{}'")
+            .execute(&target)
+            .await
+            .unwrap();
+        let stripped = import_chronicle(&target, &first_path, true).await.unwrap();
+        assert_eq!((stripped.logs_imported, stripped.logs_skipped), (0, 3));
+    }
+}
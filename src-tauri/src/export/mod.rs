@@ -0,0 +1,152 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::SqlitePool;
+
+use crate::{
+    db::{
+        models::{ActivityLog, ActivityLogRow},
+        quarantine::{self, LOG_COLUMNS},
+        time,
+    },
+    sanitizer::{expand_home, normalize_home_path},
+};
+
+pub mod journal;
+pub mod jsonl;
+pub mod timesheet;
+
+/// Project roots reported directly by editor workspaces and git repositories
+pub fn known_project_roots(logs: &[ActivityLog]) -> Vec<String> {
+    let mut roots: Vec<String> = logs
+        .iter()
+        .filter_map(|log| {
+            log.payload
+                .get("workspace")
+                .or_else(|| log.payload.get("repository"))
+                .and_then(Value::as_str)
+                .map(|root| normalize_home_path(root.trim_end_matches('/')))
+        })
+        .collect();
+
+    // Longest first so nested projects win over their parents
+    roots.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    roots.dedup();
+    roots
+}
+
+pub fn project_name(root: &str) -> String {
+    Path::new(root)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| root.to_string())
+}
+
+/// Work out which project a log belongs to, if it carries any path information
+pub fn detect_project(log: &ActivityLog, roots: &[String]) -> Option<String> {
+    let path = log
+        .payload
+        .get("workspace")
+        .or_else(|| log.payload.get("repository"))
+        .or_else(|| log.payload.get("cwd"))
+        .and_then(Value::as_str)
+        .or(log.file_path.as_deref())?;
+    // Editor paths are stored with the home directory as `~`, repository paths are not
+    let path = normalize_home_path(path);
+
+    let root = roots
+        .iter()
+        .find(|root| path == root.as_str() || path.starts_with(&format!("{}/", root)));
+
+    Some(project_name(root.map(String::as_str).unwrap_or(&path)))
+}
+
+/// The git repository enclosing `path`, found by walking up to the nearest `.git`
+fn enclosing_repository(path: &str) -> Option<PathBuf> {
+    Path::new(&expand_home(path))
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Project of a single event, as stored in the `project` column. Workspaces and repositories name
+/// their project directly, otherwise the repository around the working directory or file is used.
+pub fn event_project(payload: &Value, file_path: Option<&str>) -> Option<String> {
+    let text = |key: &str| payload.get(key).and_then(Value::as_str).filter(|value| !value.is_empty());
+
+    if let Some(root) = text("workspace").or_else(|| text("repository")) {
+        return Some(project_name(root.trim_end_matches('/')));
+    }
+
+    let cwd = text("cwd");
+    match enclosing_repository(cwd.or(file_path)?) {
+        Some(root) => Some(project_name(&root.to_string_lossy())),
+        None => cwd.map(|cwd| project_name(cwd.trim_end_matches('/'))),
+    }
+}
+
+/// Distinct project names touched by `logs`, sorted
+pub fn detect_projects(logs: &[ActivityLog]) -> Vec<String> {
+    let roots = known_project_roots(logs);
+    logs.iter()
+        .filter_map(|log| detect_project(log, &roots))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Push every Markdown heading in `summary` down by `levels` so it nests under an enclosing section
+pub fn demote_headings(summary: &str, levels: usize) -> String {
+    let prefix = "#".repeat(levels);
+    summary
+        .lines()
+        .map(|line| {
+            if line.starts_with('#') {
+                format!("{}{}", prefix, line)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parse an inclusive `YYYY-MM-DD` date range into a half-open UTC interval, with days cut at
+/// midnight in the configured time zone
+pub fn parse_date_range(start_date: &str, end_date: &str) -> Result<(DateTime<Utc>, DateTime<Utc>), Error> {
+    let parse = |value: &str| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|err| anyhow!("invalid date format: {err}"))
+    };
+
+    let start_day = parse(start_date)?;
+    let end_day = parse(end_date)?;
+    if end_day < start_day {
+        return Err(anyhow!("end date must not be before start date"));
+    }
+
+    Ok(time::current().day_range(start_day, end_day))
+}
+
+pub async fn fetch_logs_between(
+    pool: &SqlitePool,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<ActivityLog>, Error> {
+    let rows = sqlx::query_as::<_, ActivityLogRow>(&format!(
+        "SELECT {LOG_COLUMNS} FROM activity_logs \
+         WHERE timestamp >= ?1 AND timestamp < ?2 \
+         ORDER BY timestamp ASC"
+    ))
+    .bind(start.timestamp_millis())
+    .bind(end.timestamp_millis())
+    .fetch_all(pool)
+    .await?;
+
+    Ok(quarantine::load_logs(pool, rows).await)
+}
@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{detect_project, known_project_roots};
+use crate::db::{models::ActivityLog, time};
+
+const UNASSIGNED_PROJECT: &str = "unassigned";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    Nearest,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimesheetOptions {
+    /// Round each day/project total to this many minutes (0 disables rounding)
+    pub rounding_minutes: i64,
+    pub rounding_mode: RoundingMode,
+    /// Sessions shorter than this are not billed
+    pub min_session_minutes: i64,
+    /// A gap between two events longer than this ends the session
+    pub idle_gap_minutes: i64,
+}
+
+impl TimesheetOptions {
+    /// Every session counts for at least one rounding increment, or a minute without rounding
+    fn session_floor_minutes(&self) -> i64 {
+        self.rounding_minutes.max(1)
+    }
+}
+
+impl Default for TimesheetOptions {
+    fn default() -> Self {
+        Self {
+            rounding_minutes: 15,
+            rounding_mode: RoundingMode::Nearest,
+            min_session_minutes: 5,
+            idle_gap_minutes: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkSession {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl WorkSession {
+    /// Tracked minutes, at least `minimum` so a session of a single event still counts
+    fn minutes(&self, minimum: i64) -> i64 {
+        (self.end - self.start).num_minutes().max(minimum)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TimeEntry {
+    pub date: NaiveDate,
+    pub project: String,
+    pub sessions: Vec<WorkSession>,
+    pub raw_minutes: i64,
+    pub billable_minutes: i64,
+}
+
+impl TimeEntry {
+    pub fn hours(&self) -> f64 {
+        self.billable_minutes as f64 / 60.0
+    }
+}
+
+/// Round `minutes` to a multiple of `increment` according to `mode`
+pub fn round_minutes(minutes: i64, increment: i64, mode: RoundingMode) -> i64 {
+    if increment <= 0 || minutes <= 0 {
+        return minutes.max(0);
+    }
+
+    let remainder = minutes % increment;
+    if remainder == 0 {
+        return minutes;
+    }
+
+    let down = minutes - remainder;
+    match mode {
+        RoundingMode::Down => down,
+        RoundingMode::Up => down + increment,
+        // Exactly half an increment rounds up
+        RoundingMode::Nearest if remainder * 2 >= increment => down + increment,
+        RoundingMode::Nearest => down,
+    }
+}
+
+/// Group logs into per-day, per-project time entries.
+///
+/// Events without a project (e.g. browser visits) are attributed to the project of the
+/// previous event, since research usually belongs to whatever was being worked on.
+pub fn build_time_entries(mut logs: Vec<ActivityLog>, options: &TimesheetOptions) -> Vec<TimeEntry> {
+    logs.sort_by_key(|log| log.timestamp);
+
+    let roots = known_project_roots(&logs);
+    let zone = time::current();
+    let idle_gap = Duration::minutes(options.idle_gap_minutes.max(1));
+    let floor = options.session_floor_minutes();
+
+    let mut timestamps: BTreeMap<(NaiveDate, String), Vec<DateTime<Utc>>> = BTreeMap::new();
+    let mut current_project: Option<String> = None;
+
+    for log in &logs {
+        if let Some(project) = detect_project(log, &roots) {
+            current_project = Some(project);
+        }
+
+        let project = current_project
+            .clone()
+            .unwrap_or_else(|| UNASSIGNED_PROJECT.to_string());
+
+        timestamps
+            .entry((zone.local_date(log.timestamp), project))
+            .or_default()
+            .push(log.timestamp);
+    }
+
+    timestamps
+        .into_iter()
+        .filter_map(|((date, project), times)| {
+            let mut sessions: Vec<WorkSession> = Vec::new();
+
+            for time in times {
+                match sessions.last_mut() {
+                    Some(session) if time - session.end <= idle_gap => session.end = time,
+                    _ => sessions.push(WorkSession { start: time, end: time }),
+                }
+            }
+
+            sessions.retain(|session| session.minutes(floor) >= options.min_session_minutes);
+            if sessions.is_empty() {
+                return None;
+            }
+
+            let raw_minutes = sessions.iter().map(|session| session.minutes(floor)).sum();
+            let billable_minutes =
+                round_minutes(raw_minutes, options.rounding_minutes, options.rounding_mode);
+
+            Some(TimeEntry {
+                date,
+                project,
+                sessions,
+                raw_minutes,
+                billable_minutes,
+            })
+        })
+        .collect()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn to_csv(entries: &[TimeEntry]) -> String {
+    let zone = time::current();
+    let mut csv = String::from("date,project,hours,billable_minutes,raw_minutes,sessions,first_start,last_end\n");
+
+    for entry in entries {
+        let first_start = entry.sessions.first().map(|s| zone.to_local(s.start).to_rfc3339()).unwrap_or_default();
+        let last_end = entry.sessions.last().map(|s| zone.to_local(s.end).to_rfc3339()).unwrap_or_default();
+
+        csv.push_str(&format!(
+            "{},{},{:.2},{},{},{},{},{}\n",
+            entry.date,
+            csv_field(&entry.project),
+            entry.hours(),
+            entry.billable_minutes,
+            entry.raw_minutes,
+            entry.sessions.len(),
+            first_start,
+            last_end,
+        ));
+    }
+
+    csv
+}
+
+fn ics_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+fn ics_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// One VEVENT per time entry, starting at the first session and lasting the billable duration
+pub fn to_ics(entries: &[TimeEntry]) -> String {
+    let now = ics_time(Utc::now());
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//DevChronicle//Timesheet//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+
+    for entry in entries {
+        let Some(first) = entry.sessions.first() else {
+            continue;
+        };
+        let end = first.start + Duration::minutes(entry.billable_minutes);
+
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}@devchronicle", uuid::Uuid::new_v4()));
+        lines.push(format!("DTSTAMP:{}", now));
+        lines.push(format!("DTSTART:{}", ics_time(first.start)));
+        lines.push(format!("DTEND:{}", ics_time(end)));
+        lines.push(format!("SUMMARY:{}", ics_text(&entry.project)));
+        lines.push(format!(
+            "DESCRIPTION:{}",
+            ics_text(&format!(
+                "{:.2} h billable ({} min tracked across {} sessions)",
+                entry.hours(),
+                entry.raw_minutes,
+                entry.sessions.len()
+            ))
+        ));
+        lines.push("END:VEVENT".to_string());
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    // iCalendar requires CRLF line endings
+    let mut ics = lines.join("\r\n");
+    ics.push_str("\r\n");
+    ics
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn log(id: i64, minute: i64, workspace: &str) -> ActivityLog {
+        let start = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z").unwrap().with_timezone(&Utc);
+        ActivityLog {
+            id,
+            source: "vscode".to_string(),
+            payload: json!({ "workspace": workspace }),
+            timestamp: start + Duration::minutes(minute),
+            log_type: None,
+            session_id: None,
+            command: None,
+            url: None,
+            domain: None,
+            title: None,
+            file_path: None,
+            project: None,
+        }
+    }
+
+    #[test]
+    fn rounds_at_increment_boundaries() {
+        assert_eq!(round_minutes(0, 15, RoundingMode::Up), 0);
+        assert_eq!(round_minutes(15, 15, RoundingMode::Up), 15);
+        assert_eq!(round_minutes(16, 15, RoundingMode::Up), 30);
+        assert_eq!(round_minutes(29, 15, RoundingMode::Down), 15);
+        assert_eq!(round_minutes(30, 15, RoundingMode::Down), 30);
+        assert_eq!(round_minutes(22, 15, RoundingMode::Nearest), 15);
+        // Exactly half an increment rounds up
+        assert_eq!(round_minutes(7, 14, RoundingMode::Nearest), 14);
+        assert_eq!(round_minutes(23, 15, RoundingMode::Nearest), 30);
+        assert_eq!(round_minutes(37, 0, RoundingMode::Nearest), 37);
+        assert_eq!(round_minutes(-5, 15, RoundingMode::Up), 0);
+    }
+
+    #[test]
+    fn splits_sessions_at_idle_gaps() {
+        let options = TimesheetOptions {
+            rounding_minutes: 0,
+            min_session_minutes: 0,
+            ..Default::default()
+        };
+        let logs = vec![log(1, 0, "/src/app"), log(2, 10, "/src/app"), log(3, 25, "/src/app"), log(4, 41, "/src/app")];
+
+        let entries = build_time_entries(logs, &options);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].project, "app");
+        // 0..25 is one session, the 16 minute gap before 41 starts another
+        assert_eq!(entries[0].sessions.len(), 2);
+        assert_eq!(entries[0].raw_minutes, 25 + 1);
+    }
+
+    #[test]
+    fn single_event_sessions_count_one_increment() {
+        let options = TimesheetOptions::default();
+        let logs = vec![log(1, 0, "/src/app"), log(2, 120, "/src/app"), log(3, 125, "/src/app")];
+
+        let entries = build_time_entries(logs, &options);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].sessions.len(), 2);
+        assert_eq!(entries[0].raw_minutes, 15 + 15);
+        assert_eq!(entries[0].billable_minutes, 30);
+    }
+
+    #[test]
+    fn attributes_events_to_their_project() {
+        let options = TimesheetOptions::default();
+        let logs = vec![log(1, 0, "/src/app"), log(2, 5, "/src/site"), log(3, 10, "/src/app")];
+
+        let projects: Vec<String> = build_time_entries(logs, &options)
+            .into_iter()
+            .map(|entry| entry.project)
+            .collect();
+        assert_eq!(projects, ["app", "site"]);
+    }
+
+    #[test]
+    fn escapes_csv_fields() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn escapes_ics_text() {
+        assert_eq!(ics_text("a,b;c\\d"), r"a\,b\;c\\d");
+        assert_eq!(ics_text("one\r\ntwo\nthree\rfour"), "one\\ntwo\\nthree\\nfour");
+    }
+}
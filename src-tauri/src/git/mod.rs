@@ -0,0 +1,426 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Error};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+use tokio::process::Command;
+
+use crate::{
+    db::models::{GitRepository, GitRepositoryRow},
+    server::handlers::{self, StoreOutcome},
+};
+
+// Field and record separators used in `git log` / `git reflog` format strings
+const FIELD_SEP: char = '\x1f';
+const RECORD_SEP: char = '\x1e';
+
+#[derive(Debug, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub insertions: Option<i64>,
+    pub deletions: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Commit {
+    pub hash: String,
+    pub branch: Option<String>,
+    pub message: String,
+    pub committed_at: DateTime<Utc>,
+    pub files: Vec<FileChange>,
+    pub insertions: i64,
+    pub deletions: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BranchSwitch {
+    pub from: String,
+    pub to: String,
+    pub switched_at: DateTime<Utc>,
+}
+
+/// Run a git command inside `repo` and return its stdout
+async fn run_git(repo: &str, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()
+        .await
+        .with_context(|| format!("failed to run git in {repo}"))?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "git {} failed in {}: {}",
+            args.first().copied().unwrap_or_default(),
+            repo,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Resolve `path` to the top-level directory of the repository it belongs to
+pub async fn resolve_repository_root(path: &str) -> Result<String, Error> {
+    if !Path::new(path).is_dir() {
+        return Err(anyhow!("{path} is not a directory"));
+    }
+
+    let root = run_git(path, &["rev-parse", "--show-toplevel"]).await?;
+    Ok(root.trim().to_string())
+}
+
+/// List commits made on local branches after `since`, authored by the configured git user
+async fn read_commits(repo: &str, since: DateTime<Utc>) -> Result<Vec<Commit>, Error> {
+    let since_arg = format!("--since={}", since.to_rfc3339());
+    // The full message spans lines, so it is closed by another field separator before the numstat lines
+    let format_arg = format!("--format={RECORD_SEP}%H{FIELD_SEP}%S{FIELD_SEP}%cI{FIELD_SEP}%B{FIELD_SEP}");
+
+    let mut args = vec![
+        "log",
+        "--branches",
+        "--source",
+        "--no-merges",
+        "--numstat",
+        since_arg.as_str(),
+        format_arg.as_str(),
+    ];
+
+    // Only pick up our own commits, not whatever arrived through a pull
+    let author = run_git(repo, &["config", "user.email"]).await.unwrap_or_default();
+    let author_arg = format!("--author={}", author.trim());
+    if !author.trim().is_empty() {
+        args.push(author_arg.as_str());
+    }
+
+    let output = run_git(repo, &args).await?;
+    Ok(parse_commits(&output, since))
+}
+
+/// The path after a rename as `--numstat` prints it: `old => new` or `dir/{old => new}/file`
+fn renamed_path(path: &str) -> String {
+    let Some((before, after)) = path.split_once(" => ") else {
+        return path.to_string();
+    };
+
+    match (before.rfind('{'), after.find('}')) {
+        (Some(open), Some(close)) => {
+            // `{ => dir}` and `{dir => }` leave an empty part, which would double the slash around it
+            let joined = format!("{}{}{}", &before[..open], &after[..close], &after[close + 1..]);
+            joined.replace("//", "/")
+        }
+        _ => after.to_string(),
+    }
+}
+
+fn parse_commits(output: &str, since: DateTime<Utc>) -> Vec<Commit> {
+    output
+        .split(RECORD_SEP)
+        .filter_map(|record| {
+            let mut fields = record.splitn(5, FIELD_SEP);
+
+            let hash = fields.next()?.trim().to_string();
+            let branch = fields
+                .next()
+                .map(|source| source.trim_start_matches("refs/heads/").to_string())
+                .filter(|source| !source.is_empty());
+            let committed_at = DateTime::parse_from_rfc3339(fields.next()?)
+                .ok()?
+                .with_timezone(&Utc);
+            let message = fields.next()?.trim().to_string();
+
+            // `--since` is inclusive and only has second precision
+            if committed_at <= since {
+                return None;
+            }
+
+            let files: Vec<FileChange> = fields
+                .next()
+                .unwrap_or_default()
+                .lines()
+                .filter_map(|line| {
+                    let mut parts = line.splitn(3, '\t');
+                    let insertions = parts.next()?;
+                    let deletions = parts.next()?;
+                    let path = parts.next()?;
+                    Some(FileChange {
+                        path: renamed_path(path),
+                        // Binary files report "-" instead of line counts
+                        insertions: insertions.parse().ok(),
+                        deletions: deletions.parse().ok(),
+                    })
+                })
+                .collect();
+
+            let insertions = files.iter().filter_map(|file| file.insertions).sum();
+            let deletions = files.iter().filter_map(|file| file.deletions).sum();
+
+            Some(Commit {
+                hash,
+                branch,
+                message,
+                committed_at,
+                files,
+                insertions,
+                deletions,
+            })
+        })
+        .collect()
+}
+
+/// Read `checkout: moving from A to B` entries from the HEAD reflog after `since`
+async fn read_branch_switches(repo: &str, since: DateTime<Utc>) -> Result<Vec<BranchSwitch>, Error> {
+    let format_arg = format!("--format=%gd{FIELD_SEP}%gs");
+    let output = run_git(
+        repo,
+        &["reflog", "show", "--date=iso-strict", format_arg.as_str(), "HEAD"],
+    )
+    .await?;
+
+    Ok(parse_branch_switches(&output, since))
+}
+
+fn parse_branch_switches(output: &str, since: DateTime<Utc>) -> Vec<BranchSwitch> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (selector, subject) = line.split_once(FIELD_SEP)?;
+
+            // Selector looks like `HEAD@{2024-05-01T10:00:00+02:00}`
+            let date = selector.split_once("@{")?.1.trim_end_matches('}');
+            let switched_at = DateTime::parse_from_rfc3339(date).ok()?.with_timezone(&Utc);
+            if switched_at <= since {
+                return None;
+            }
+
+            let (from, to) = subject
+                .strip_prefix("checkout: moving from ")?
+                .split_once(" to ")?;
+
+            Some(BranchSwitch {
+                from: from.to_string(),
+                to: to.to_string(),
+                switched_at,
+            })
+        })
+        .collect()
+}
+
+/// Identity of a git event stored as `event_id`: the repository with the commit hash, or with the
+/// time and branches of a switch. Computed from the payload as scanned, before redaction.
+pub fn event_id(payload: &Value) -> Option<String> {
+    let text = |key: &str| payload.get(key).and_then(Value::as_str);
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"git\0");
+    hasher.update(text("repository")?.as_bytes());
+    match text("hash") {
+        Some(hash) => hasher.update(format!("\0commit\0{hash}").as_bytes()),
+        None => hasher.update(format!("\0switch\0{}\0{}\0{}", text("switched_at")?, text("from")?, text("to")?).as_bytes()),
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+async fn already_ingested(pool: &SqlitePool, event_id: &str) -> Result<bool, Error> {
+    let exists = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM activity_logs WHERE event_id = ?1)")
+        .bind(event_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(exists)
+}
+
+/// Store a scanned event through the same privacy, redaction and insert steps as ingested ones,
+/// unless an earlier scan already stored it. Returns whether it was stored.
+async fn ingest_event(
+    pool: &SqlitePool,
+    repo: &str,
+    log_type: &str,
+    mut payload: Value,
+    timestamp: DateTime<Utc>,
+) -> Result<bool, Error> {
+    payload["type"] = json!(log_type);
+    payload["repository"] = json!(repo);
+
+    let event_id = event_id(&payload).ok_or_else(|| anyhow!("git {log_type} payload is missing its identity"))?;
+    if already_ingested(pool, &event_id).await? {
+        return Ok(false);
+    }
+
+    let outcome = handlers::store_event(pool, "git", payload, timestamp, Some(event_id)).await?;
+    Ok(matches!(outcome, StoreOutcome::Stored(_)))
+}
+
+/// Ingest new commits and branch switches from a single repository, returning the number of new logs.
+/// Events are matched by `event_id`, so ones seen by an overlapping earlier scan are skipped.
+async fn scan_repository(pool: &SqlitePool, repo: &GitRepository, now: DateTime<Utc>) -> Result<usize, Error> {
+    let since = repo.last_scanned_at.unwrap_or(repo.added_at);
+    let mut ingested = 0;
+
+    for commit in read_commits(&repo.path, since).await? {
+        if ingest_event(pool, &repo.path, "commit", json!(commit), commit.committed_at).await? {
+            ingested += 1;
+        }
+    }
+
+    for switch in read_branch_switches(&repo.path, since).await? {
+        if ingest_event(pool, &repo.path, "branch_switch", json!(switch), switch.switched_at).await? {
+            ingested += 1;
+        }
+    }
+
+    sqlx::query("UPDATE git_repositories SET last_scanned_at = ?1 WHERE id = ?2")
+        .bind(now.timestamp_millis())
+        .bind(repo.id)
+        .execute(pool)
+        .await?;
+
+    Ok(ingested)
+}
+
+/// Scan every configured repository. A broken repository is reported and skipped so the others still get scanned.
+pub async fn scan_repositories(pool: &SqlitePool) -> Result<usize, Error> {
+    let repositories = list_repositories(pool).await?;
+    let now = Utc::now();
+    let mut ingested = 0;
+
+    for repo in repositories {
+        match scan_repository(pool, &repo, now).await {
+            Ok(count) => ingested += count,
+            Err(err) => eprintln!("⚠️  Git scan failed for {}: {}", repo.path, err),
+        }
+    }
+
+    Ok(ingested)
+}
+
+pub async fn list_repositories(pool: &SqlitePool) -> Result<Vec<GitRepository>, Error> {
+    let rows = sqlx::query_as::<_, GitRepositoryRow>(
+        "SELECT id, path, added_at, last_scanned_at FROM git_repositories ORDER BY path ASC",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(GitRepository::try_from)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| anyhow!(err.0))
+}
+
+/// Register a repository. History before this moment is not imported.
+pub async fn add_repository(pool: &SqlitePool, path: &str) -> Result<String, Error> {
+    let root = resolve_repository_root(path).await?;
+    let now = Utc::now().timestamp_millis();
+
+    sqlx::query(
+        "INSERT INTO git_repositories (path, added_at) VALUES (?1, ?2)
+         ON CONFLICT(path) DO NOTHING",
+    )
+    .bind(&root)
+    .bind(now)
+    .execute(pool)
+    .await?;
+
+    Ok(root)
+}
+
+/// Stop scanning a repository. Logs that were already ingested are kept.
+pub async fn remove_repository(pool: &SqlitePool, id: i64) -> Result<(), Error> {
+    sqlx::query("DELETE FROM git_repositories WHERE id = ?1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(timestamp).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn parses_full_messages_and_numstat() {
+        let output = format!(
+            "{RECORD_SEP}abc123{FIELD_SEP}refs/heads/main{FIELD_SEP}2024-05-01T10:00:00+02:00{FIELD_SEP}Fix parser\n\nLonger body\nover two lines\n{FIELD_SEP}\n\
+             3\t1\tsrc/lib.rs\n-\t-\tlogo.png\n2\t0\tsrc/{{old => new}}/mod.rs\n"
+        );
+
+        let commits = parse_commits(&output, at("2024-05-01T00:00:00Z"));
+        assert_eq!(commits.len(), 1);
+
+        let commit = &commits[0];
+        assert_eq!(commit.hash, "abc123");
+        assert_eq!(commit.branch.as_deref(), Some("main"));
+        assert_eq!(commit.message, "Fix parser\n\nLonger body\nover two lines");
+        assert_eq!(commit.committed_at, at("2024-05-01T08:00:00Z"));
+        assert_eq!((commit.insertions, commit.deletions), (5, 1));
+
+        let paths: Vec<&str> = commit.files.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, ["src/lib.rs", "logo.png", "src/new/mod.rs"]);
+        assert_eq!(commit.files[1].insertions, None);
+    }
+
+    #[test]
+    fn skips_commits_up_to_since() {
+        let output = format!(
+            "{RECORD_SEP}old{FIELD_SEP}{FIELD_SEP}2024-05-01T10:00:00Z{FIELD_SEP}Old{FIELD_SEP}\n\
+             {RECORD_SEP}new{FIELD_SEP}{FIELD_SEP}2024-05-01T10:00:01Z{FIELD_SEP}New{FIELD_SEP}\n"
+        );
+
+        let commits = parse_commits(&output, at("2024-05-01T10:00:00Z"));
+        assert_eq!(commits.len(), 1);
+        assert_eq!(commits[0].hash, "new");
+        assert_eq!(commits[0].branch, None);
+        assert!(commits[0].files.is_empty());
+    }
+
+    #[test]
+    fn resolves_renamed_paths() {
+        assert_eq!(renamed_path("src/lib.rs"), "src/lib.rs");
+        assert_eq!(renamed_path("old.rs => new.rs"), "new.rs");
+        assert_eq!(renamed_path("src/{a => b}/mod.rs"), "src/b/mod.rs");
+        assert_eq!(renamed_path("src/{ => nested}/mod.rs"), "src/nested/mod.rs");
+        assert_eq!(renamed_path("src/{nested => }/mod.rs"), "src/mod.rs");
+        assert_eq!(renamed_path("{a.rs => b.rs}"), "b.rs");
+    }
+
+    #[test]
+    fn parses_branch_switches() {
+        let output = format!(
+            "HEAD@{{2024-05-01T12:00:00+02:00}}{FIELD_SEP}checkout: moving from main to feature\n\
+             HEAD@{{2024-05-01T11:00:00+02:00}}{FIELD_SEP}commit: Fix parser\n\
+             HEAD@{{2024-04-30T09:00:00+02:00}}{FIELD_SEP}checkout: moving from feature to main\n"
+        );
+
+        let switches = parse_branch_switches(&output, at("2024-05-01T00:00:00Z"));
+        assert_eq!(switches.len(), 1);
+        assert_eq!(switches[0].from, "main");
+        assert_eq!(switches[0].to, "feature");
+        assert_eq!(switches[0].switched_at, at("2024-05-01T10:00:00Z"));
+    }
+
+    #[test]
+    fn event_ids_identify_commits_and_switches() {
+        let commit = json!({ "repository": "/repo", "hash": "abc123", "message": "Fix" });
+        let edited = json!({ "repository": "/repo", "hash": "abc123", "message": "[REDACTED]" });
+        assert_eq!(event_id(&commit), event_id(&edited));
+        assert_ne!(event_id(&commit), event_id(&json!({ "repository": "/other", "hash": "abc123" })));
+
+        let switch = json!({
+            "repository": "/repo",
+            "from": "main",
+            "to": "feature",
+            "switched_at": "2024-05-01T10:00:00Z",
+        });
+        assert!(event_id(&switch).is_some());
+        assert_ne!(event_id(&switch), event_id(&commit));
+        assert_eq!(event_id(&json!({ "hash": "abc123" })), None);
+    }
+}
@@ -1,35 +1,89 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+// Settings helpers and the like are only called by the Tauri commands
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
 
 mod ai;
+#[cfg(feature = "gui")]
 mod commands;
+mod console;
+mod daemon;
 mod db;
+mod engine;
+mod events;
+mod export;
+mod git;
+mod privacy;
+mod retention;
 mod sanitizer;
+mod secrets;
 mod server;
 mod state;
+#[cfg(feature = "gui")]
+mod tray;
+
+pub use console::run_console;
+pub use daemon::run_daemon;
+#[cfg(feature = "gui")]
+use engine::Engine;
+#[cfg(feature = "gui")]
+use tauri::{async_runtime, Emitter, Manager};
+#[cfg(feature = "gui")]
+use tokio::sync::broadcast::error::RecvError;
 
-use std::{error::Error, str::FromStr, time::Duration};
-
-use ai::client::AiClient;
-use db::init_db;
-use sqlx::{
-    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
-    SqlitePool,
-};
-use tauri::{async_runtime, Manager};
-
-const SERVER_PORT: u16 = 3030;
 const DB_FILENAME: &str = "activity_logs.db";
 
+#[cfg(feature = "gui")]
 pub fn run() {
     tauri::Builder::default()
+        .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
-            commands::get_logs_by_date,
+            commands::query_logs,
             commands::get_ai_reports,
-            commands::get_reports_by_date,
+            commands::query_reports,
+            commands::fetch_activity_stats,
             commands::trigger_manual_summary,
             commands::fetch_ai_settings,
             commands::save_ai_settings,
-            commands::test_ai_connection
+            commands::get_report_prompt,
+            commands::test_ai_connection,
+            commands::list_git_repositories,
+            commands::add_git_repository,
+            commands::remove_git_repository,
+            commands::export_timesheet,
+            commands::fetch_journal_settings,
+            commands::save_journal_settings,
+            commands::export_journal,
+            commands::export_chronicle,
+            commands::import_chronicle,
+            commands::fetch_retention_settings,
+            commands::save_retention_settings,
+            commands::preview_retention,
+            commands::fetch_redaction_settings,
+            commands::save_redaction_settings,
+            commands::list_redaction_rules,
+            commands::save_redaction_rule,
+            commands::delete_redaction_rule,
+            commands::test_redaction,
+            commands::fetch_privacy_status,
+            commands::fetch_rejected_events,
+            commands::fetch_time_settings,
+            commands::save_time_settings,
+            commands::list_quarantined_rows,
+            commands::repair_quarantined_row,
+            commands::pause_recording,
+            commands::resume_recording,
+            commands::list_privacy_exclusions,
+            commands::add_privacy_exclusion,
+            commands::remove_privacy_exclusion,
+            commands::fetch_encryption_status,
+            commands::enable_database_encryption,
+            commands::disable_database_encryption,
+            commands::rekey_database,
+            commands::fetch_api_token,
+            commands::rotate_api_token,
+            commands::fetch_server_status,
+            commands::fetch_server_settings,
+            commands::save_server_settings
         ])
         .setup(|app| {
             let app_handle = app.handle();
@@ -38,50 +92,46 @@ pub fn run() {
                 .app_data_dir()
                 .map_err(|_| "failed to resolve app data directory".to_string())?;
 
-            std::fs::create_dir_all(&data_dir)?;
-
-            let db_path = data_dir.join(DB_FILENAME);
-            let db_url = format!("sqlite://{}", db_path.to_string_lossy());
-
-            let pool = create_pool(&db_url)?;
-            async_runtime::block_on(async { init_db(&pool).await })
-                .map_err(|err| -> Box<dyn Error> { Box::new(err) })?;
-
-            app.manage(pool.clone());
-            app.manage(AiClient::new());
+            let engine = async_runtime::block_on(Engine::open(&data_dir)).map_err(|err| err.to_string())?;
 
-            // Build and start the Axum server
-            let router = server::build_router(pool.clone());
+            app.manage(engine.pool.clone());
+            app.manage(engine.secret_store.clone());
+            app.manage(engine.api_token.clone());
+            app.manage(engine.events.clone());
+            app.manage(engine.ai_client.clone());
+            app.manage(engine.health.clone());
 
-            println!("🚀 Starting Axum ingestion server on port {}", SERVER_PORT);
+            tray::setup(app, engine.pool.clone())?;
 
-            // Spawn server using axum::Server::bind + router.into_make_service()
+            // Forward live events to the frontend as Tauri events of the same name
+            let mut receiver = engine.events.subscribe();
+            let app_handle_for_events = app_handle.clone();
             async_runtime::spawn(async move {
-                if let Err(err) = start_server(router).await {
-                    eprintln!("❌ Axum server error: {err}");
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => {
+                            if let Err(err) = app_handle_for_events.emit(event.name(), &event) {
+                                eprintln!("⚠️  Failed to emit {} event: {err}", event.name());
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped)) => eprintln!("⚠️  Frontend missed {skipped} live events"),
+                        Err(RecvError::Closed) => break,
+                    }
                 }
             });
 
-            // Start automatic scheduler (every 10 minutes)
-            let pool_for_scheduler = pool.clone();
-            let app_handle_for_scheduler = app_handle.clone();
-
+            // The server and scheduler run until the app exits. Summaries keep being generated
+            // when the server can't start, the UI shows why it failed.
             async_runtime::spawn(async move {
-                use tokio::time::interval;
-
-                let mut interval_timer = interval(Duration::from_secs(600)); // 10 minutes
-
-                loop {
-                    interval_timer.tick().await;
-
-                    match auto_process_logs(&pool_for_scheduler, &app_handle_for_scheduler).await {
-                        Ok(summary) => {
-                            if !summary.is_empty() {
-                                println!("✅ Auto-generated summary at {}", chrono::Utc::now());
-                            }
-                        }
-                        Err(e) => eprintln!("⚠️  Auto-processing error: {}", e),
+                let listener = match engine.bind().await {
+                    Ok(listener) => Some(listener),
+                    Err(err) => {
+                        eprintln!("❌ Ingestion server failed to start: {err:#}");
+                        None
                     }
+                };
+                if let Err(err) = engine.run(listener, std::future::pending()).await {
+                    eprintln!("❌ Ingestion server error: {err:#}");
                 }
             });
 
@@ -90,105 +140,3 @@ pub fn run() {
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
-
-fn create_pool(database_url: &str) -> Result<SqlitePool, Box<dyn Error>> {
-    let connect_options = SqliteConnectOptions::from_str(database_url)?
-        .create_if_missing(true)
-        .journal_mode(SqliteJournalMode::Wal)
-        .busy_timeout(Duration::from_secs(5));
-
-    async_runtime::block_on(async {
-        SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect_with(connect_options)
-            .await
-    })
-    .map_err(|err| -> Box<dyn Error> { Box::new(err) })
-}
-
-async fn start_server(router: axum::Router) -> Result<(), Box<dyn Error + Send + Sync>>
-{
-    let addr = format!("127.0.0.1:{}", SERVER_PORT);
-    println!("🎧 Binding Axum server to {}", addr);
-
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-    
-    println!("✅ Axum server successfully started on http://{}", addr);
-
-    // into_make_service() is available on Router without type parameters
-    axum::serve(listener, router.into_make_service())
-        .await
-        .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync>)?;
-
-    Ok(())
-}
-
-async fn auto_process_logs(
-    pool: &SqlitePool,
-    app_handle: &tauri::AppHandle,
-) -> Result<String, Box<dyn Error>> {
-    use chrono::{Duration, Utc};
-    use crate::db::models::{ActivityLog, ActivityLogRow, ActivityLogConversionError};
-
-    let ai_client = app_handle.state::<AiClient>();
-
-    let end = Utc::now();
-    let start = end - Duration::minutes(10);
-
-    // Fetch unprocessed logs with all normalized fields
-    let rows = sqlx::query_as::<_, ActivityLogRow>(
-        "SELECT id, source, payload, timestamp, log_type, session_id, command, url, domain, title, file_path 
-         FROM activity_logs \
-         WHERE is_processed = 0 AND timestamp >= ?1 AND timestamp <= ?2 \
-         ORDER BY timestamp ASC",
-    )
-    .bind(start.to_rfc3339())
-    .bind(end.to_rfc3339())
-    .fetch_all(pool)
-    .await?;
-
-    if rows.is_empty() {
-        return Ok(String::new());
-    }
-
-    let logs: Vec<ActivityLog> = rows
-        .into_iter()
-        .map(ActivityLog::try_from)
-        .collect::<Result<_, ActivityLogConversionError>>()
-        .map_err(|e| Box::new(std::io::Error::new(std::io::ErrorKind::Other, e.0)) as Box<dyn Error>)?;
-
-    // Collect log IDs and metadata before generating summary
-    let log_ids: Vec<i64> = logs.iter().map(|l| l.id).collect();
-    let sources_set: std::collections::HashSet<String> = logs.iter().map(|l| l.source.clone()).collect();
-    let sources_vec: Vec<String> = sources_set.into_iter().collect();
-
-    let summary = ai::generate_summary(pool, ai_client.inner(), logs).await?;
-
-    // Save the summary with metadata
-    let log_ids_json = serde_json::to_string(&log_ids).unwrap_or_default();
-    let sources_str = sources_vec.join(",");
-
-    sqlx::query(
-        "INSERT INTO ai_reports (summary, generated_at, log_ids, log_count, sources, time_range_start, time_range_end) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)"
-    )
-    .bind(&summary)
-    .bind(end.to_rfc3339())
-    .bind(&log_ids_json)
-    .bind(log_ids.len() as i64)
-    .bind(&sources_str)
-    .bind(start.to_rfc3339())
-    .bind(end.to_rfc3339())
-    .execute(pool)
-    .await?;
-
-    // Mark logs as processed
-    sqlx::query("UPDATE activity_logs SET is_processed = 1 WHERE timestamp >= ?1 AND timestamp <= ?2")
-        .bind(start.to_rfc3339())
-        .bind(end.to_rfc3339())
-        .execute(pool)
-        .await?;
-
-    Ok(summary)
-}
-
@@ -0,0 +1,368 @@
+use std::sync::{Arc, RwLock};
+
+use anyhow::{anyhow, Error};
+use chrono::{DateTime, Duration, Utc};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+
+use crate::{
+    db::time::{self, StoredTimestamp},
+    sanitizer::expand_home,
+};
+
+/// Exclusion kinds as stored in `privacy_exclusions.kind`
+pub const KIND_DOMAIN: &str = "domain";
+pub const KIND_URL: &str = "url";
+pub const KIND_PATH: &str = "path";
+pub const KIND_COMMAND: &str = "command";
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PrivacyExclusion {
+    #[serde(default)]
+    pub id: i64,
+    pub kind: String,
+    pub pattern: String,
+}
+
+/// Why an event was dropped. Only this reason is recorded, never the event's content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    Paused,
+    Domain,
+    Url,
+    Path,
+    Command,
+}
+
+impl DropReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DropReason::Paused => "paused",
+            DropReason::Domain => KIND_DOMAIN,
+            DropReason::Url => KIND_URL,
+            DropReason::Path => KIND_PATH,
+            DropReason::Command => KIND_COMMAND,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PrivacyStatus {
+    pub paused_until: Option<DateTime<Utc>>,
+    pub dropped_today: i64,
+    pub dropped_total: i64,
+}
+
+/// Pause state plus the compiled exclusion lists, swapped whenever either changes
+#[derive(Debug, Default)]
+pub struct PrivacyPolicy {
+    paused_until: Option<DateTime<Utc>>,
+    domains: Vec<String>,
+    urls: Vec<Regex>,
+    paths: GlobSet,
+    commands: Vec<String>,
+}
+
+/// Check that `pattern` is usable for `kind` before it is stored
+pub fn validate_exclusion(kind: &str, pattern: &str) -> Result<(), String> {
+    if pattern.trim().is_empty() {
+        return Err("pattern must not be empty".to_string());
+    }
+
+    match kind {
+        KIND_DOMAIN | KIND_COMMAND => Ok(()),
+        KIND_URL => Regex::new(pattern).map(|_| ()).map_err(|err| format!("invalid URL pattern: {err}")),
+        KIND_PATH => GlobBuilder::new(&expand_home(pattern))
+            .literal_separator(true)
+            .build()
+            .map(|_| ())
+            .map_err(|err| format!("invalid path glob: {err}")),
+        other => Err(format!(
+            "unknown exclusion kind '{other}', expected one of {KIND_DOMAIN}, {KIND_URL}, {KIND_PATH}, {KIND_COMMAND}"
+        )),
+    }
+}
+
+impl PrivacyPolicy {
+    fn compile(paused_until: Option<DateTime<Utc>>, exclusions: &[PrivacyExclusion]) -> Self {
+        let mut policy = PrivacyPolicy {
+            paused_until,
+            ..Default::default()
+        };
+        let mut paths = GlobSetBuilder::new();
+
+        for exclusion in exclusions {
+            if let Err(err) = validate_exclusion(&exclusion.kind, &exclusion.pattern) {
+                eprintln!("⚠️  Skipping privacy exclusion '{}': {}", exclusion.pattern, err);
+                continue;
+            }
+
+            let pattern = exclusion.pattern.trim();
+            match exclusion.kind.as_str() {
+                KIND_DOMAIN => policy
+                    .domains
+                    .push(pattern.trim_start_matches("*.").trim_start_matches('.').to_ascii_lowercase()),
+                KIND_URL => policy.urls.extend(Regex::new(pattern).ok()),
+                KIND_PATH => {
+                    if let Ok(glob) = GlobBuilder::new(&expand_home(pattern)).literal_separator(true).build() {
+                        paths.add(glob);
+                    }
+                }
+                KIND_COMMAND => policy.commands.push(pattern.to_string()),
+                _ => {}
+            }
+        }
+
+        policy.paths = paths.build().unwrap_or_else(|_| GlobSet::empty());
+        policy
+    }
+
+    pub fn paused_until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.paused_until.filter(|until| *until > now)
+    }
+
+    fn domain_excluded(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.domains
+            .iter()
+            .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+    }
+
+    /// Commands match an exclusion when they start with it as whole words, so `pass` doesn't exclude `passwd`
+    fn command_excluded(&self, command: &str) -> bool {
+        let command = command.trim_start();
+        self.commands.iter().any(|prefix| {
+            command
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        })
+    }
+
+    /// Decide whether an incoming event must be dropped, based on its raw (unredacted) payload
+    pub fn drop_reason(&self, payload: &Value, now: DateTime<Utc>) -> Option<DropReason> {
+        if self.paused_until(now).is_some() {
+            return Some(DropReason::Paused);
+        }
+
+        let text = |key: &str| payload.get(key).and_then(Value::as_str);
+
+        if let Some(url) = text("url") {
+            let host = url::Url::parse(url).ok().and_then(|parsed| parsed.host_str().map(str::to_string));
+            if host.as_deref().or(text("domain")).is_some_and(|host| self.domain_excluded(host)) {
+                return Some(DropReason::Domain);
+            }
+            if self.urls.iter().any(|pattern| pattern.is_match(url)) {
+                return Some(DropReason::Url);
+            }
+        }
+
+        if ["cwd", "file_path", "workspace", "repository"]
+            .iter()
+            .filter_map(|key| text(key))
+            .any(|path| self.paths.is_match(path))
+        {
+            return Some(DropReason::Path);
+        }
+
+        if text("command").is_some_and(|command| self.command_excluded(command)) {
+            return Some(DropReason::Command);
+        }
+
+        None
+    }
+}
+
+lazy_static! {
+    static ref POLICY: RwLock<Arc<PrivacyPolicy>> = RwLock::new(Arc::new(PrivacyPolicy::default()));
+}
+
+/// The policy currently enforced by ingestion
+pub fn current() -> Arc<PrivacyPolicy> {
+    POLICY.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+pub async fn list_exclusions(pool: &SqlitePool) -> Result<Vec<PrivacyExclusion>, sqlx::Error> {
+    sqlx::query_as::<_, PrivacyExclusion>("SELECT id, kind, pattern FROM privacy_exclusions ORDER BY kind, pattern")
+        .fetch_all(pool)
+        .await
+}
+
+/// Reload the pause state and exclusion lists from the database and make them current
+pub async fn reload(pool: &SqlitePool) -> Result<(), Error> {
+    let paused_until: Option<StoredTimestamp> =
+        sqlx::query_scalar("SELECT paused_until FROM privacy_settings WHERE id = 1")
+            .fetch_one(pool)
+            .await?;
+    let paused_until = paused_until
+        .map(|value| value.to_utc())
+        .transpose()
+        .map_err(|err| anyhow!(err))?;
+
+    let policy = PrivacyPolicy::compile(paused_until, &list_exclusions(pool).await?);
+    *POLICY.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(policy);
+    Ok(())
+}
+
+/// Stop recording for `minutes`, or resume immediately with `None`
+pub async fn pause(pool: &SqlitePool, minutes: Option<i64>, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, Error> {
+    let paused_until = match minutes {
+        Some(minutes) if minutes > 0 => Some(
+            Duration::try_minutes(minutes)
+                .and_then(|duration| now.checked_add_signed(duration))
+                .ok_or_else(|| anyhow!("pause duration of {minutes} minutes is too long"))?,
+        ),
+        Some(_) => return Err(anyhow!("pause duration must be positive")),
+        None => None,
+    };
+
+    sqlx::query("UPDATE privacy_settings SET paused_until = ?1 WHERE id = 1")
+        .bind(paused_until.map(|until| until.timestamp_millis()))
+        .execute(pool)
+        .await?;
+
+    reload(pool).await?;
+    Ok(paused_until)
+}
+
+pub async fn add_exclusion(pool: &SqlitePool, kind: &str, pattern: &str) -> Result<PrivacyExclusion, Error> {
+    validate_exclusion(kind, pattern).map_err(|err| anyhow!(err))?;
+
+    sqlx::query("INSERT INTO privacy_exclusions (kind, pattern) VALUES (?1, ?2) ON CONFLICT(kind, pattern) DO NOTHING")
+        .bind(kind)
+        .bind(pattern.trim())
+        .execute(pool)
+        .await?;
+
+    let exclusion = sqlx::query_as::<_, PrivacyExclusion>(
+        "SELECT id, kind, pattern FROM privacy_exclusions WHERE kind = ?1 AND pattern = ?2",
+    )
+    .bind(kind)
+    .bind(pattern.trim())
+    .fetch_one(pool)
+    .await?;
+
+    reload(pool).await?;
+    Ok(exclusion)
+}
+
+pub async fn remove_exclusion(pool: &SqlitePool, id: i64) -> Result<(), Error> {
+    sqlx::query("DELETE FROM privacy_exclusions WHERE id = ?1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    reload(pool).await
+}
+
+/// Count a dropped event per day, source and reason
+pub async fn record_dropped(pool: &SqlitePool, source: &str, reason: DropReason, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO dropped_events (day, source, reason, count) VALUES (?1, ?2, ?3, 1)
+         ON CONFLICT(day, source, reason) DO UPDATE SET count = count + 1",
+    )
+    .bind(time::current().local_date(now).to_string())
+    .bind(source)
+    .bind(reason.as_str())
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn status(pool: &SqlitePool, now: DateTime<Utc>) -> Result<PrivacyStatus, sqlx::Error> {
+    let (dropped_today, dropped_total): (i64, i64) = sqlx::query_as(
+        "SELECT COALESCE(SUM(CASE WHEN day = ?1 THEN count ELSE 0 END), 0), COALESCE(SUM(count), 0) FROM dropped_events",
+    )
+    .bind(time::current().local_date(now).to_string())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(PrivacyStatus {
+        paused_until: current().paused_until(now),
+        dropped_today,
+        dropped_total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::db;
+
+    fn policy(exclusions: &[(&str, &str)]) -> PrivacyPolicy {
+        let exclusions: Vec<_> = exclusions
+            .iter()
+            .map(|(kind, pattern)| PrivacyExclusion {
+                id: 0,
+                kind: kind.to_string(),
+                pattern: pattern.to_string(),
+            })
+            .collect();
+        PrivacyPolicy::compile(None, &exclusions)
+    }
+
+    #[test]
+    fn domains_match_themselves_and_subdomains_only() {
+        let policy = policy(&[(KIND_DOMAIN, "*.Bank.example")]);
+        let reason = |url: &str| policy.drop_reason(&json!({ "url": url }), Utc::now());
+
+        assert_eq!(reason("https://bank.example/login"), Some(DropReason::Domain));
+        assert_eq!(reason("https://online.BANK.example/"), Some(DropReason::Domain));
+        assert_eq!(reason("https://notbank.example/"), None);
+        assert_eq!(reason("https://bank.example.org/"), None);
+    }
+
+    #[test]
+    fn commands_match_whole_word_prefixes() {
+        let policy = policy(&[(KIND_COMMAND, "pass")]);
+        let reason = |command: &str| policy.drop_reason(&json!({ "command": command }), Utc::now());
+
+        assert_eq!(reason("pass"), Some(DropReason::Command));
+        assert_eq!(reason("  pass show email/work"), Some(DropReason::Command));
+        assert_eq!(reason("passwd"), None);
+        assert_eq!(reason("echo pass"), None);
+    }
+
+    #[test]
+    fn path_globs_expand_the_home_directory() {
+        let Some(home) = std::env::var("HOME").ok().filter(|home| home.starts_with('/')) else {
+            return;
+        };
+        let home = home.trim_end_matches('/');
+        let policy = policy(&[(KIND_PATH, "~/private/**")]);
+        let reason = |key: &str, path: String| policy.drop_reason(&json!({ key: path }), Utc::now());
+
+        assert_eq!(reason("cwd", format!("{home}/private/taxes")), Some(DropReason::Path));
+        assert_eq!(reason("file_path", format!("{home}/private/a/b.txt")), Some(DropReason::Path));
+        assert_eq!(reason("cwd", format!("{home}/public/private/taxes")), None);
+        assert_eq!(reason("cwd", "/private/taxes".to_string()), None);
+    }
+
+    #[test]
+    fn pauses_expire() {
+        let now = Utc::now();
+        let policy = PrivacyPolicy::compile(Some(now + Duration::minutes(5)), &[]);
+
+        assert_eq!(policy.drop_reason(&json!({}), now), Some(DropReason::Paused));
+        assert_eq!(policy.drop_reason(&json!({}), now + Duration::minutes(5)), None);
+        assert_eq!(policy.paused_until(now + Duration::minutes(6)), None);
+    }
+
+    #[tokio::test]
+    async fn pause_rejects_durations_out_of_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        let now = Utc::now();
+
+        assert!(pause(&pool, Some(0), now).await.is_err());
+        assert!(pause(&pool, Some(i64::MAX), now).await.is_err());
+        assert_eq!(pause(&pool, Some(30), now).await.unwrap(), Some(now + Duration::minutes(30)));
+        assert_eq!(pause(&pool, None, now).await.unwrap(), None);
+    }
+}
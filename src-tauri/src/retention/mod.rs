@@ -0,0 +1,382 @@
+use anyhow::Error;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::{ConnectOptions, Connection, SqlitePool};
+
+use crate::db::{crypto, get_retention_settings, models::RetentionSettings, quarantine};
+
+const DELETE_BATCH_SIZE: i64 = 500;
+
+// Rough on-disk size of a row: the variable-length columns plus fixed overhead
+const LOG_ROW_SIZE_SQL: &str = "LENGTH(payload) + COALESCE(LENGTH(command), 0) + COALESCE(LENGTH(url), 0) \
+     + COALESCE(LENGTH(title), 0) + COALESCE(LENGTH(file_path), 0) + COALESCE(LENGTH(cwd), 0) + 64";
+const REPORT_ROW_SIZE_SQL: &str = "LENGTH(summary) + COALESCE(LENGTH(log_ids), 0) + 64";
+
+// Payload keys kept when stripping: what identifies an event and what git log formatting shows.
+// Everything else, e.g. commands, URLs, titles and commit messages, is dropped.
+const STRIPPED_PAYLOAD_KEYS: &[&str] = &[
+    "type",
+    "hash",
+    "branch",
+    "repository",
+    "from",
+    "to",
+    "switched_at",
+    "insertions",
+    "deletions",
+];
+
+#[derive(Debug, Default, Serialize)]
+pub struct RetentionReport {
+    pub logs_deleted: i64,
+    pub reports_deleted: i64,
+    pub payloads_stripped: i64,
+    pub estimated_bytes_freed: i64,
+    pub vacuumed: bool,
+}
+
+/// Cutoff in epoch milliseconds, `None` when the period is unlimited or too long to reach back to
+fn cutoff(now: DateTime<Utc>, days: Option<i64>) -> Option<i64> {
+    days.filter(|days| *days > 0)
+        .and_then(Duration::try_days)
+        .and_then(|period| now.checked_sub_signed(period))
+        .map(|cutoff| cutoff.timestamp_millis())
+}
+
+/// Count what the current policy would remove without touching anything
+pub async fn preview(pool: &SqlitePool, now: DateTime<Utc>) -> Result<RetentionReport, Error> {
+    let settings = get_retention_settings(pool).await?;
+    let mut report = RetentionReport::default();
+
+    if let Some(cutoff) = cutoff(now, settings.raw_log_days) {
+        let (count, bytes): (i64, Option<i64>) = sqlx::query_as(&format!(
+            "SELECT COUNT(*), SUM({LOG_ROW_SIZE_SQL}) FROM activity_logs WHERE timestamp < ?1"
+        ))
+        .bind(cutoff)
+        .fetch_one(pool)
+        .await?;
+        report.logs_deleted = count;
+        report.estimated_bytes_freed += bytes.unwrap_or(0);
+    }
+
+    if let Some(cutoff) = cutoff(now, settings.report_days) {
+        let (count, bytes): (i64, Option<i64>) = sqlx::query_as(&format!(
+            "SELECT COUNT(*), SUM({REPORT_ROW_SIZE_SQL}) FROM ai_reports WHERE generated_at < ?1"
+        ))
+        .bind(cutoff)
+        .fetch_one(pool)
+        .await?;
+        report.reports_deleted = count;
+        report.estimated_bytes_freed += bytes.unwrap_or(0);
+    }
+
+    if settings.strip_payload_after_summary {
+        // Rows that are about to be deleted anyway are not counted twice
+        let keep_after = cutoff(now, settings.raw_log_days).unwrap_or(i64::MIN);
+        let (count, bytes): (i64, Option<i64>) = sqlx::query_as(
+            "SELECT COUNT(*), SUM(LENGTH(payload)) FROM activity_logs \
+             WHERE is_processed = 1 AND payload_stripped = 0 AND timestamp >= ?1",
+        )
+        .bind(keep_after)
+        .fetch_one(pool)
+        .await?;
+        report.payloads_stripped = count;
+        report.estimated_bytes_freed += bytes.unwrap_or(0);
+    }
+
+    Ok(report)
+}
+
+/// Delete the rows matching `condition`, which compares against the cutoff as `?1`, in small
+/// batches so the ingestion server is never locked out for long
+async fn delete_in_batches(pool: &SqlitePool, table: &str, condition: &str, cutoff: i64) -> Result<i64, Error> {
+    let sql = format!(
+        "DELETE FROM {table} WHERE id IN (SELECT id FROM {table} WHERE {condition} LIMIT ?2)"
+    );
+    let mut deleted = 0;
+
+    loop {
+        let result = sqlx::query(&sql)
+            .bind(cutoff)
+            .bind(DELETE_BATCH_SIZE)
+            .execute(pool)
+            .await?;
+
+        let affected = result.rows_affected() as i64;
+        deleted += affected;
+        if affected < DELETE_BATCH_SIZE {
+            return Ok(deleted);
+        }
+    }
+}
+
+/// The payload reduced to [`STRIPPED_PAYLOAD_KEYS`]
+fn stripped_payload(payload: &str) -> Result<String, Error> {
+    let payload: Value = serde_json::from_str(&crypto::open(payload)?).unwrap_or_default();
+    let kept: Map<String, Value> = STRIPPED_PAYLOAD_KEYS
+        .iter()
+        .filter_map(|key| Some((key.to_string(), payload.get(*key)?.clone())))
+        .collect();
+    crypto::seal(&serde_json::to_string(&kept)?)
+}
+
+/// Strip the payloads of summarized logs. Payloads that can't be decrypted are quarantined and
+/// left as they are, so one bad row doesn't block stripping, and the VACUUM after it, for good.
+async fn strip_payloads(pool: &SqlitePool) -> Result<i64, Error> {
+    let mut stripped = 0;
+    let mut last_id = 0;
+
+    loop {
+        let batch: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT id, payload FROM activity_logs WHERE is_processed = 1 AND payload_stripped = 0 AND id > ?1 \
+             ORDER BY id LIMIT ?2",
+        )
+        .bind(last_id)
+        .bind(DELETE_BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+        let Some((id, _)) = batch.last() else {
+            return Ok(stripped);
+        };
+        last_id = *id;
+
+        let writes = crypto::write_access().await;
+        let mut tx = pool.begin().await?;
+        for (id, payload) in &batch {
+            let payload = match stripped_payload(payload) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    eprintln!("⚠️  Not stripping unreadable activity_logs row {id}: {err:#}");
+                    quarantine::record(&mut *tx, quarantine::TABLE_LOGS, *id, &format!("{err:#}")).await?;
+                    continue;
+                }
+            };
+            sqlx::query("UPDATE activity_logs SET payload = ?1, payload_stripped = 1 WHERE id = ?2")
+                .bind(payload)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            stripped += 1;
+        }
+        tx.commit().await?;
+        drop(writes);
+
+        if (batch.len() as i64) < DELETE_BATCH_SIZE {
+            return Ok(stripped);
+        }
+    }
+}
+
+/// Bytes in pages that hold data, i.e. the database size minus its free pages
+async fn used_bytes(pool: &SqlitePool) -> Result<i64, Error> {
+    let used = sqlx::query_scalar(
+        "SELECT (page_count - freelist_count) * page_size FROM pragma_page_count, pragma_freelist_count, pragma_page_size",
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(used)
+}
+
+/// Checkpoint the WAL and VACUUM when the configured interval has passed since the last run
+async fn maybe_vacuum(pool: &SqlitePool, settings: &RetentionSettings, now: DateTime<Utc>) -> Result<bool, Error> {
+    let Some(interval_hours) = settings.vacuum_interval_hours.filter(|hours| *hours > 0) else {
+        return Ok(false);
+    };
+
+    let due = settings
+        .last_vacuum_at
+        .as_ref()
+        .and_then(|last| last.to_utc().ok())
+        .map(|last| Duration::try_hours(interval_hours).is_some_and(|interval| now - last >= interval))
+        .unwrap_or(true);
+
+    if !due {
+        return Ok(false);
+    }
+
+    // On its own connection, so the pool's connections stay free for ingestion while it runs
+    let mut connection = pool.connect_options().as_ref().clone().connect().await?;
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&mut connection).await?;
+    sqlx::query("VACUUM").execute(&mut connection).await?;
+    connection.close().await?;
+    sqlx::query("UPDATE retention_settings SET last_vacuum_at = ?1 WHERE id = 1")
+        .bind(now.timestamp_millis())
+        .execute(pool)
+        .await?;
+
+    Ok(true)
+}
+
+/// Apply the retention policy. Called by the scheduler on every run.
+pub async fn enforce(pool: &SqlitePool, now: DateTime<Utc>) -> Result<RetentionReport, Error> {
+    let settings = get_retention_settings(pool).await?;
+    let used_before = used_bytes(pool).await?;
+
+    let logs_deleted = match cutoff(now, settings.raw_log_days) {
+        Some(cutoff) => {
+            // Prompts that produced no saved report, e.g. from the CLI, are copies of raw activity
+            delete_in_batches(pool, "ai_requests", "report_id IS NULL AND created_at < ?1", cutoff).await?;
+            delete_in_batches(pool, "activity_logs", "timestamp < ?1", cutoff).await?
+        }
+        None => 0,
+    };
+
+    let reports_deleted = match cutoff(now, settings.report_days) {
+        Some(cutoff) => {
+            // The prompts behind reports hold raw activity, so they expire with the reports
+            delete_in_batches(pool, "ai_requests", "created_at < ?1", cutoff).await?;
+            delete_in_batches(pool, "ai_reports", "generated_at < ?1", cutoff).await?
+        }
+        None => 0,
+    };
+
+    let payloads_stripped = if settings.strip_payload_after_summary {
+        strip_payloads(pool).await?
+    } else {
+        0
+    };
+
+    // Deleted rows and shrunk payloads leave free pages behind, which VACUUM then returns to the disk
+    let estimated_bytes_freed = (used_before - used_bytes(pool).await?).max(0);
+
+    Ok(RetentionReport {
+        logs_deleted,
+        reports_deleted,
+        payloads_stripped,
+        estimated_bytes_freed,
+        vacuumed: maybe_vacuum(pool, &settings, now).await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{db, git};
+
+    #[test]
+    fn stripping_keeps_identifying_keys_only() {
+        let payload = json!({
+            "type": "commit",
+            "repository": "/src/app",
+            "hash": "abc123",
+            "message": "Rotate the API key",
+            "files": [{ "path": "secrets.env" }],
+        });
+        let stored = stripped_payload(&payload.to_string()).unwrap();
+        let stripped: Value = serde_json::from_str(&crypto::open(&stored).unwrap()).unwrap();
+
+        assert_eq!(stripped, json!({ "type": "commit", "repository": "/src/app", "hash": "abc123" }));
+        assert_eq!(git::event_id(&stripped), git::event_id(&payload));
+        assert_eq!(crypto::open(&stripped_payload("not json").unwrap()).unwrap(), "{}");
+    }
+
+    #[tokio::test]
+    async fn enforce_strips_processed_payloads_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        sqlx::query("UPDATE retention_settings SET strip_payload_after_summary = 1, vacuum_interval_hours = NULL")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO activity_logs (source, payload, timestamp, is_processed) VALUES \
+             ('terminal', '{\"command\":\"ls\"}', 1714557600000, 1), ('terminal', '{\"command\":\"pwd\"}', 1714557600000, 0)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let report = enforce(&pool, Utc::now()).await.unwrap();
+        assert_eq!(report.payloads_stripped, 1);
+        assert!(!report.vacuumed);
+
+        let payloads: Vec<String> = sqlx::query_scalar("SELECT payload FROM activity_logs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(payloads, ["{}", "{\"command\":\"pwd\"}"]);
+
+        assert_eq!(enforce(&pool, Utc::now()).await.unwrap().payloads_stripped, 0);
+    }
+
+    #[tokio::test]
+    async fn enforce_expires_unlinked_prompts_with_raw_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        sqlx::query(
+            "UPDATE retention_settings SET raw_log_days = 7, report_days = NULL, \
+             strip_payload_after_summary = 0, vacuum_interval_hours = NULL",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let old = (Utc::now() - Duration::days(30)).timestamp_millis();
+        for report_id in [None, Some(1)] {
+            sqlx::query(
+                "INSERT INTO ai_requests (report_id, created_at, model, provider_host, prompt_sha256, latency_ms, status) \
+                 VALUES (?1, ?2, 'm', 'localhost', 'x', 1, 'ok')",
+            )
+            .bind(report_id)
+            .bind(old)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        enforce(&pool, Utc::now()).await.unwrap();
+
+        let remaining: Vec<Option<i64>> = sqlx::query_scalar("SELECT report_id FROM ai_requests")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(remaining, [Some(1)]);
+    }
+
+    #[test]
+    fn periods_too_long_to_reach_back_to_are_unlimited() {
+        let now = Utc::now();
+        assert_eq!(cutoff(now, Some(1)), Some((now - Duration::days(1)).timestamp_millis()));
+        assert_eq!(cutoff(now, Some(0)), None);
+        assert_eq!(cutoff(now, Some(i64::MAX)), None);
+        assert_eq!(cutoff(now, Some(99_999_999_999)), None);
+    }
+
+    #[tokio::test]
+    async fn enforce_skips_undecryptable_payloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        sqlx::query(
+            "UPDATE retention_settings SET strip_payload_after_summary = 1, \
+             vacuum_interval_hours = 9223372036854775807, last_vacuum_at = '2024-05-01T00:00:00Z'",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO activity_logs (source, payload, timestamp, is_processed) VALUES \
+             ('terminal', 'enc:v1:not-a-ciphertext', 1714557600000, 1), ('terminal', '{\"command\":\"ls\"}', 1714557600000, 1)",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let report = enforce(&pool, Utc::now()).await.unwrap();
+        assert_eq!(report.payloads_stripped, 1);
+        assert!(!report.vacuumed);
+
+        let stripped: Vec<(i64, bool)> = sqlx::query_as("SELECT id, payload_stripped FROM activity_logs ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stripped, [(1, false), (2, true)]);
+        let quarantined: Vec<i64> = sqlx::query_scalar("SELECT row_id FROM quarantined_rows")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(quarantined, [1]);
+    }
+}
@@ -0,0 +1,220 @@
+const MAX_DIGITS: usize = 19;
+const MAX_GROUPS: usize = 5;
+
+/// Digit group layouts printed on cards: 4-4-4-4 (most), 4-6-5 (Amex), 4-6-4 (Diners), 4-4-4-4-3 (19 digits)
+const CARD_GROUPINGS: &[&[usize]] = &[&[4, 4, 4, 4], &[4, 6, 5], &[4, 6, 4], &[4, 4, 4, 4, 3]];
+
+fn luhn_valid(digits: &[u8]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| {
+            let digit = digit as u32;
+            if i % 2 == 1 {
+                let doubled = digit * 2;
+                if doubled > 9 { doubled - 9 } else { doubled }
+            } else {
+                digit
+            }
+        })
+        .sum();
+
+    sum.is_multiple_of(10)
+}
+
+/// Numeric value of the first `len` digits
+fn prefix(digits: &[u8], len: usize) -> u32 {
+    digits.iter().take(len).fold(0, |acc, &digit| acc * 10 + digit as u32)
+}
+
+/// True when the issuer identification number and length belong to a known card network
+fn known_issuer(digits: &[u8]) -> bool {
+    let len = digits.len();
+    let p1 = prefix(digits, 1);
+    let p2 = prefix(digits, 2);
+    let p3 = prefix(digits, 3);
+    let p4 = prefix(digits, 4);
+    let p6 = prefix(digits, 6);
+
+    // Visa
+    (p1 == 4 && matches!(len, 13 | 16 | 19))
+        // Mastercard
+        || (((51..=55).contains(&p2) || (2221..=2720).contains(&p4)) && len == 16)
+        // American Express
+        || (matches!(p2, 34 | 37) && len == 15)
+        // Discover
+        || ((p4 == 6011 || (644..=649).contains(&p3) || p2 == 65 || (622126..=622925).contains(&p6))
+            && (16..=19).contains(&len))
+        // Diners Club
+        || (((300..=305).contains(&p3) || matches!(p2, 36 | 38 | 39)) && (14..=19).contains(&len))
+        // JCB
+        || ((3528..=3589).contains(&p4) && (16..=19).contains(&len))
+        // UnionPay
+        || (p2 == 62 && (16..=19).contains(&len))
+        // Maestro
+        || (matches!(p4, 5018 | 5020 | 5038 | 5893 | 6304 | 6759 | 6761 | 6762 | 6763) && (13..=19).contains(&len))
+}
+
+/// Separated numbers must use one separator throughout and a layout found on real cards
+fn plausible_grouping(candidate: &str) -> bool {
+    let separators: Vec<char> = candidate.chars().filter(|c| !c.is_ascii_digit()).collect();
+    if separators.is_empty() {
+        return true;
+    }
+    if separators.iter().any(|&c| c != separators[0]) {
+        return false;
+    }
+
+    let groups: Vec<usize> = candidate.split(separators[0]).map(str::len).collect();
+    CARD_GROUPINGS.contains(&groups.as_slice())
+}
+
+/// Whether a 13–19 digit candidate (optionally split by spaces or dashes) is a valid payment card number
+pub(super) fn is_card_number(candidate: &str) -> bool {
+    let digits: Vec<u8> = candidate
+        .chars()
+        .filter_map(|c| c.to_digit(10).map(|digit| digit as u8))
+        .collect();
+
+    (13..=MAX_DIGITS).contains(&digits.len())
+        && plausible_grouping(candidate)
+        && known_issuer(&digits)
+        && luhn_valid(&digits)
+}
+
+/// Redact the card numbers in a run of digit groups, e.g. `4111 1111 1111 1111 12` where an expiry
+/// month follows the number. Every span of whole groups holding 13–19 digits is tried, longest first.
+/// Returns `None` when the run holds no card number.
+pub(super) fn redact_card_numbers(candidate: &str, replacement: &str) -> Option<String> {
+    let groups: Vec<(usize, usize)> = candidate
+        .match_indices(|c: char| c.is_ascii_digit())
+        .fold(Vec::new(), |mut groups: Vec<(usize, usize)>, (at, _)| {
+            match groups.last_mut() {
+                Some((_, end)) if *end == at => *end = at + 1,
+                _ => groups.push((at, at + 1)),
+            }
+            groups
+        });
+
+    let mut redacted = String::new();
+    let mut copied = 0;
+    let mut first = 0;
+    while first < groups.len() {
+        // Spans past 19 digits or the 5 groups of the longest layout can't be cards, so each
+        // group starts at most a handful of candidates and long digit runs stay linear
+        let mut digits = 0;
+        let spans: Vec<usize> = (first..groups.len())
+            .take(MAX_GROUPS)
+            .take_while(|&last| {
+                digits += groups[last].1 - groups[last].0;
+                digits <= MAX_DIGITS
+            })
+            .collect();
+        let card = spans
+            .into_iter()
+            .rev()
+            .find(|&last| is_card_number(&candidate[groups[first].0..groups[last].1]));
+
+        match card {
+            Some(last) => {
+                redacted.push_str(&candidate[copied..groups[first].0]);
+                redacted.push_str(replacement);
+                copied = groups[last].1;
+                first = last + 1;
+            }
+            None => first += 1,
+        }
+    }
+
+    (copied > 0).then(|| redacted + &candidate[copied..])
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::super::{rules::RuleSet, RedactionOptions, Redactor};
+
+    fn redact(text: &str) -> String {
+        Redactor::new(RedactionOptions::default(), &RuleSet::default(), None).redact_text(text, None).0
+    }
+
+    /// Append the Luhn check digit to `body`
+    fn with_check_digit(mut body: Vec<u8>) -> Vec<u8> {
+        let check = (0..10).find(|&check| {
+            let mut digits = body.clone();
+            digits.push(check);
+            super::luhn_valid(&digits)
+        });
+        body.push(check.unwrap());
+        body
+    }
+
+    /// A Luhn-valid Visa, Mastercard or Amex number and the group layout printed on such cards
+    fn card() -> impl Strategy<Value = (Vec<u8>, &'static [usize])> {
+        let issuer = prop_oneof![
+            Just((vec![4], 16, &[4, 4, 4, 4][..])),
+            (51u8..=55).prop_map(|p| (vec![p / 10, p % 10], 16, &[4, 4, 4, 4][..])),
+            prop_oneof![Just(34u8), Just(37u8)].prop_map(|p| (vec![p / 10, p % 10], 15, &[4, 6, 5][..])),
+        ];
+
+        issuer.prop_flat_map(|(prefix, len, groups)| {
+            proptest::collection::vec(0u8..10, len - prefix.len() - 1).prop_map(move |rest| {
+                let body = prefix.iter().copied().chain(rest).collect();
+                (with_check_digit(body), groups)
+            })
+        })
+    }
+
+    fn format_card(digits: &[u8], groups: &[usize], separator: &str) -> String {
+        let mut text = Vec::new();
+        let mut start = 0;
+        for len in groups {
+            text.push(digits[start..start + len].iter().map(|d| d.to_string()).collect::<String>());
+            start += len;
+        }
+        text.join(separator)
+    }
+
+    #[test]
+    fn redacts_cards_followed_by_an_expiry_date() {
+        assert_eq!(redact("4111 1111 1111 1111 12/25"), "[CC_REDACTED] 12/25");
+        assert_eq!(redact("card 4111-1111-1111-1111 exp 12 25"), "card [CC_REDACTED] exp 12 25");
+        assert_eq!(redact("ref 12 378282246310005"), "ref 12 [CC_REDACTED]");
+    }
+
+    #[test]
+    fn long_digit_runs_are_scanned_quickly() {
+        let run = (0..20_000).map(|i| (i % 10).to_string()).collect::<Vec<_>>().join(" ");
+        let started = std::time::Instant::now();
+        redact(&run);
+        assert!(started.elapsed() < std::time::Duration::from_secs(2), "took {:?}", started.elapsed());
+
+        let glued = "7".repeat(20_000);
+        assert_eq!(redact(&glued), glued);
+    }
+
+    #[test]
+    fn keeps_numbers_that_are_not_cards() {
+        for text in ["4111 1111 1111 1112", "1234 5678 9012 3456 7890", "order 2024 0611 1530 42"] {
+            assert_eq!(redact(text), text);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn luhn_valid_cards_are_always_redacted(
+            (digits, groups) in card(),
+            separator in prop_oneof![Just(" "), Just("-"), Just("")],
+            before in "|[a-z]{1,8}[ :=]|[0-9]{1,4} ",
+            after in "| [0-9]{1,4}| [0-9]{2}/[0-9]{2}| [a-z]{1,8}|[.,;)]",
+        ) {
+            let number = format_card(&digits, groups, separator);
+            let redacted = redact(&format!("{before}{number}{after}"));
+
+            prop_assert!(redacted.contains("[CC_REDACTED]"), "{redacted}");
+            prop_assert!(!redacted.contains(&number), "{redacted}");
+        }
+    }
+}
@@ -1,7 +1,17 @@
+use std::net::{IpAddr, Ipv6Addr};
+
 use lazy_static::lazy_static;
 use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+mod cards;
+mod detectors;
+pub mod pseudonym;
+pub mod rules;
+
+use rules::{RuleSet, Scope};
+
 /// Helper: returns true when `ip` is a valid IPv4 string and is *public* (not localhost or private ranges).
 fn is_public_ipv4(ip: &str) -> bool {
     // Parse octets
@@ -50,6 +60,58 @@ fn is_public_ipv4(ip: &str) -> bool {
     true
 }
 
+/// Returns true when `ip` is a globally routable IPv6 address. IPv4-mapped addresses are judged by their IPv4 part.
+fn is_public_ipv6(ip: &Ipv6Addr) -> bool {
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(&v4.to_string());
+    }
+
+    let segments = ip.segments();
+    let unique_local = (segments[0] & 0xfe00) == 0xfc00; // fc00::/7
+    let link_local = (segments[0] & 0xffc0) == 0xfe80; // fe80::/10
+    let documentation = segments[0] == 0x2001 && segments[1] == 0x0db8; // 2001:db8::/32
+
+    !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local || documentation)
+}
+
+/// Find the IPv6 address in a candidate match. The candidate may start with a label glued on by a
+/// colon, as in `ip:2001:4860::8888`, so when it doesn't parse whole each suffix after a colon is
+/// tried, longest first. Returns the byte offset of the address and the address.
+fn find_ipv6(candidate: &str) -> Option<(usize, Ipv6Addr)> {
+    let starts = std::iter::once(0).chain(
+        candidate
+            .match_indices(':')
+            .flat_map(|(colon, _)| [colon, colon + 1]),
+    );
+
+    starts
+        .filter(|&start| start < candidate.len())
+        .find_map(|start| candidate[start..].parse().ok().map(|address| (start, address)))
+}
+
+/// Returns true when `ip` is globally routable, i.e. neither loopback nor in a private or local range
+pub fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_public_ipv4(&v4.to_string()),
+        IpAddr::V6(v6) => is_public_ipv6(v6),
+    }
+}
+
+/// Replace the user-specific part of a home directory path with `~`
+pub fn normalize_home_path(path: &str) -> String {
+    HOME_PATH_PATTERN.replace_all(path, "$1~").into_owned()
+}
+
+/// Expand a leading `~`, so paths stored with [`normalize_home_path`] and globs written
+/// like the paths users see can be matched against the filesystem
+pub fn expand_home(path: &str) -> String {
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Ok(home)) => format!("{}{}", home.trim_end_matches(['/', '\\']), rest),
+        _ => path.to_string(),
+    }
+}
+
 lazy_static! {
     // API Keys, tokens, secrets (case-insensitive)
     static ref API_KEY_PATTERN: Regex = Regex::new(
@@ -66,67 +128,402 @@ lazy_static! {
         r#"\b(?:[0-9]{1,3}\.){3}[0-9]{1,3}\b"#
     ).unwrap();
 
+    // Anything that could be an IPv6 address; candidates are validated by parsing them with `Ipv6Addr`
+    static ref IPV6_CANDIDATE_PATTERN: Regex = Regex::new(
+        r#"[0-9A-Za-z_.]*:[0-9A-Za-z_:.]*"#
+    ).unwrap();
+
+    // Home directories: /home/<user>, /Users/<user>, C:\Users\<user> (only at the start of a path)
+    static ref HOME_PATH_PATTERN: Regex = Regex::new(
+        r#"(^|[\s"'=:(\[,])(?:/home/[^/\s"'`]+|/Users/[^/\s"'`]+|(?i:[A-Z]:[\\/]Users[\\/])[^\\/\s"'`]+)"#
+    ).unwrap();
+
     // Private keys — allow dot to match newlines with (?s) and use non-greedy match
     static ref PRIVATE_KEY_PATTERN: Regex = Regex::new(
         r"(?s)-----BEGIN [A-Z ]+PRIVATE KEY-----.+?-----END [A-Z ]+PRIVATE KEY-----"
     ).unwrap();
 
-    // Credit card numbers (simple pattern)
+    // `Authorization: Bearer <token>` style credentials, e.g. in curl -H arguments
+    static ref AUTH_SCHEME_PATTERN: Regex = Regex::new(
+        r#"(?i)\b(bearer|authorization:\s*basic)\s+([A-Za-z0-9_\-./+=~]{8,})"#
+    ).unwrap();
+
+    // Credentials passed as URL query parameters (?token=..., &access_token=...)
+    static ref URL_SECRET_PARAM_PATTERN: Regex = Regex::new(
+        r#"(?i)([?&#](?:access_token|refresh_token|id_token|token|api[_-]?key|secret|password|passwd|pwd|sig|signature)=)[^&#\s"']+"#
+    ).unwrap();
+
+    // Parameters whose names are also used for ordinary values (?key=Enter, ?code=US),
+    // only redacted when the value looks like a token
+    static ref URL_GENERIC_PARAM_PATTERN: Regex = Regex::new(
+        r#"(?i)([?&#](?:key|code|auth|session)=)([^&#\s"']+)"#
+    ).unwrap();
+
+    // Card number candidates: runs of digit groups separated by single spaces or dashes, holding
+    // at least 13 digits. The card numbers inside are found by issuer ranges and the Luhn checksum in code.
     static ref CC_PATTERN: Regex = Regex::new(
-        r#"\b\d{4}[\s-]?\d{4}[\s-]?\d{4}[\s-]?\d{4}\b"#
+        r#"\b\d(?:[ -]?\d){12,}\b"#
     ).unwrap();
 }
 
-pub fn sanitize_text(text: &str) -> String {
-    let mut sanitized = text.to_string();
+/// Which optional detectors the redaction stage runs. Secrets, private keys and card numbers are always redacted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RedactionOptions {
+    pub redact_emails: bool,
+    pub redact_ip_addresses: bool,
+}
 
-    // Replace API keys keeping the captured key name ($1)
-    sanitized = API_KEY_PATTERN
-        .replace_all(&sanitized, "$1: [REDACTED_KEY]")
-        .to_string();
+impl Default for RedactionOptions {
+    fn default() -> Self {
+        Self {
+            redact_emails: true,
+            redact_ip_addresses: true,
+        }
+    }
+}
 
-    // Emails
-    sanitized = EMAIL_PATTERN
-        .replace_all(&sanitized, "[EMAIL_REDACTED]")
-        .to_string();
+/// At least 16 characters mixing letters and digits, without the spaces or dots of ordinary values
+fn looks_like_token(value: &str) -> bool {
+    value.len() >= 16
+        && value.chars().any(|c| c.is_ascii_alphabetic())
+        && value.chars().any(|c| c.is_ascii_digit())
+        && !value.contains(['.', '+'])
+}
 
-    // IPv4s: use closure to check whether the match is public (only redact public IPv4s)
-    sanitized = IPV4_PATTERN
-        .replace_all(&sanitized, |caps: &Captures| {
-            let ip = caps.get(0).map(|m| m.as_str()).unwrap_or("");
-            if is_public_ipv4(ip) {
-                "[IP_REDACTED]".to_string()
-            } else {
-                // return original (keep localhost/private addresses untouched)
-                ip.to_string()
+/// Replace every match of `pattern` for which `replacement` returns `Some`, counting the replacements.
+/// Matches covered by an allow rule are kept.
+fn replace_counted<F>(pattern: &Regex, text: &str, count: &mut usize, allowed: &dyn Fn(&str) -> bool, replacement: F) -> String
+where
+    F: Fn(&Captures) -> Option<String>,
+{
+    pattern
+        .replace_all(text, |caps: &Captures| match replacement(caps) {
+            Some(replaced) if !allowed(&caps[0]) => {
+                *count += 1;
+                replaced
             }
+            _ => caps[0].to_string(),
         })
-        .to_string();
-
-    // Private keys
-    sanitized = PRIVATE_KEY_PATTERN
-        .replace_all(&sanitized, "[PRIVATE_KEY_REDACTED]")
-        .to_string();
-
-    // Credit cards
-    sanitized = CC_PATTERN
-        .replace_all(&sanitized, "[CC_REDACTED]")
-        .to_string();
-
-    sanitized
-}
-
-pub fn sanitize_json(value: &Value) -> Value {
-    match value {
-        Value::String(s) => Value::String(sanitize_text(s)),
-        Value::Array(arr) => Value::Array(
-            arr.iter().map(|v| sanitize_json(v)).collect::<Vec<Value>>()
-        ),
-        Value::Object(obj) => Value::Object(
-            obj.iter()
-                .map(|(k, v)| (k.clone(), sanitize_json(v)))
-                .collect::<serde_json::Map<String, Value>>()
-        ),
-        other => other.clone(),
+        .to_string()
+}
+
+/// Runs the built-in detectors plus the user rules that apply to one event source
+pub struct Redactor<'a> {
+    options: RedactionOptions,
+    rules: &'a RuleSet,
+    source: Option<&'a str>,
+}
+
+impl<'a> Redactor<'a> {
+    pub fn new(options: RedactionOptions, rules: &'a RuleSet, source: Option<&'a str>) -> Self {
+        Self { options, rules, source }
+    }
+
+    /// Redact sensitive values in `text`, returning the redacted text and the number of replacements.
+    /// `field` is the payload key the text was found under, if any.
+    pub fn redact_text(&self, text: &str, field: Option<&str>) -> (String, usize) {
+        let scope = Scope { source: self.source, field };
+        let allowed = |matched: &str| self.rules.is_allowed(scope, matched);
+        let mut count = 0;
+
+        // User rules first, so e.g. internal hostnames are replaced before the generic patterns see them
+        let mut redacted = text.to_string();
+        for rule in self.rules.redactions(scope) {
+            redacted = replace_counted(&rule.regex, &redacted, &mut count, &allowed, |_| {
+                Some(rule.replacement.clone())
+            });
+        }
+
+        // Private keys before API keys so their base64 body isn't picked apart by the other patterns
+        redacted = replace_counted(&PRIVATE_KEY_PATTERN, &redacted, &mut count, &allowed, |_| {
+            Some("[PRIVATE_KEY_REDACTED]".to_string())
+        });
+
+        // Cloud keys, tokens, JWTs, URL passwords and secret CLI flags, labelled by kind
+        redacted = detectors::redact_known_secrets(&redacted, &mut count, &allowed);
+
+        // Query parameters before keyword keys, so `?access_token=…` keeps the shape of a URL
+        redacted = replace_counted(&URL_SECRET_PARAM_PATTERN, &redacted, &mut count, &allowed, |caps| {
+            Some(format!("{}[REDACTED]", &caps[1]))
+        });
+        redacted = replace_counted(&URL_GENERIC_PARAM_PATTERN, &redacted, &mut count, &allowed, |caps| {
+            looks_like_token(&caps[2]).then(|| format!("{}[REDACTED]", &caps[1]))
+        });
+
+        // Replace API keys keeping the captured key name ($1)
+        redacted = replace_counted(&API_KEY_PATTERN, &redacted, &mut count, &allowed, |caps| {
+            Some(format!("{}: [REDACTED_KEY]", &caps[1]))
+        });
+
+        redacted = replace_counted(&AUTH_SCHEME_PATTERN, &redacted, &mut count, &allowed, |caps| {
+            Some(format!("{} [REDACTED_KEY]", &caps[1]))
+        });
+
+
+        if self.options.redact_emails {
+            redacted = replace_counted(&EMAIL_PATTERN, &redacted, &mut count, &allowed, |_| {
+                Some("[EMAIL_REDACTED]".to_string())
+            });
+        }
+
+        // IPv6 and IPv4: only redact public addresses, keep localhost/private ones untouched
+        if self.options.redact_ip_addresses {
+            redacted = replace_counted(&IPV6_CANDIDATE_PATTERN, &redacted, &mut count, &allowed, |caps| {
+                // Drop a trailing sentence dot before parsing, and keep it and any label in the output
+                let candidate = caps[0].trim_end_matches('.');
+                let (start, address) = find_ipv6(candidate)?;
+                is_public_ipv6(&address)
+                    .then(|| format!("{}[IP_REDACTED]{}", &candidate[..start], &caps[0][candidate.len()..]))
+            });
+
+            redacted = replace_counted(&IPV4_PATTERN, &redacted, &mut count, &allowed, |caps| {
+                is_public_ipv4(&caps[0]).then(|| "[IP_REDACTED]".to_string())
+            });
+        }
+
+        // Usernames in home directory paths
+        redacted = replace_counted(&HOME_PATH_PATTERN, &redacted, &mut count, &allowed, |caps| {
+            Some(format!("{}~", &caps[1]))
+        });
+
+        // Credit cards
+        redacted = replace_counted(&CC_PATTERN, &redacted, &mut count, &allowed, |caps| {
+            cards::redact_card_numbers(&caps[0], "[CC_REDACTED]")
+        });
+
+        redacted = detectors::redact_high_entropy_tokens(&redacted, &mut count, &allowed);
+
+        (redacted, count)
+    }
+
+    /// Redact every string inside `value`, returning the redacted value and the number of replacements
+    pub fn redact_json(&self, value: &Value) -> (Value, usize) {
+        self.redact_json_field(value, None)
+    }
+
+    fn redact_json_field(&self, value: &Value, field: Option<&str>) -> (Value, usize) {
+        match value {
+            Value::String(s) => {
+                let (redacted, count) = self.redact_text(s, field);
+                (Value::String(redacted), count)
+            }
+            Value::Array(arr) => {
+                let mut count = 0;
+                let items = arr
+                    .iter()
+                    .map(|v| {
+                        let (redacted, n) = self.redact_json_field(v, field);
+                        count += n;
+                        redacted
+                    })
+                    .collect::<Vec<Value>>();
+                (Value::Array(items), count)
+            }
+            Value::Object(obj) => {
+                let mut count = 0;
+                let entries = obj
+                    .iter()
+                    .map(|(k, v)| {
+                        let (redacted, n) = self.redact_json_field(v, Some(k));
+                        count += n;
+                        (k.clone(), redacted)
+                    })
+                    .collect::<serde_json::Map<String, Value>>();
+                (Value::Object(entries), count)
+            }
+            other => (other.clone(), 0),
+        }
+    }
+}
+
+/// Redact `text` with the default options and `rules`, including those scoped to `source`
+pub fn sanitize_text(rules: &RuleSet, text: &str, source: Option<&str>) -> String {
+    Redactor::new(RedactionOptions::default(), rules, source).redact_text(text, None).0
+}
+
+/// Redact `value` with the default options and `rules`, including those scoped to `source`
+pub fn sanitize_json(rules: &RuleSet, value: &Value, source: Option<&str>) -> Value {
+    Redactor::new(RedactionOptions::default(), rules, source).redact_json(value).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redact(text: &str) -> String {
+        Redactor::new(RedactionOptions::default(), &RuleSet::default(), None).redact_text(text, None).0
+    }
+
+    #[test]
+    fn redacts_secret_url_parameters() {
+        assert_eq!(
+            redact("https://example.com/cb?access_token=abc&state=1"),
+            "https://example.com/cb?access_token=[REDACTED]&state=1"
+        );
+        assert_eq!(
+            redact("https://example.com/cb?code=4f9Qz81kLmN02pXy&state=1"),
+            "https://example.com/cb?code=[REDACTED]&state=1"
+        );
+        assert_eq!(
+            redact("https://example.com/app?session=s3ss10nAbcdef12345"),
+            "https://example.com/app?session=[REDACTED]"
+        );
+    }
+
+    #[test]
+    fn keeps_ordinary_values_of_generic_parameters() {
+        for url in [
+            "https://developer.mozilla.org/docs?key=ArrowDown",
+            "https://example.com/countries?code=US",
+            "https://example.com/login?auth=google",
+            "https://example.com/talks?session=keynote-day-2",
+        ] {
+            assert_eq!(redact(url), url);
+        }
+    }
+
+    #[test]
+    fn redacts_ipv6_addresses_behind_labels() {
+        assert_eq!(redact("ip:2001:4860::8888"), "ip:[IP_REDACTED]");
+        assert_eq!(redact("server:2606:4700::1111."), "server:[IP_REDACTED].");
+        assert_eq!(redact("dns 2606:4700:4700::1111"), "dns [IP_REDACTED]");
+        assert_eq!(redact("link fe80::1 and ::1"), "link fe80::1 and ::1");
+        assert_eq!(redact("use std::fs::read at 12:34:56"), "use std::fs::read at 12:34:56");
+    }
+
+    mod properties {
+        use proptest::prelude::*;
+
+        use super::redact;
+
+        /// Words around a value, kept apart from it by spaces
+        fn words() -> impl Strategy<Value = String> {
+            "[a-z]{0,8}( [a-z]{1,8})?"
+        }
+
+        /// `value` between random words is replaced, and `value` no longer appears
+        fn assert_redacted(before: &str, value: &str, after: &str, placeholder: &str) -> Result<(), TestCaseError> {
+            let redacted = redact(&format!("{before} {value} {after}"));
+            prop_assert!(redacted.contains(placeholder), "{redacted}");
+            prop_assert!(!redacted.contains(value), "{redacted}");
+            Ok(())
+        }
+
+        /// `value` between random words is left exactly as it is
+        fn assert_kept(before: &str, value: &str, after: &str) -> Result<(), TestCaseError> {
+            let text = format!("{before} {value} {after}");
+            prop_assert_eq!(redact(&text), text);
+            Ok(())
+        }
+
+        proptest! {
+            #[test]
+            fn emails_are_redacted(
+                before in words(), after in words(),
+                email in "[a-z][a-z0-9._]{0,10}@[a-z]{2,10}\\.(com|org|io)",
+            ) {
+                assert_redacted(&before, &email, &after, "[EMAIL_REDACTED]")?;
+            }
+
+            #[test]
+            fn mentions_and_decorators_are_not_emails(before in words(), after in words(), name in "@[A-Za-z]{2,12}") {
+                assert_kept(&before, &name, &after)?;
+            }
+
+            #[test]
+            fn public_ipv4_addresses_are_redacted(
+                before in words(), after in words(),
+                first in 11u8..=99, rest in proptest::array::uniform3(0u8..=255),
+            ) {
+                let address = format!("{first}.{}.{}.{}", rest[0], rest[1], rest[2]);
+                assert_redacted(&before, &address, &after, "[IP_REDACTED]")?;
+            }
+
+            #[test]
+            fn private_ipv4_addresses_and_versions_are_kept(
+                before in words(), after in words(),
+                octets in proptest::array::uniform3(0u8..=255),
+                private in prop_oneof![Just("10"), Just("127"), Just("192.168")],
+            ) {
+                let address = match private {
+                    "192.168" => format!("192.168.{}.{}", octets[0], octets[1]),
+                    prefix => format!("{prefix}.{}.{}.{}", octets[0], octets[1], octets[2]),
+                };
+                assert_kept(&before, &address, &after)?;
+                assert_kept(&before, &format!("v{}.{}.{}", octets[0], octets[1], octets[2]), &after)?;
+            }
+
+            #[test]
+            fn public_ipv6_addresses_are_redacted(
+                before in words(), after in words(),
+                label in "(|[g-z]{1,8}:)", segments in proptest::array::uniform2(1u16..),
+            ) {
+                let address = format!("2606:4700:{:x}::{:x}", segments[0], segments[1]);
+                let redacted = redact(&format!("{before} {label}{address} {after}"));
+                prop_assert!(redacted.contains("[IP_REDACTED]"), "{redacted}");
+                prop_assert!(!redacted.contains(&address), "{redacted}");
+            }
+
+            #[test]
+            fn local_ipv6_addresses_times_and_paths_are_kept(
+                before in words(), after in words(),
+                segment in 1u16.., time in (0u8..24, 0u8..60, 0u8..60), path in "[g-z]{2,8}::[g-z]{2,8}(::[g-z]{2,8})?",
+            ) {
+                assert_kept(&before, &format!("fe80::{segment:x}"), &after)?;
+                assert_kept(&before, &format!("::ffff:192.168.0.{}", segment % 256), &after)?;
+                assert_kept(&before, &format!("{:02}:{:02}:{:02}", time.0, time.1, time.2), &after)?;
+                assert_kept(&before, &path, &after)?;
+            }
+
+            #[test]
+            fn keyword_keys_are_redacted(
+                before in words(), after in words(),
+                keyword in "(api_key|apikey|token|secret|password)", separator in "(: |=| = )", value in "[A-Za-z0-9]{16,30}",
+            ) {
+                assert_redacted(&before, &format!("{keyword}{separator}{value}"), &after, "[REDACTED_KEY]")?;
+            }
+
+            #[test]
+            fn keywords_in_prose_are_kept(
+                before in words(), after in words(),
+                keyword in "(token|secret|password)", verb in "(expired|rotated|was reset|is missing)",
+            ) {
+                assert_kept(&before, &format!("the {keyword} {verb}"), &after)?;
+            }
+
+            #[test]
+            fn url_secret_parameters_are_redacted(
+                param in "(access_token|token|api_key|password|sig)", value in "[A-Za-z0-9_-]{4,40}",
+            ) {
+                let url = format!("https://example.com/cb?state=1&{param}={value}&page=2");
+                prop_assert_eq!(redact(&url), format!("https://example.com/cb?state=1&{param}=[REDACTED]&page=2"));
+            }
+
+            #[test]
+            fn ordinary_values_of_generic_parameters_are_kept(
+                param in "(key|code|auth|session)", value in "([A-Za-z]{1,20}|[A-Z]{2}|[a-z]{2,8}-[a-z0-9]{1,6})",
+            ) {
+                let url = format!("https://example.com/page?{param}={value}&page=2");
+                prop_assert_eq!(redact(&url), url);
+            }
+
+            #[test]
+            fn provider_credentials_are_redacted(
+                before in words(), after in words(),
+                aws in "(AKIA|ASIA)[A-Z0-9]{16}", github in "gh[pousr]_[A-Za-z0-9]{36}",
+            ) {
+                assert_redacted(&before, &aws, &after, "[AWS_KEY_REDACTED]")?;
+                assert_redacted(&before, &github, &after, "[GITHUB_TOKEN_REDACTED]")?;
+            }
+
+            #[test]
+            fn digests_and_ids_are_kept(
+                before in words(), after in words(),
+                digest in "[0-9a-f]{40}|[0-9a-f]{64}", uuid in "[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}",
+            ) {
+                assert_kept(&before, &digest, &after)?;
+                assert_kept(&before, &uuid, &after)?;
+            }
+        }
     }
 }
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+
+lazy_static! {
+    // Host part of a URL, after the scheme and optional credentials
+    static ref URL_HOST_PATTERN: Regex = Regex::new(
+        r"(?i)\b([a-z][a-z0-9+.-]*://(?:[^\s/@]*@)?)([a-z0-9-]+(?:\.[a-z0-9-]+)*)"
+    ).unwrap();
+
+    // Target of commands that connect to or look up a host, e.g. `ssh -A deploy@build01.corp`
+    static ref COMMAND_HOST_PATTERN: Regex = Regex::new(
+        r"(?i)\b((?:ssh|mosh|telnet|ping|dig|nslookup)\s+(?:-\S+\s+)*(?:[\w.-]+@)?)([a-z0-9-]+(?:\.[a-z0-9-]+)*)"
+    ).unwrap();
+}
+
+/// Replaces hostnames with stable placeholders (`host-1`, `host-2`, …). One instance is used per
+/// report, so the same host keeps the same placeholder throughout it without being revealed.
+#[derive(Debug, Default)]
+pub struct Pseudonymizer {
+    hosts: HashMap<String, String>,
+}
+
+impl Pseudonymizer {
+    pub fn host(&mut self, host: &str) -> String {
+        let key = host.to_ascii_lowercase();
+
+        // Loopback and IP literals identify nothing beyond what IP redaction already covers
+        if key == "localhost" || key.parse::<IpAddr>().is_ok() || !key.chars().any(|c| c.is_ascii_alphabetic()) {
+            return host.to_string();
+        }
+
+        let next = self.hosts.len() + 1;
+        self.hosts
+            .entry(key)
+            .or_insert_with(|| format!("host-{next}"))
+            .clone()
+    }
+
+    /// Pseudonymize hostnames in URLs and in the targets of network commands, plus bare mentions
+    /// of hosts already seen that way, e.g. in a page title
+    pub fn pseudonymize_text(&mut self, text: &str) -> String {
+        let mut replace = |caps: &Captures| format!("{}{}", &caps[1], self.host(&caps[2]));
+
+        let text = URL_HOST_PATTERN.replace_all(text, &mut replace).into_owned();
+        let text = COMMAND_HOST_PATTERN.replace_all(&text, &mut replace).into_owned();
+        self.replace_known_hosts(&text)
+    }
+
+    fn replace_known_hosts(&self, text: &str) -> String {
+        if self.hosts.is_empty() {
+            return text.to_string();
+        }
+
+        // Longest first, so `api.corp.example` wins over `corp.example`
+        let mut hosts: Vec<&str> = self.hosts.keys().map(String::as_str).collect();
+        hosts.sort_by_key(|host| std::cmp::Reverse(host.len()));
+        let alternatives: Vec<String> = hosts.iter().map(|host| regex::escape(host)).collect();
+        let pattern = Regex::new(&format!(r"(?i)\b(?:{})\b", alternatives.join("|"))).unwrap();
+
+        pattern
+            .replace_all(text, |caps: &Captures| self.hosts[&caps[0].to_ascii_lowercase()].clone())
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pseudonymizes_hosts_mentioned_after_their_url() {
+        let mut pseudonyms = Pseudonymizer::default();
+
+        assert_eq!(pseudonyms.pseudonymize_text("https://build01.corp.example/job/42"), "https://host-1/job/42");
+        assert_eq!(pseudonyms.pseudonymize_text("Job 42 - Build01.corp.example"), "Job 42 - host-1");
+        assert_eq!(pseudonyms.pseudonymize_text("ssh ops@db.corp.example"), "ssh ops@host-2");
+        assert_eq!(pseudonyms.pseudonymize_text("db.corp.example and corp.example"), "host-2 and corp.example");
+    }
+}
@@ -0,0 +1,206 @@
+use std::sync::{Arc, RwLock};
+
+use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
+use sqlx::SqlitePool;
+
+use crate::db::{list_redaction_rules, models::RedactionRule};
+
+/// Rule kinds as stored in `redaction_rules.kind`
+pub const KIND_REDACT: &str = "redact";
+pub const KIND_ALLOW: &str = "allow";
+
+// Keeps a pathological user pattern from blowing up memory on every ingest
+const RULE_SIZE_LIMIT: usize = 1 << 20;
+
+/// Where a value came from, used to pick the user rules that apply to it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scope<'a> {
+    pub source: Option<&'a str>,
+    pub field: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub id: i64,
+    pub regex: Regex,
+    pub replacement: String,
+    allow: bool,
+    source: Option<String>,
+    field: Option<String>,
+}
+
+impl CompiledRule {
+    pub fn compile(rule: &RedactionRule) -> Result<Self, String> {
+        let allow = match rule.kind.as_str() {
+            KIND_REDACT => false,
+            KIND_ALLOW => true,
+            other => return Err(format!("unknown rule kind '{other}', expected '{KIND_REDACT}' or '{KIND_ALLOW}'")),
+        };
+
+        if rule.pattern.is_empty() {
+            return Err("pattern must not be empty".to_string());
+        }
+
+        // An allow rule must match the whole detected value, so a rule for `alice@example.com`
+        // doesn't also exempt `alice@example.com.evil.io` or a token that merely contains it
+        let pattern = match allow {
+            true => format!("^(?:{})$", rule.pattern),
+            false => rule.pattern.clone(),
+        };
+
+        let regex = RegexBuilder::new(&pattern)
+            .size_limit(RULE_SIZE_LIMIT)
+            .build()
+            .map_err(|err| format!("invalid pattern: {err}"))?;
+
+        if !allow && regex.is_match("") {
+            return Err("pattern matches empty text".to_string());
+        }
+
+        let non_empty = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+
+        Ok(Self {
+            id: rule.id,
+            regex,
+            replacement: rule.replacement.clone(),
+            allow,
+            source: non_empty(&rule.source),
+            field: non_empty(&rule.field),
+        })
+    }
+
+    /// Unscoped rules apply everywhere; scoped ones only when the value's source/field matches
+    fn applies_to(&self, scope: Scope) -> bool {
+        let matches = |rule: &Option<String>, actual: Option<&str>| {
+            rule.as_deref().is_none_or(|expected| actual == Some(expected))
+        };
+
+        matches(&self.source, scope.source) && matches(&self.field, scope.field)
+    }
+}
+
+/// The enabled user rules, compiled once and swapped whenever the rules change
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    /// Compile `rules`, skipping invalid ones. Returns the set and an error message per skipped rule.
+    pub fn compile(rules: &[RedactionRule]) -> (Self, Vec<String>) {
+        let mut compiled = Vec::new();
+        let mut errors = Vec::new();
+
+        for rule in rules.iter().filter(|rule| rule.enabled) {
+            match CompiledRule::compile(rule) {
+                Ok(rule) => compiled.push(rule),
+                Err(err) => errors.push(format!("rule '{}': {}", rule.name, err)),
+            }
+        }
+
+        (Self { rules: compiled }, errors)
+    }
+
+    /// Copy of this set with `rule` added, replacing a rule with the same id
+    pub fn with_rule(&self, rule: CompiledRule) -> Self {
+        let mut rules: Vec<CompiledRule> = self
+            .rules
+            .iter()
+            .filter(|existing| rule.id == 0 || existing.id != rule.id)
+            .cloned()
+            .collect();
+        rules.push(rule);
+        Self { rules }
+    }
+
+    pub(super) fn redactions<'a>(&'a self, scope: Scope<'a>) -> impl Iterator<Item = &'a CompiledRule> + 'a {
+        self.rules
+            .iter()
+            .filter(move |rule| !rule.allow && rule.applies_to(scope))
+    }
+
+    /// True when an allow rule for `scope` matches all of `text`, which must then be left as is
+    pub(super) fn is_allowed(&self, scope: Scope, text: &str) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| rule.allow && rule.applies_to(scope) && rule.regex.is_match(text))
+    }
+}
+
+lazy_static! {
+    static ref RULE_SET: RwLock<Arc<RuleSet>> = RwLock::new(Arc::new(RuleSet::default()));
+}
+
+/// The rule set currently used by ingestion and the prompt formatter
+pub fn current() -> Arc<RuleSet> {
+    RULE_SET.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+/// Recompile the rules from the database and make them current. Returns the number of active rules.
+pub async fn reload(pool: &SqlitePool) -> Result<usize, sqlx::Error> {
+    let rules = list_redaction_rules(pool).await?;
+    let (rule_set, errors) = RuleSet::compile(&rules);
+
+    for err in errors {
+        eprintln!("⚠️  Skipping redaction {err}");
+    }
+
+    let active = rule_set.rules.len();
+    *RULE_SET.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(rule_set);
+    Ok(active)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sanitizer::{RedactionOptions, Redactor};
+
+    fn rule(kind: &str, pattern: &str, source: Option<&str>) -> RedactionRule {
+        RedactionRule {
+            id: 0,
+            name: pattern.to_string(),
+            pattern: pattern.to_string(),
+            replacement: "[INTERNAL]".to_string(),
+            kind: kind.to_string(),
+            source: source.map(str::to_string),
+            field: None,
+            enabled: true,
+        }
+    }
+
+    fn redact(rules: &RuleSet, source: Option<&str>, text: &str) -> String {
+        Redactor::new(RedactionOptions::default(), rules, source).redact_text(text, None).0
+    }
+
+    #[test]
+    fn allow_rules_must_match_the_whole_value() {
+        let (rules, errors) = RuleSet::compile(&[rule(KIND_ALLOW, r"alice@example\.com", None)]);
+        assert!(errors.is_empty());
+
+        assert!(rules.is_allowed(Scope::default(), "alice@example.com"));
+        assert!(!rules.is_allowed(Scope::default(), "alice@example.com.evil.io"));
+        assert!(!rules.is_allowed(Scope::default(), "xalice@example.com"));
+
+        assert_eq!(redact(&rules, None, "mail alice@example.com"), "mail alice@example.com");
+        assert_ne!(redact(&rules, None, "mail bob.alice@example.com"), "mail bob.alice@example.com");
+    }
+
+    #[test]
+    fn alternations_in_allow_rules_stay_anchored() {
+        let (rules, _) = RuleSet::compile(&[rule(KIND_ALLOW, "a@x.io|b@y.io", None)]);
+
+        assert!(rules.is_allowed(Scope::default(), "b@y.io"));
+        assert!(!rules.is_allowed(Scope::default(), "a@x.io.other.net"));
+        assert!(!rules.is_allowed(Scope::default(), "me.b@y.io"));
+    }
+
+    #[test]
+    fn scoped_rules_apply_only_to_their_source() {
+        let (rules, _) = RuleSet::compile(&[rule(KIND_REDACT, r"corp-db-\d+", Some("terminal"))]);
+
+        assert_eq!(redact(&rules, Some("terminal"), "ssh corp-db-7"), "ssh [INTERNAL]");
+        assert_eq!(redact(&rules, Some("browser"), "ssh corp-db-7"), "ssh corp-db-7");
+        assert_eq!(redact(&rules, None, "ssh corp-db-7"), "ssh corp-db-7");
+    }
+}
@@ -0,0 +1,433 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use anyhow::{anyhow, Context, Error};
+use base64::{
+    engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD},
+    Engine,
+};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::SqlitePool;
+
+pub const KEY_FILENAME: &str = "secret.key";
+
+/// Key written during a re-key until the re-encrypted data is committed
+pub const PENDING_KEY_FILENAME: &str = "secret.key.new";
+
+/// Bearer token scripts send to the local read API
+pub const API_TOKEN_FILENAME: &str = "api_token";
+
+/// Optional passphrase used to wrap the key file
+pub const PASSPHRASE_ENV: &str = "DEVCHRONICLE_KEY_PASSPHRASE";
+
+// Stored secrets look like `enc:v1:<base64(nonce || ciphertext)>`
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+#[cfg(not(test))]
+const KDF_ROUNDS: u32 = 600_000;
+// Unoptimized test builds would take seconds per derivation
+#[cfg(test)]
+const KDF_ROUNDS: u32 = 1_000;
+
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    version: u32,
+    /// Base64 key, or the key encrypted with the passphrase-derived key when `salt` is set
+    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+}
+
+/// Encrypts secrets such as the AI provider API key before they are written to SQLite.
+/// Clones share the same key, so a re-key is seen by every holder.
+#[derive(Clone)]
+pub struct SecretStore {
+    cipher: Arc<RwLock<Aes256Gcm>>,
+}
+
+fn passphrase_cipher(passphrase: &str, salt: &[u8]) -> Aes256Gcm {
+    let mut derived = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, KDF_ROUNDS, &mut derived);
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&derived))
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, Error> {
+    BASE64.decode(value).context("invalid base64 in key file")
+}
+
+/// Write `contents` to `path` so that only the current user can read it
+fn write_private_file(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    file.write_all(contents)?;
+
+    // `mode` only applies on creation, so tighten permissions of pre-existing files as well
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(())
+}
+
+fn write_key_file(path: &Path, key: &[u8], passphrase: Option<&str>) -> Result<(), Error> {
+    let key_file = match passphrase {
+        None => KeyFile {
+            version: 1,
+            key: BASE64.encode(key),
+            salt: None,
+            nonce: None,
+        },
+        Some(passphrase) => {
+            let salt: [u8; 16] = rand_bytes();
+            let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+            let wrapped = passphrase_cipher(passphrase, &salt)
+                .encrypt(&nonce, key)
+                .map_err(|_| anyhow!("failed to wrap key"))?;
+
+            KeyFile {
+                version: 1,
+                key: BASE64.encode(wrapped),
+                salt: Some(BASE64.encode(salt)),
+                nonce: Some(BASE64.encode(nonce)),
+            }
+        }
+    };
+
+    write_private_file(path, serde_json::to_string_pretty(&key_file)?.as_bytes())
+}
+
+fn rand_bytes<const N: usize>() -> [u8; N] {
+    use aes_gcm::aead::rand_core::RngCore;
+
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+impl SecretStore {
+    /// Load the key file from `data_dir`, creating it on first run.
+    ///
+    /// When `passphrase` is given the key file is stored wrapped with it, and an existing
+    /// unwrapped key file is upgraded in place. A wrapped key file cannot be opened without it.
+    pub fn load_or_create(data_dir: &Path, passphrase: Option<&str>) -> Result<Self, Error> {
+        let path = data_dir.join(KEY_FILENAME);
+
+        if !path.exists() {
+            return Self::create_at(&path, passphrase);
+        }
+
+        if data_dir.join(PENDING_KEY_FILENAME).exists() {
+            eprintln!(
+                "⚠️  Found {} from an interrupted re-key. If encrypted data can't be read, replace {} with it.",
+                PENDING_KEY_FILENAME, KEY_FILENAME
+            );
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let key_file: KeyFile = serde_json::from_str(&contents)
+            .with_context(|| format!("{} is not a valid key file", path.display()))?;
+
+        let key = match (&key_file.salt, &key_file.nonce) {
+            (Some(salt), Some(nonce)) => {
+                let passphrase = passphrase.ok_or_else(|| {
+                    anyhow!("the key file is passphrase protected, set {PASSPHRASE_ENV} to unlock it")
+                })?;
+                let nonce = decode(nonce)?;
+                passphrase_cipher(passphrase, &decode(salt)?)
+                    .decrypt(Nonce::from_slice(&nonce), decode(&key_file.key)?.as_slice())
+                    .map_err(|_| anyhow!("wrong passphrase for the key file"))?
+            }
+            _ => {
+                let key = decode(&key_file.key)?;
+                if let Some(passphrase) = passphrase {
+                    write_key_file(&path, &key, Some(passphrase))?;
+                }
+                key
+            }
+        };
+
+        if key.len() != 32 {
+            return Err(anyhow!("key file contains a key of the wrong length"));
+        }
+
+        Ok(Self::from_cipher(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))))
+    }
+
+    /// Generate a fresh key and write it to `path`
+    pub fn create_at(path: &Path, passphrase: Option<&str>) -> Result<Self, Error> {
+        let key = Aes256Gcm::generate_key(OsRng);
+        write_key_file(path, &key, passphrase)?;
+        Ok(Self::from_cipher(Aes256Gcm::new(&key)))
+    }
+
+    fn from_cipher(cipher: Aes256Gcm) -> Self {
+        Self {
+            cipher: Arc::new(RwLock::new(cipher)),
+        }
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        self.cipher.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Switch this store, and every clone of it, over to the key of `other`
+    pub fn replace_key(&self, other: &SecretStore) {
+        let cipher = other.cipher();
+        *self.cipher.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = cipher;
+    }
+
+    pub fn is_encrypted(value: &str) -> bool {
+        value.starts_with(ENCRYPTED_PREFIX)
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, Error> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher()
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow!("failed to encrypt secret"))?;
+
+        let mut combined = nonce.to_vec();
+        combined.extend_from_slice(&ciphertext);
+        Ok(format!("{ENCRYPTED_PREFIX}{}", BASE64.encode(combined)))
+    }
+
+    /// Decrypt a stored secret. Values without the encryption prefix predate encryption and are returned unchanged.
+    pub fn decrypt(&self, stored: &str) -> Result<String, Error> {
+        let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+
+        let combined = BASE64.decode(encoded).context("stored secret is not valid base64")?;
+        if combined.len() <= NONCE_LEN {
+            return Err(anyhow!("stored secret is truncated"));
+        }
+
+        let (nonce, ciphertext) = combined.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt secret, was the key file replaced?"))?;
+
+        String::from_utf8(plaintext).context("decrypted secret is not valid UTF-8")
+    }
+}
+
+/// Token that authorizes requests to the local read API. It is kept in a private file in the
+/// data directory so scripts can read it. Clones share the token, so a rotation is seen by the server.
+#[derive(Clone)]
+pub struct ApiToken {
+    path: PathBuf,
+    token: Arc<RwLock<String>>,
+}
+
+impl ApiToken {
+    pub fn load_or_create(data_dir: &Path) -> Result<Self, Error> {
+        let path = data_dir.join(API_TOKEN_FILENAME);
+        let stored = match fs::read_to_string(&path) {
+            Ok(contents) => Some(contents.trim().to_string()).filter(|token| !token.is_empty()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        let token = match stored {
+            Some(token) => token,
+            None => {
+                let token = URL_SAFE_NO_PAD.encode(rand_bytes::<32>());
+                write_private_file(&path, token.as_bytes())?;
+                token
+            }
+        };
+
+        Ok(Self {
+            path,
+            token: Arc::new(RwLock::new(token)),
+        })
+    }
+
+    pub fn current(&self) -> String {
+        self.token.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Compare in constant time, so response timing doesn't reveal how much of a guess was right
+    pub fn matches(&self, presented: &str) -> bool {
+        let token = self.current();
+        token.len() == presented.len()
+            && token
+                .bytes()
+                .zip(presented.bytes())
+                .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+
+    /// Replace the token, invalidating the old one
+    pub fn rotate(&self) -> Result<String, Error> {
+        let token = URL_SAFE_NO_PAD.encode(rand_bytes::<32>());
+        write_private_file(&self.path, token.as_bytes())?;
+        *self.token.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = token.clone();
+        Ok(token)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Short hint such as `sk-…abcd` that identifies a key without revealing it
+pub fn mask_secret(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    if chars.len() <= 8 {
+        return "…".to_string();
+    }
+
+    let prefix: String = chars.iter().take(3).collect();
+    let suffix: String = chars[chars.len() - 4..].iter().collect();
+    format!("{prefix}…{suffix}")
+}
+
+/// Encrypt an API key left in plain text by an older version
+pub async fn migrate_plaintext_api_key(pool: &SqlitePool, store: &SecretStore) -> Result<bool, Error> {
+    let stored: Option<String> = sqlx::query_scalar("SELECT api_key FROM ai_settings WHERE id = 1")
+        .fetch_optional(pool)
+        .await?
+        .flatten();
+
+    match stored {
+        Some(key) if !key.is_empty() && !SecretStore::is_encrypted(&key) => {
+            sqlx::query("UPDATE ai_settings SET api_key = ?1 WHERE id = 1")
+                .bind(store.encrypt(&key)?)
+                .execute(pool)
+                .await?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db;
+
+    #[test]
+    fn encrypts_and_decrypts_with_the_stored_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SecretStore::load_or_create(dir.path(), None).unwrap();
+
+        let sealed = store.encrypt("sk-live-1234").unwrap();
+        assert!(SecretStore::is_encrypted(&sealed));
+        assert_ne!(store.encrypt("sk-live-1234").unwrap(), sealed, "nonces must differ");
+        assert_eq!(store.decrypt(&sealed).unwrap(), "sk-live-1234");
+
+        let reopened = SecretStore::load_or_create(dir.path(), None).unwrap();
+        assert_eq!(reopened.decrypt(&sealed).unwrap(), "sk-live-1234");
+
+        let other = tempfile::tempdir().unwrap();
+        assert!(SecretStore::load_or_create(other.path(), None).unwrap().decrypt(&sealed).is_err());
+    }
+
+    #[test]
+    fn passphrase_protected_key_needs_the_right_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let sealed = SecretStore::load_or_create(dir.path(), Some("correct horse"))
+            .unwrap()
+            .encrypt("secret")
+            .unwrap();
+
+        let wrong = SecretStore::load_or_create(dir.path(), Some("battery staple")).err().unwrap();
+        assert!(wrong.to_string().contains("wrong passphrase"), "{wrong}");
+        let missing = SecretStore::load_or_create(dir.path(), None).err().unwrap();
+        assert!(missing.to_string().contains(PASSPHRASE_ENV), "{missing}");
+
+        let store = SecretStore::load_or_create(dir.path(), Some("correct horse")).unwrap();
+        assert_eq!(store.decrypt(&sealed).unwrap(), "secret");
+    }
+
+    #[test]
+    fn passphrase_wraps_an_existing_plain_key_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let sealed = SecretStore::load_or_create(dir.path(), None).unwrap().encrypt("secret").unwrap();
+
+        SecretStore::load_or_create(dir.path(), Some("hunter2")).unwrap();
+        assert!(SecretStore::load_or_create(dir.path(), None).is_err());
+        let store = SecretStore::load_or_create(dir.path(), Some("hunter2")).unwrap();
+        assert_eq!(store.decrypt(&sealed).unwrap(), "secret");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn key_and_token_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        SecretStore::load_or_create(dir.path(), None).unwrap();
+        ApiToken::load_or_create(dir.path()).unwrap();
+
+        for name in [KEY_FILENAME, API_TOKEN_FILENAME] {
+            let mode = fs::metadata(dir.path().join(name)).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{name}");
+        }
+
+        // Loosened permissions of an existing file are tightened when it is rewritten
+        let path = dir.path().join(KEY_FILENAME);
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        SecretStore::load_or_create(dir.path(), Some("hunter2")).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn masks_secrets() {
+        assert_eq!(mask_secret("sk-proj-abcdef123456"), "sk-…3456");
+        assert_eq!(mask_secret("short"), "…");
+        assert_eq!(mask_secret("12345678"), "…");
+        assert_eq!(mask_secret("äöü-ßçñ-éèê"), "äöü…-éèê");
+    }
+
+    #[tokio::test]
+    async fn migrates_a_plaintext_api_key_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_lock, pool) = db::test_pool(dir.path()).await;
+        let store = SecretStore::load_or_create(dir.path(), None).unwrap();
+        sqlx::query("UPDATE ai_settings SET api_key = 'sk-plain-key' WHERE id = 1")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert!(migrate_plaintext_api_key(&pool, &store).await.unwrap());
+        let stored: String = sqlx::query_scalar("SELECT api_key FROM ai_settings WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(SecretStore::is_encrypted(&stored));
+        assert_eq!(store.decrypt(&stored).unwrap(), "sk-plain-key");
+
+        assert!(!migrate_plaintext_api_key(&pool, &store).await.unwrap());
+        let again: String = sqlx::query_scalar("SELECT api_key FROM ai_settings WHERE id = 1")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(again, stored);
+    }
+}
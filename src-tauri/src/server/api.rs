@@ -0,0 +1,282 @@
+use std::{convert::Infallible, net::SocketAddr};
+
+use axum::{
+    extract::{rejection::QueryRejection, ConnectInfo, Query, Request, State},
+    http::{header::AUTHORIZATION, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use futures_util::{stream, Stream};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::{
+    db::{
+        models::{ActivityLog, AiReport},
+        query::{self, ActivityStats, InvalidCursor, LogPage, LogQuery, NamedCount, Page, ReportPage, ReportQuery, TimeRange},
+    },
+    events::ChronicleEvent,
+    server::endpoint::{ServerState, ServerStatus},
+    state::AppState,
+};
+
+/// Body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiErrorBody {
+    pub error: String,
+}
+
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl ToString) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+        }
+    }
+
+    fn internal(err: anyhow::Error) -> Self {
+        // A cursor is the only input the query itself rejects
+        let status = if err.is::<InvalidCursor>() {
+            StatusCode::BAD_REQUEST
+        } else {
+            StatusCode::INTERNAL_SERVER_ERROR
+        };
+        Self::new(status, err)
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(ApiErrorBody { error: self.message })).into_response()
+    }
+}
+
+/// Let requests with `Authorization: Bearer <token>` through. Browsers can't set headers on an
+/// `EventSource`, so the token may also be passed as the `access_token` query parameter.
+pub async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    if has_token(&state, &request) {
+        next.run(request).await
+    } else {
+        ApiError::new(StatusCode::UNAUTHORIZED, "missing or invalid API token").into_response()
+    }
+}
+
+/// Let ingestion from this machine through as is, and require the API token from other machines,
+/// which can reach the server when it listens on a non-loopback host
+pub async fn require_token_off_loopback(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let peer = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(peer)| *peer);
+
+    if from_this_machine(peer) || has_token(&state, &request) {
+        next.run(request).await
+    } else {
+        ApiError::new(StatusCode::UNAUTHORIZED, "events from other machines need the API token").into_response()
+    }
+}
+
+/// Loopback peers, also as IPv4-mapped IPv6 addresses. Without connection info, e.g. when the
+/// router is called in-process, the request can only come from here.
+fn from_this_machine(peer: Option<SocketAddr>) -> bool {
+    peer.is_none_or(|peer| peer.ip().to_canonical().is_loopback())
+}
+
+fn has_token(state: &AppState, request: &Request) -> bool {
+    let header = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+    let parameter = || {
+        url::form_urlencoded::parse(request.uri().query().unwrap_or_default().as_bytes())
+            .find(|(key, _)| key == "access_token")
+            .map(|(_, token)| token.into_owned())
+    };
+    header
+        .or_else(parameter)
+        .is_some_and(|token| state.api_token.matches(&token))
+}
+
+/// Logs matching the filters, newest first
+#[utoipa::path(
+    get,
+    path = "/api/logs",
+    params(LogQuery),
+    responses(
+        (status = 200, body = LogPage),
+        (status = 400, body = ApiErrorBody),
+        (status = 401, body = ApiErrorBody),
+    ),
+    security(("token" = []))
+)]
+pub async fn logs(
+    State(state): State<AppState>,
+    query: Result<Query<LogQuery>, QueryRejection>,
+) -> Result<Json<Page<ActivityLog>>, ApiError> {
+    let Query(query) = query?;
+    let page = query::query_logs(&state.pool, &query).await.map_err(ApiError::internal)?;
+    Ok(Json(page))
+}
+
+/// Logs containing the text `q`, with the same filters as `/api/logs`
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    params(LogQuery),
+    responses(
+        (status = 200, body = LogPage),
+        (status = 400, body = ApiErrorBody),
+        (status = 401, body = ApiErrorBody),
+    ),
+    security(("token" = []))
+)]
+pub async fn search(
+    State(state): State<AppState>,
+    query: Result<Query<LogQuery>, QueryRejection>,
+) -> Result<Json<Page<ActivityLog>>, ApiError> {
+    let Query(query) = query?;
+    if query.text.as_deref().is_none_or(|text| text.trim().is_empty()) {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, "the search text `q` is required"));
+    }
+    let page = query::query_logs(&state.pool, &query).await.map_err(ApiError::internal)?;
+    Ok(Json(page))
+}
+
+/// AI reports, newest first
+#[utoipa::path(
+    get,
+    path = "/api/reports",
+    params(ReportQuery),
+    responses(
+        (status = 200, body = ReportPage),
+        (status = 400, body = ApiErrorBody),
+        (status = 401, body = ApiErrorBody),
+    ),
+    security(("token" = []))
+)]
+pub async fn reports(
+    State(state): State<AppState>,
+    query: Result<Query<ReportQuery>, QueryRejection>,
+) -> Result<Json<Page<AiReport>>, ApiError> {
+    let Query(query) = query?;
+    let page = query::query_reports(&state.pool, &query).await.map_err(ApiError::internal)?;
+    Ok(Json(page))
+}
+
+/// Log and report counts in a time range, all time by default
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    params(TimeRange),
+    responses(
+        (status = 200, body = ActivityStats),
+        (status = 400, body = ApiErrorBody),
+        (status = 401, body = ApiErrorBody),
+    ),
+    security(("token" = []))
+)]
+pub async fn stats(
+    State(state): State<AppState>,
+    range: Result<Query<TimeRange>, QueryRejection>,
+) -> Result<Json<ActivityStats>, ApiError> {
+    let Query(range) = range?;
+    let stats = query::activity_stats(&state.pool, &range).await.map_err(ApiError::internal)?;
+    Ok(Json(stats))
+}
+
+/// Live [`ChronicleEvent`]s as server-sent events. Each event is named after its `type` and
+/// carries it as JSON. A `lagged` event with the number of missed events is sent when this
+/// client fell behind, after which it should refetch.
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    responses(
+        (status = 200, description = "Stream of server-sent events", body = ChronicleEvent, content_type = "text/event-stream"),
+        (status = 401, body = ApiErrorBody),
+    ),
+    security(("token" = []))
+)]
+pub async fn events(State(state): State<AppState>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    // The stream ends when the bus is closed so shutdown doesn't wait on connected clients
+    let stream = stream::unfold((state.events.subscribe(), state.events), |(mut receiver, events)| async move {
+        let received = tokio::select! {
+            received = receiver.recv() => received,
+            _ = events.closed() => return None,
+        };
+        let event = match received {
+            Ok(event) => Event::default()
+                .event(event.name())
+                .json_data(&event)
+                .unwrap_or_else(|err| Event::default().event("error").data(err.to_string())),
+            Err(RecvError::Lagged(skipped)) => Event::default().event("lagged").data(skipped.to_string()),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), (receiver, events)))
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+struct TokenAuth;
+
+impl Modify for TokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "DevChronicle local API",
+        description = "Read-only access to recorded activity. Send the token from the `api_token` file in the data directory as `Authorization: Bearer <token>`."
+    ),
+    paths(logs, search, reports, stats, events),
+    components(schemas(ActivityLog, AiReport, LogPage, ReportPage, ActivityStats, NamedCount, ChronicleEvent, ServerStatus, ServerState, ApiErrorBody)),
+    modifiers(&TokenAuth)
+)]
+pub struct ApiDoc;
+
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_loopback_peers_count_as_this_machine() {
+        let peer = |address: &str| Some(address.parse::<SocketAddr>().unwrap());
+
+        assert!(from_this_machine(peer("127.0.0.1:50000")));
+        assert!(from_this_machine(peer("[::1]:50000")));
+        assert!(from_this_machine(peer("[::ffff:127.0.0.1]:50000")));
+        assert!(from_this_machine(None));
+        assert!(!from_this_machine(peer("192.168.1.20:50000")));
+        assert!(!from_this_machine(peer("[2001:4860::8888]:50000")));
+    }
+}
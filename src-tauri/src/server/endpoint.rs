@@ -0,0 +1,252 @@
+use std::{
+    env, fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{anyhow, Context, Error};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use utoipa::ToSchema;
+
+use crate::{
+    db::models::ServerSettings,
+    events::{ChronicleEvent, EventBus},
+};
+
+/// Override the host and port stored in the settings
+pub const HOST_ENV: &str = "DEVCHRONICLE_HOST";
+pub const PORT_ENV: &str = "DEVCHRONICLE_PORT";
+
+/// Written to the data directory while the server runs, so clients can find it
+pub const DISCOVERY_FILENAME: &str = "server.json";
+
+/// Upper bound for `fallback_ports`, so a typo can't make startup probe thousands of ports
+pub const MAX_FALLBACK_PORTS: i64 = 100;
+
+/// Where the server listens, with the environment overrides applied
+#[derive(Debug, Clone)]
+pub struct BindConfig {
+    pub host: IpAddr,
+    pub port: u16,
+    pub fallback_ports: u16,
+}
+
+fn parse_host(host: &str) -> Result<IpAddr, Error> {
+    match host.trim() {
+        "localhost" => Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+        host => host
+            .parse()
+            .map_err(|_| anyhow!("invalid host '{host}', expected an IP address such as 127.0.0.1")),
+    }
+}
+
+fn parse_port(port: i64) -> Result<u16, Error> {
+    u16::try_from(port)
+        .ok()
+        .filter(|port| *port != 0)
+        .ok_or_else(|| anyhow!("invalid port {port}, expected 1 to 65535"))
+}
+
+/// Reject settings the server could not start with
+pub fn validate(settings: &ServerSettings) -> Result<(), Error> {
+    parse_host(&settings.host)?;
+    parse_port(settings.port)?;
+    if !(0..=MAX_FALLBACK_PORTS).contains(&settings.fallback_ports) {
+        return Err(anyhow!("fallback ports must be between 0 and {MAX_FALLBACK_PORTS}"));
+    }
+    Ok(())
+}
+
+/// Names of the environment variables that currently override the settings
+pub fn env_overrides() -> Vec<&'static str> {
+    [HOST_ENV, PORT_ENV]
+        .into_iter()
+        .filter(|name| env::var_os(name).is_some())
+        .collect()
+}
+
+impl BindConfig {
+    pub fn resolve(settings: &ServerSettings) -> Result<Self, Error> {
+        validate(settings)?;
+
+        let host = match env::var(HOST_ENV) {
+            Ok(host) => parse_host(&host).with_context(|| format!("invalid {HOST_ENV}"))?,
+            Err(_) => parse_host(&settings.host)?,
+        };
+        let port = match env::var(PORT_ENV) {
+            Ok(port) => port
+                .trim()
+                .parse()
+                .map_err(Error::from)
+                .and_then(parse_port)
+                .with_context(|| format!("invalid {PORT_ENV} '{port}'"))?,
+            Err(_) => parse_port(settings.port)?,
+        };
+
+        Ok(Self {
+            host,
+            port,
+            fallback_ports: settings.fallback_ports as u16,
+        })
+    }
+}
+
+/// Bind the configured port or, when it is taken, the first free one of the fallback ports after it
+pub async fn bind(config: &BindConfig) -> Result<TcpListener, Error> {
+    let last = config.port.saturating_add(config.fallback_ports);
+    let mut port = config.port;
+    loop {
+        match TcpListener::bind((config.host, port)).await {
+            Ok(listener) => return Ok(listener),
+            Err(err) if err.kind() == io::ErrorKind::AddrInUse && port < last => port += 1,
+            Err(err) if err.kind() == io::ErrorKind::AddrInUse && port != config.port => {
+                return Err(anyhow!("ports {} to {last} on {} are all in use", config.port, config.host));
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to bind {}", SocketAddr::new(config.host, port)));
+            }
+        }
+    }
+}
+
+/// Contents of the discovery file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Discovery {
+    /// Base URL for clients, e.g. `http://127.0.0.1:3030`
+    pub url: String,
+    /// Address to connect to. Loopback when the server listens on all interfaces.
+    pub address: SocketAddr,
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+}
+
+impl Discovery {
+    pub fn new(local_addr: SocketAddr) -> Self {
+        let address = match local_addr.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), local_addr.port()),
+            IpAddr::V6(ip) if ip.is_unspecified() => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), local_addr.port()),
+            _ => local_addr,
+        };
+
+        Self {
+            url: format!("http://{address}"),
+            address,
+            pid: std::process::id(),
+            started_at: Utc::now(),
+        }
+    }
+
+    /// The server last started for `data_dir`. It may have exited without removing the file.
+    pub fn read(data_dir: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(data_dir.join(DISCOVERY_FILENAME)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn write(&self, data_dir: &Path) -> Result<(), Error> {
+        // Written aside and renamed so readers never see half a file
+        let path = data_dir.join(DISCOVERY_FILENAME);
+        let pending = data_dir.join(format!("{DISCOVERY_FILENAME}.tmp"));
+        fs::write(&pending, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("failed to write {}", pending.display()))?;
+        fs::rename(&pending, &path).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Remove the file unless another process has taken it over since
+    pub fn remove(data_dir: &Path) {
+        if Self::read(data_dir).is_some_and(|discovery| discovery.pid == std::process::id()) {
+            let _ = fs::remove_file(data_dir.join(DISCOVERY_FILENAME));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerState {
+    Starting,
+    Running,
+    Failed,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ServerStatus {
+    pub state: ServerState,
+    /// Base URL while running
+    pub url: Option<String>,
+    /// The configured port, `port` differs when a fallback port was used
+    pub requested_port: Option<u16>,
+    pub port: Option<u16>,
+    /// Why the server failed
+    pub error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// The server's status, shared with the UI. Changes are published as [`ChronicleEvent::ServerStatus`].
+#[derive(Clone)]
+pub struct ServerHealth {
+    status: Arc<RwLock<ServerStatus>>,
+    events: EventBus,
+}
+
+impl ServerHealth {
+    pub fn new(events: EventBus) -> Self {
+        let status = ServerStatus {
+            state: ServerState::Starting,
+            url: None,
+            requested_port: None,
+            port: None,
+            error: None,
+            updated_at: Utc::now(),
+        };
+
+        Self {
+            status: Arc::new(RwLock::new(status)),
+            events,
+        }
+    }
+
+    pub fn current(&self) -> ServerStatus {
+        self.status.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ServerStatus)) {
+        let status = {
+            let mut status = self.status.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+            change(&mut status);
+            status.updated_at = Utc::now();
+            status.clone()
+        };
+        self.events.publish(ChronicleEvent::ServerStatus { status });
+    }
+
+    pub fn running(&self, requested_port: u16, discovery: &Discovery) {
+        self.update(|status| {
+            status.state = ServerState::Running;
+            status.url = Some(discovery.url.clone());
+            status.requested_port = Some(requested_port);
+            status.port = Some(discovery.address.port());
+            status.error = None;
+        });
+    }
+
+    pub fn failed(&self, requested_port: Option<u16>, err: &Error) {
+        self.update(|status| {
+            status.state = ServerState::Failed;
+            status.url = None;
+            status.requested_port = requested_port.or(status.requested_port);
+            status.port = None;
+            status.error = Some(format!("{err:#}"));
+        });
+    }
+
+    pub fn stopped(&self) {
+        self.update(|status| {
+            status.state = ServerState::Stopped;
+            status.url = None;
+            status.port = None;
+        });
+    }
+}
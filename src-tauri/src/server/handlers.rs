@@ -1,117 +1,352 @@
-use axum::{extract::State, http::StatusCode, Json};
-use chrono::{DateTime, Utc};
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, SubsecRound, Utc};
 use serde::Deserialize;
 use serde_json::Value;
 use sqlx::SqlitePool;
 
-use crate::state::AppState;
+use crate::{
+    db::{
+        self, crypto, get_redaction_settings,
+        models::{ActivityLog, EditorEvent},
+        time::StoredTimestamp,
+    },
+    events::ChronicleEvent,
+    export::event_project,
+    git,
+    privacy::{self, DropReason},
+    sanitizer::{rules, Redactor},
+    server::payloads::{self, EventPayload, FieldError, Rejection},
+    state::AppState,
+};
 
 #[derive(Deserialize)]
 pub struct IngestRequest {
     pub source: String,
+    /// Version of the payload schema the client was written against. Clients that predate
+    /// versioning omit it and are treated as the current version.
+    #[serde(default)]
+    pub schema_version: Option<u32>,
     pub payload: Value,
     #[serde(default)]
     pub timestamp: Option<DateTime<Utc>>,
 }
 
+fn internal_error(err: impl ToString) -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+}
+
+/// Count a malformed event and build the response listing what was wrong with it
+async fn reject(state: &AppState, source: &str, status: StatusCode, errors: Vec<FieldError>) -> Response {
+    println!("🚫 Rejected {} event: {} field error(s)", source, errors.len());
+    if let Err(err) = payloads::record_rejected(&state.pool, source, Utc::now()).await {
+        eprintln!("⚠️  Failed to count rejected event: {err}");
+    }
+    Rejection { status, errors }.into_response()
+}
+
 pub async fn ingest(
     State(state): State<AppState>,
-    Json(body): Json<IngestRequest>,
-) -> Result<StatusCode, (StatusCode, String)> {
+    body: Result<Json<IngestRequest>, JsonRejection>,
+) -> Result<StatusCode, Response> {
+    let body = match body {
+        Ok(Json(body)) => body,
+        Err(rejection) => {
+            let errors = vec![FieldError {
+                field: "body".to_string(),
+                message: rejection.body_text(),
+            }];
+            return Err(reject(&state, "unknown", rejection.status(), errors).await);
+        }
+    };
+
     println!("📥 Received ingestion request from source: {}", body.source);
     
     let timestamp = body.timestamp.unwrap_or_else(Utc::now);
-    let payload_text = serde_json::to_string(&body.payload)
-        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
-    
-    println!("📝 Payload length: {} bytes", payload_text.len());
-    
+
+    // Validate against the source's schema and rename legacy fields before anything looks at the payload
+    let payload = match EventPayload::parse(&body.source, body.schema_version, body.payload) {
+        Ok(parsed) => parsed.into_value(),
+        Err(errors) => return Err(reject(&state, &body.source, StatusCode::UNPROCESSABLE_ENTITY, errors).await),
+    };
+
+    match store_event(&state.pool, &body.source, payload, timestamp, None).await {
+        Ok(StoreOutcome::Stored(log)) => {
+            state.events.publish(ChronicleEvent::LogCreated { log: *log });
+            Ok(StatusCode::CREATED)
+        }
+        Ok(StoreOutcome::Dropped(_)) => Ok(StatusCode::ACCEPTED),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+/// What became of an event handed to [`store_event`]
+pub enum StoreOutcome {
+    Stored(Box<ActivityLog>),
+    /// Excluded by the privacy settings, nothing about it was stored
+    Dropped(DropReason),
+}
+
+/// Store one event the way every source goes in: privacy exclusions, redaction, field extraction
+/// and the insert. Used by the ingestion endpoint and the git scanner. `event_id` defaults to
+/// [`db::event_id`] of the redacted payload.
+pub async fn store_event(
+    pool: &SqlitePool,
+    source: &str,
+    payload: Value,
+    timestamp: DateTime<Utc>,
+    event_id: Option<String>,
+) -> Result<StoreOutcome, anyhow::Error> {
+    // Excluded or paused events are dropped before anything about their content is stored
+    if let Some(reason) = privacy::current().drop_reason(&payload, Utc::now()) {
+        println!("🙈 Dropped {} event ({})", source, reason.as_str());
+        privacy::record_dropped(pool, source, reason, Utc::now()).await?;
+        return Ok(StoreOutcome::Dropped(reason));
+    }
+
+    // Redact before anything is stored. Normalized columns are extracted from the redacted payload so they are covered too.
+    let redaction = get_redaction_settings(pool).await?;
+    let (payload, redactions) = if redaction.enabled {
+        let rules = rules::current();
+        Redactor::new(redaction.options(), &rules, Some(source)).redact_json(&payload)
+    } else {
+        (payload, 0)
+    };
+
+    let payload_text = serde_json::to_string(&payload)?;
+
+    println!("📝 Payload length: {} bytes, {} redactions", payload_text.len(), redactions);
+
     // Extract normalized fields from payload based on source
-    let (log_type, command, url, domain, title, file_path) = extract_fields(&body.source, &body.payload);
-    
-    // Generate or retrieve session ID (simplified: hourly sessions)
-    let session_id = format!("session_{}", timestamp.format("%Y%m%d_%H"));
-    
-    insert_log(
-        &state.pool,
-        &body.source,
+    let mut fields = extract_fields(source, &payload);
+    fields.project = project_of(&payload, fields.file_path.as_deref()).await;
+
+    let event_id = event_id.unwrap_or_else(|| db::event_id(source, timestamp, &payload_text));
+
+    let id = insert_log(
+        pool,
+        source,
         &payload_text,
-        timestamp.to_rfc3339(),
-        &log_type,
-        &session_id,
-        &command,
-        &url,
-        &domain,
-        &title,
-        &file_path,
+        timestamp,
+        &event_id,
+        &fields,
+        redactions as i64,
     )
     .await?;
-    
+
     println!("✅ Successfully saved log to database");
-    
-    Ok(StatusCode::CREATED)
+
+    Ok(StoreOutcome::Stored(Box::new(ActivityLog {
+        id,
+        source: source.to_string(),
+        payload,
+        // As stored, in whole milliseconds
+        timestamp: timestamp.trunc_subsecs(3),
+        log_type: Some(fields.log_type),
+        session_id: Some(session_id(timestamp)),
+        command: fields.command,
+        url: fields.url,
+        domain: fields.domain,
+        title: fields.title,
+        file_path: fields.file_path,
+        project: Some(fields.project).filter(|project| !project.is_empty()),
+    })))
+}
+
+/// Columns extracted from a payload so logs can be queried without decrypting or parsing it
+#[derive(Debug, Default)]
+pub struct NormalizedFields {
+    pub log_type: String,
+    pub command: Option<String>,
+    pub exit_code: Option<i64>,
+    /// Working directory of a command, or the repository of a git event
+    pub cwd: Option<String>,
+    pub url: Option<String>,
+    pub domain: Option<String>,
+    pub title: Option<String>,
+    pub file_path: Option<String>,
+    /// Empty when the event carries no path to attribute it to a project. Filled by [`project_of`].
+    pub project: String,
 }
 
 /// Extract normalized fields from payload based on source type
-fn extract_fields(source: &str, payload: &Value) -> (String, Option<String>, Option<String>, Option<String>, Option<String>, Option<String>) {
+pub fn extract_fields(source: &str, payload: &Value) -> NormalizedFields {
+    let text = |key: &str| payload.get(key).and_then(Value::as_str).map(str::to_string);
+
     match source {
-        "terminal" => {
-            let command = payload.get("command").and_then(|v| v.as_str()).map(|s| s.to_string());
-            ("command".to_string(), command, None, None, None, None)
-        }
+        "terminal" => NormalizedFields {
+            log_type: "command".to_string(),
+            command: text("command"),
+            exit_code: payload.get("exit_code").and_then(Value::as_i64),
+            cwd: text("cwd"),
+            ..Default::default()
+        },
         "browser" => {
-            let url = payload.get("url").and_then(|v| v.as_str()).map(|s| s.to_string());
-            let title = payload.get("title").and_then(|v| v.as_str()).map(|s| s.to_string());
+            let url = text("url");
             let domain = url.as_ref().and_then(|u| {
                 url::Url::parse(u).ok().and_then(|parsed| parsed.domain().map(|d| d.to_string()))
             });
-            ("browse".to_string(), None, url, domain, title, None)
+            NormalizedFields {
+                log_type: "browse".to_string(),
+                url,
+                domain,
+                title: text("title"),
+                ..Default::default()
+            }
         }
-        "vscode" => {
-            let file_path = payload.get("file_path").and_then(|v| v.as_str()).map(|s| s.to_string());
-            let language = payload.get("language").and_then(|v| v.as_str()).map(|s| s.to_string());
-            ("file_edit".to_string(), None, None, None, language, file_path)
+        "vscode" => match EditorEvent::from_payload(payload) {
+            Ok(event) => NormalizedFields {
+                log_type: event.log_type().to_string(),
+                file_path: event.file_path().map(str::to_string),
+                title: event.title().map(str::to_string),
+                ..Default::default()
+            },
+            Err(_) => NormalizedFields {
+                log_type: "unknown".to_string(),
+                ..Default::default()
+            },
+        },
+        // Commit messages are stored whole in the payload, the title is their first line
+        "git" => NormalizedFields {
+            log_type: text("type").unwrap_or_else(|| "unknown".to_string()),
+            cwd: text("repository"),
+            title: text("message")
+                .map(|message| message.lines().next().unwrap_or_default().to_string())
+                .or_else(|| text("to")),
+            ..Default::default()
+        },
+        _ => NormalizedFields {
+            log_type: "unknown".to_string(),
+            ..Default::default()
+        },
+    }
+}
+
+/// The event's project, or an empty string. [`event_project`] looks for the enclosing repository
+/// on disk, so it runs on the blocking pool.
+async fn project_of(payload: &Value, file_path: Option<&str>) -> String {
+    let (payload, file_path) = (payload.clone(), file_path.map(str::to_string));
+    tokio::task::spawn_blocking(move || event_project(&payload, file_path.as_deref()))
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+/// Fill `project`, `exit_code` and `event_id` for logs stored before they were extracted at ingestion.
+/// Rows whose payload can't be read are left as they are. Returns how many rows were updated.
+pub async fn backfill_fields(pool: &SqlitePool) -> Result<u64, anyhow::Error> {
+    const BATCH_SIZE: i64 = 500;
+    let mut updated = 0;
+    let mut last_id = 0;
+
+    loop {
+        let batch: Vec<(i64, String, String, StoredTimestamp)> = sqlx::query_as(
+            "SELECT id, source, payload, timestamp FROM activity_logs \
+             WHERE (project IS NULL OR event_id IS NULL) AND id > ?1 ORDER BY id ASC LIMIT ?2",
+        )
+        .bind(last_id)
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+        let Some((id, ..)) = batch.last() else {
+            return Ok(updated);
+        };
+        last_id = *id;
+
+        let _writes = crypto::write_access().await;
+        let mut tx = pool.begin().await?;
+        for (id, source, payload, timestamp) in batch {
+            let Some(payload_text) = crypto::open(&payload).ok() else {
+                continue;
+            };
+            let (Ok(payload), Ok(timestamp)) = (serde_json::from_str::<Value>(&payload_text), timestamp.to_utc()) else {
+                continue;
+            };
+
+            let fields = extract_fields(&source, &payload);
+            let project = crypto::seal(&project_of(&payload, fields.file_path.as_deref()).await)?;
+            let event_id = match source.as_str() {
+                "git" => git::event_id(&payload),
+                _ => None,
+            }
+            .unwrap_or_else(|| db::event_id(&source, timestamp, &payload_text));
+
+            sqlx::query(
+                "UPDATE activity_logs SET project = COALESCE(project, ?1), exit_code = COALESCE(exit_code, ?2), \
+                 event_id = COALESCE(event_id, ?3) WHERE id = ?4",
+            )
+            .bind(project)
+            .bind(fields.exit_code)
+            .bind(event_id)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+            updated += 1;
         }
-        _ => ("unknown".to_string(), None, None, None, None, None),
+        tx.commit().await?;
     }
 }
 
+/// Generate or retrieve session ID (simplified: hourly sessions)
+fn session_id(timestamp: DateTime<Utc>) -> String {
+    format!("session_{}", timestamp.format("%Y%m%d_%H"))
+}
+
 async fn insert_log(
     pool: &SqlitePool,
     source: &str,
     payload: &str,
-    timestamp: String,
-    log_type: &str,
-    session_id: &str,
-    command: &Option<String>,
-    url: &Option<String>,
-    domain: &Option<String>,
-    title: &Option<String>,
-    file_path: &Option<String>,
-) -> Result<(), (StatusCode, String)> {
-    println!("💾 Inserting into database: source={}, timestamp={}, type={}", source, timestamp, log_type);
-    
+    timestamp: DateTime<Utc>,
+    event_id: &str,
+    fields: &NormalizedFields,
+    redactions: i64,
+) -> Result<i64, anyhow::Error> {
+    println!("💾 Inserting into database: source={}, timestamp={}, type={}", source, timestamp, fields.log_type);
+
+    // Sealed and inserted without encryption being switched on, off or re-keyed in between
+    let _writes = crypto::write_access().await;
+    let payload = crypto::seal(payload)?;
+    let command = crypto::seal_opt(fields.command.as_deref())?;
+    let cwd = crypto::seal_opt(fields.cwd.as_deref())?;
+    let url = crypto::seal_opt(fields.url.as_deref())?;
+    let domain = crypto::seal_opt(fields.domain.as_deref())?;
+    let title = crypto::seal_opt(fields.title.as_deref())?;
+    let file_path = crypto::seal_opt(fields.file_path.as_deref())?;
+    let project = crypto::seal(&fields.project)?;
+
     sqlx::query(
-        "INSERT INTO activity_logs (source, payload, timestamp, log_type, session_id, command, url, domain, title, file_path) 
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        "INSERT INTO activity_logs (source, payload, timestamp, log_type, session_id, command, exit_code, cwd, url, domain, \
+         title, file_path, project, redactions, event_id) 
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
     )
     .bind(source)
     .bind(payload)
-    .bind(timestamp)
-    .bind(log_type)
-    .bind(session_id)
+    .bind(timestamp.timestamp_millis())
+    .bind(&fields.log_type)
+    .bind(session_id(timestamp))
     .bind(command)
+    .bind(fields.exit_code)
+    .bind(cwd)
     .bind(url)
     .bind(domain)
     .bind(title)
     .bind(file_path)
+    .bind(project)
+    .bind(redactions)
+    .bind(event_id)
     .execute(pool)
     .await
     .map(|result| {
         println!("✅ Database insert successful, rows affected: {}", result.rows_affected());
+        result.last_insert_rowid()
     })
     .map_err(|err| {
         eprintln!("❌ Database error: {}", err);
-        (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+        err.into()
     })
 }
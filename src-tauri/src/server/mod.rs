@@ -1,23 +1,44 @@
-use axum::{routing::post, Router};
+use axum::{middleware, routing::post, Router};
 use axum::routing::get;
 use axum::http::Method;
 use tower_http::cors::{Any, CorsLayer};
 
-use crate::state::AppState;
+use crate::{events::EventBus, secrets::ApiToken, state::AppState};
 
+pub mod api;
+pub mod endpoint;
 pub mod handlers;
+pub mod payloads;
 
-pub fn build_router(pool: sqlx::SqlitePool) -> Router {
+pub fn build_router(pool: sqlx::SqlitePool, api_token: ApiToken, events: EventBus) -> Router {
     let cors = CorsLayer::new()
-        .allow_methods([Method::POST])
+        .allow_methods([Method::GET, Method::POST])
         .allow_origin(Any)
         .allow_headers(Any);
 
-    Router::new()
+    let state = AppState { pool, api_token, events };
+
+    // Read-only API for scripts and dashboards, behind the token
+    let api = Router::new()
+        .route("/api/logs", get(api::logs))
+        .route("/api/reports", get(api::reports))
+        .route("/api/stats", get(api::stats))
+        .route("/api/search", get(api::search))
+        .route("/api/events", get(api::events))
+        .route_layer(middleware::from_fn_with_state(state.clone(), api::require_token));
+
+    // Open to clients on this machine; others, possible on a non-loopback host, need the token
+    let ingest = Router::new()
         .route("/ingest/terminal", post(handlers::ingest))
         .route("/ingest/vscode", post(handlers::ingest))
         .route("/ingest/browser", post(handlers::ingest))
+        .route_layer(middleware::from_fn_with_state(state.clone(), api::require_token_off_loopback));
+
+    Router::new()
+        .merge(ingest)
         .route("/health", get(|| async { "OK" }))  // Health check
-        .with_state(AppState { pool })
+        .merge(api)
+        .route("/api/openapi.json", get(api::openapi))
+        .with_state(state)
         .layer(cors)
 }
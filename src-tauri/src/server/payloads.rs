@@ -0,0 +1,379 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_path_to_error::Segment;
+use serde_json::{json, Map, Value};
+use sqlx::SqlitePool;
+
+use crate::db::{models::EditorEvent, time};
+
+/// Payload schema version assumed when a client doesn't send `schema_version`
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// `/ingest/terminal`, sent by the shell hook after every command
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TerminalPayload {
+    /// Required, checked before deserializing
+    #[serde(default)]
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    #[serde(default, alias = "duration", skip_serializing_if = "Option::is_none")]
+    pub duration_sec: Option<f64>,
+    #[serde(default, alias = "working_directory", skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<String>,
+    /// Fields this schema doesn't know are stored as they are
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `/ingest/browser`, sent by the browser extension when a page has been viewed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BrowserPayload {
+    /// Required, checked before deserializing
+    #[serde(default)]
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_on_page_sec: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referrer: Option<String>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// `/ingest/vscode`. Extension versions before schema 1 sent the path as `file`.
+/// Event-specific fields are checked against [`EditorEvent`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VscodePayload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    #[serde(default, alias = "file", skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_spent_sec: Option<u64>,
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// A validated event payload, normalized to the current schema's field names
+#[derive(Debug)]
+pub enum EventPayload {
+    Terminal(TerminalPayload),
+    Browser(BrowserPayload),
+    Vscode(VscodePayload),
+    /// Sources without a schema are passed through unchanged
+    Other(Value),
+}
+
+/// Turn a serde error at `path` into a field error
+fn field_error(path: &str, message: String) -> FieldError {
+    let parent = if path == "." { "payload".to_string() } else { format!("payload.{path}") };
+
+    // serde reports a missing field against its parent, so name the field itself
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next());
+    match missing {
+        Some(field) => FieldError::new(&format!("{parent}.{field}"), "is required"),
+        None => FieldError::new(&parent, message),
+    }
+}
+
+/// Deserialize `payload` as `T`, reporting every field that doesn't fit instead of only the first.
+/// Fields that fail are left out and deserialization is retried, so the result may lack them.
+fn deserialize<T: DeserializeOwned>(mut payload: Value, required: &[&str]) -> Result<(T, Vec<FieldError>), Vec<FieldError>> {
+    // Required fields are checked here, because serde gives up at the first one missing
+    let mut errors: Vec<FieldError> = required
+        .iter()
+        .filter(|field| payload.get(**field).is_none())
+        .map(|field| FieldError::new(&format!("payload.{field}"), "is required"))
+        .collect();
+
+    loop {
+        match serde_path_to_error::deserialize(payload.clone()) {
+            Ok(parsed) => return Ok((parsed, errors)),
+            Err(err) => {
+                errors.push(field_error(&err.path().to_string(), err.inner().to_string()));
+                let removed = match (err.path().iter().next(), payload.as_object_mut()) {
+                    (Some(Segment::Map { key }), Some(object)) => object.remove(key).is_some(),
+                    _ => false,
+                };
+                if !removed {
+                    return Err(errors);
+                }
+            }
+        }
+    }
+}
+
+/// Add the errors from `validate` for fields that deserialization didn't already report
+fn merge(mut errors: Vec<FieldError>, validated: Vec<FieldError>) -> Vec<FieldError> {
+    for error in validated {
+        if !errors.iter().any(|known| known.field == error.field) {
+            errors.push(error);
+        }
+    }
+    errors
+}
+
+impl TerminalPayload {
+    fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+        if self.command.trim().is_empty() {
+            errors.push(FieldError::new("payload.command", "must not be empty"));
+        }
+        if self.duration_sec.is_some_and(|duration| !duration.is_finite() || duration < 0.0) {
+            errors.push(FieldError::new("payload.duration_sec", "must be a non-negative number"));
+        }
+        errors
+    }
+}
+
+impl BrowserPayload {
+    fn validate(&self) -> Vec<FieldError> {
+        match url::Url::parse(&self.url) {
+            Ok(_) => Vec::new(),
+            Err(err) => vec![FieldError::new("payload.url", format!("must be an absolute URL ({err})"))],
+        }
+    }
+}
+
+impl VscodePayload {
+    fn validate(&self) -> Vec<FieldError> {
+        if let Some(event) = self.event.as_deref().filter(|event| !EditorEvent::NAMES.contains(event)) {
+            return vec![FieldError::new(
+                "payload.event",
+                format!("unknown event '{event}', expected one of {}", EditorEvent::NAMES.join(", ")),
+            )];
+        }
+
+        // Each event type has its own required fields, e.g. `file_path` for file events
+        let payload = serde_json::to_value(self).unwrap_or(Value::Null);
+        let mut errors = Vec::new();
+        match EditorEvent::from_payload(&payload) {
+            Ok(EditorEvent::TaskRun { name, .. }) if name.trim().is_empty() => {
+                errors.push(FieldError::new("payload.name", "must not be empty"));
+            }
+            Ok(event) => {
+                if event.file_path().is_some_and(|path| path.trim().is_empty()) {
+                    errors.push(FieldError::new("payload.file_path", "must not be empty"));
+                }
+            }
+            Err(err) => errors.push(field_error(".", err.to_string())),
+        }
+        errors
+    }
+}
+
+impl EventPayload {
+    /// Check `payload` against the schema for `source` and the requested version
+    pub fn parse(source: &str, schema_version: Option<u32>, payload: Value) -> Result<Self, Vec<FieldError>> {
+        let version = schema_version.unwrap_or(CURRENT_SCHEMA_VERSION);
+        if version != CURRENT_SCHEMA_VERSION {
+            return Err(vec![FieldError::new(
+                "schema_version",
+                format!("unsupported version {version}, this server accepts {CURRENT_SCHEMA_VERSION}"),
+            )]);
+        }
+        if !payload.is_object() {
+            return Err(vec![FieldError::new("payload", "must be a JSON object")]);
+        }
+
+        let (parsed, errors) = match source {
+            "terminal" => {
+                let (parsed, errors): (TerminalPayload, _) = deserialize(payload, &["command"])?;
+                let errors = merge(errors, parsed.validate());
+                (EventPayload::Terminal(parsed), errors)
+            }
+            "browser" => {
+                let (parsed, errors): (BrowserPayload, _) = deserialize(payload, &["url"])?;
+                let errors = merge(errors, parsed.validate());
+                (EventPayload::Browser(parsed), errors)
+            }
+            "vscode" => {
+                let (parsed, errors): (VscodePayload, _) = deserialize(payload, &[])?;
+                let errors = merge(errors, parsed.validate());
+                (EventPayload::Vscode(parsed), errors)
+            }
+            _ => (EventPayload::Other(payload), Vec::new()),
+        };
+
+        if errors.is_empty() {
+            Ok(parsed)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The payload as it is stored, using current field names
+    pub fn into_value(self) -> Value {
+        let value = match self {
+            EventPayload::Terminal(payload) => serde_json::to_value(payload),
+            EventPayload::Browser(payload) => serde_json::to_value(payload),
+            EventPayload::Vscode(payload) => serde_json::to_value(payload),
+            EventPayload::Other(value) => return value,
+        };
+        value.unwrap_or(Value::Null)
+    }
+}
+
+/// Response for events that don't match their schema
+pub struct Rejection {
+    pub status: StatusCode,
+    pub errors: Vec<FieldError>,
+}
+
+impl IntoResponse for Rejection {
+    fn into_response(self) -> Response {
+        let body = json!({
+            "error": "invalid event",
+            "schema_version": CURRENT_SCHEMA_VERSION,
+            "errors": self.errors,
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RejectedEvents {
+    pub rejected_today: i64,
+    pub rejected_total: i64,
+}
+
+/// Count a rejected event per day and source. The event itself is not kept.
+pub async fn record_rejected(pool: &SqlitePool, source: &str, now: DateTime<Utc>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO rejected_events (day, source, count) VALUES (?1, ?2, 1)
+         ON CONFLICT(day, source) DO UPDATE SET count = count + 1",
+    )
+    .bind(time::current().local_date(now).to_string())
+    .bind(source)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn rejected_events(pool: &SqlitePool, now: DateTime<Utc>) -> Result<RejectedEvents, sqlx::Error> {
+    let (rejected_today, rejected_total): (i64, i64) = sqlx::query_as(
+        "SELECT COALESCE(SUM(CASE WHEN day = ?1 THEN count ELSE 0 END), 0), COALESCE(SUM(count), 0) FROM rejected_events",
+    )
+    .bind(time::current().local_date(now).to_string())
+    .fetch_one(pool)
+    .await?;
+
+    Ok(RejectedEvents {
+        rejected_today,
+        rejected_total,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn legacy_file_field_is_stored_as_file_path() {
+        let payload = json!({ "event": "file_save", "file": "/src/main.rs", "language": "rust" });
+        let stored = EventPayload::parse("vscode", None, payload).unwrap().into_value();
+
+        assert_eq!(stored["file_path"], "/src/main.rs");
+        assert!(stored.get("file").is_none());
+    }
+
+    #[test]
+    fn missing_command_is_required() {
+        let errors = EventPayload::parse("terminal", Some(1), json!({ "cwd": "/src" })).unwrap_err();
+
+        assert_eq!(fields(&errors), ["payload.command"]);
+        assert_eq!(errors[0].message, "is required");
+    }
+
+    #[test]
+    fn every_invalid_field_is_reported() {
+        let payload = json!({ "exit_code": "zero", "duration_sec": "long", "cwd": 7 });
+        let errors = EventPayload::parse("terminal", None, payload).unwrap_err();
+
+        let mut reported = fields(&errors);
+        reported.sort_unstable();
+        assert_eq!(reported, ["payload.command", "payload.cwd", "payload.duration_sec", "payload.exit_code"]);
+    }
+
+    #[test]
+    fn unknown_vscode_events_are_rejected() {
+        let errors = EventPayload::parse("vscode", None, json!({ "event": "file_renamed" })).unwrap_err();
+
+        assert_eq!(fields(&errors), ["payload.event"]);
+        assert!(errors[0].message.starts_with("unknown event 'file_renamed'"));
+    }
+
+    #[test]
+    fn unsupported_schema_versions_are_rejected() {
+        let errors = EventPayload::parse("terminal", Some(2), json!({ "command": "ls" })).unwrap_err();
+
+        assert_eq!(fields(&errors), ["schema_version"]);
+        assert_eq!(errors[0].message, "unsupported version 2, this server accepts 1");
+    }
+
+    #[test]
+    fn unknown_fields_and_sources_pass_through() {
+        let payload = json!({ "command": "ls", "shell": "zsh" });
+        assert_eq!(EventPayload::parse("terminal", None, payload.clone()).unwrap().into_value(), payload);
+
+        let payload = json!({ "anything": [1, 2] });
+        assert_eq!(EventPayload::parse("jetbrains", None, payload.clone()).unwrap().into_value(), payload);
+    }
+
+    #[tokio::test]
+    async fn rejections_list_field_errors() {
+        let errors = EventPayload::parse("browser", None, json!({ "url": "not a url", "time_on_page_sec": -1 })).unwrap_err();
+        let response = Rejection {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            errors,
+        }
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "invalid event");
+        assert_eq!(body["schema_version"], CURRENT_SCHEMA_VERSION);
+        let mut reported: Vec<_> = body["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+        reported.sort_unstable();
+        assert_eq!(reported, ["payload.time_on_page_sec", "payload.url"]);
+    }
+}
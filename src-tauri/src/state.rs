@@ -1,6 +1,10 @@
 use sqlx::SqlitePool;
 
+use crate::{events::EventBus, secrets::ApiToken};
+
 #[derive(Clone)]
 pub struct AppState {
     pub pool: SqlitePool,
+    pub api_token: ApiToken,
+    pub events: EventBus,
 }
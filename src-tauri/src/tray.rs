@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use sqlx::SqlitePool;
+use tauri::{
+    async_runtime,
+    menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem},
+    tray::TrayIconBuilder,
+    App, AppHandle, Manager, Wry,
+};
+
+use crate::privacy;
+
+const PAUSE_ID: &str = "pause_recording";
+const QUIT_ID: &str = "quit";
+
+/// Minutes the tray toggle pauses recording for
+const TRAY_PAUSE_MINUTES: i64 = 60;
+
+/// The tray's pause toggle, kept in app state so commands can update its check mark
+pub struct PauseToggle(pub CheckMenuItem<Wry>);
+
+/// Reflect the current pause state in the tray menu
+pub fn sync_pause_toggle(app: &AppHandle) {
+    if let Some(toggle) = app.try_state::<PauseToggle>() {
+        let paused = privacy::current().paused_until(Utc::now()).is_some();
+        if let Err(err) = toggle.0.set_checked(paused) {
+            eprintln!("⚠️  Failed to update tray menu: {err}");
+        }
+    }
+}
+
+pub fn setup(app: &App, pool: SqlitePool) -> tauri::Result<()> {
+    let paused = privacy::current().paused_until(Utc::now()).is_some();
+    let pause = CheckMenuItem::with_id(
+        app,
+        PAUSE_ID,
+        format!("Pause recording ({TRAY_PAUSE_MINUTES} min)"),
+        true,
+        paused,
+        None::<&str>,
+    )?;
+    let quit = MenuItem::with_id(app, QUIT_ID, "Quit DevChronicle", true, None::<&str>)?;
+    let menu = Menu::with_items(app, &[&pause, &PredefinedMenuItem::separator(app)?, &quit])?;
+
+    let mut tray = TrayIconBuilder::with_id("main")
+        .tooltip("DevChronicle")
+        .menu(&menu)
+        .on_menu_event(move |app, event| {
+            if event.id() == PAUSE_ID {
+                let pool = pool.clone();
+                let app = app.clone();
+                async_runtime::spawn(async move {
+                    // Toggle from the actual pause state; the check mark may be stale if a pause just expired
+                    let pause = privacy::current().paused_until(Utc::now()).is_none();
+                    let minutes = pause.then_some(TRAY_PAUSE_MINUTES);
+                    if let Err(err) = privacy::pause(&pool, minutes, Utc::now()).await {
+                        eprintln!("⚠️  Failed to toggle recording: {err}");
+                    }
+                    sync_pause_toggle(&app);
+                });
+            } else if event.id() == QUIT_ID {
+                app.exit(0);
+            }
+        });
+
+    if let Some(icon) = app.default_window_icon() {
+        tray = tray.icon(icon.clone());
+    }
+
+    tray.build(app)?;
+    app.manage(PauseToggle(pause));
+
+    // A timed pause ends on its own, so keep the check mark in step with it
+    let app_handle = app.handle().clone();
+    async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            sync_pause_toggle(&app_handle);
+        }
+    });
+
+    Ok(())
+}
@@ -2,12 +2,18 @@
 import React, { useEffect, useMemo, useState } from "react";
 import Dashboard from "./components/Dashboard/Dashboard";
 import Settings from "./components/Settings/Settings";
-import { ActivityLog, AiSettings, AiReport } from "./types";
-import { invokeCommand } from "./utils/tauri";
+import { ActivityLog, AiSettings, AiReport, ChronicleEvent, LogQuery, Page, ServerStatus, TimeSettings } from "./types";
+import { invokeCommand, listenEvent } from "./utils/tauri";
+
+const TIMELINE_PAGE_SIZE = 100;
 
 function App(): JSX.Element {
   const [logs, setLogs] = useState<ActivityLog[]>([]);
+  const [logsTotal, setLogsTotal] = useState<number>(0);
+  const [logsQuery, setLogsQuery] = useState<LogQuery>();
+  const [nextCursor, setNextCursor] = useState<string | null>(null);
   const [isLoading, setIsLoading] = useState<boolean>(true);
+  const [isLoadingMore, setIsLoadingMore] = useState<boolean>(false);
   const [error, setError] = useState<string>();
   const [latestReport, setLatestReport] = useState<string>();
   const [reportError, setReportError] = useState<string>();
@@ -17,19 +23,26 @@ function App(): JSX.Element {
   const [reportsForDate, setReportsForDate] = useState<AiReport[]>([]);
   const [isLoadingReports, setIsLoadingReports] = useState<boolean>(false);
   const [reportsError, setReportsError] = useState<string>();
-  const [selectedDate, setSelectedDate] = useState<string>(
-    new Date().toISOString().slice(0, 10)
-  );
+  const [selectedDate, setSelectedDate] = useState<string>("");
+  const [today, setToday] = useState<string>("");
+  const [serverStatus, setServerStatus] = useState<ServerStatus>();
 
   useEffect(() => {
     const fetchLogs = async () => {
       setIsLoading(true);
       setError(undefined);
-      const today = new Date().toISOString().slice(0, 10);
 
       try {
-        const response = await invokeCommand<ActivityLog[]>("get_logs_by_date", { date: today });
-        setLogs(response);
+        // "Today" follows the time zone setting, which may differ from the browser's
+        const { today } = await invokeCommand<TimeSettings>("fetch_time_settings");
+        setToday(today);
+        handleDateChange(today);
+        const query: LogQuery = { from_date: today, to_date: today, limit: TIMELINE_PAGE_SIZE };
+        const page = await invokeCommand<Page<ActivityLog>>("query_logs", { query });
+        setLogsQuery(query);
+        setLogs(page.items);
+        setLogsTotal(page.total);
+        setNextCursor(page.next_cursor);
       } catch (err) {
         console.error("Failed to fetch logs", err);
         setError(err instanceof Error ? err.message : String(err));
@@ -41,17 +54,63 @@ function App(): JSX.Element {
     fetchLogs();
   }, []);
 
+  // New events arrive as they are ingested, so the timeline doesn't need to poll
+  useEffect(() => {
+    const unlisten = [
+      listenEvent<ChronicleEvent>("log_created", (event) => {
+        if (event.type !== "log_created") return;
+        setLogs((previous) => [event.log, ...previous]);
+        setLogsTotal((previous) => previous + 1);
+      }),
+      listenEvent<ChronicleEvent>("report_created", (event) => {
+        if (event.type !== "report_created") return;
+        setLatestReport(event.report.summary);
+      }),
+      listenEvent<ChronicleEvent>("summary_failed", (event) => {
+        if (event.type !== "summary_failed") return;
+        setReportError(`Summary failed: ${event.error}`);
+      }),
+      listenEvent<ChronicleEvent>("server_status", (event) => {
+        if (event.type !== "server_status") return;
+        setServerStatus(event.status);
+      }),
+    ];
+
+    // The server may have started, or failed to, before the listeners were registered
+    invokeCommand<ServerStatus>("fetch_server_status")
+      .then(setServerStatus)
+      .catch((err) => console.error("Failed to fetch server status", err));
+
+    return () => {
+      unlisten.forEach((promise) => promise.then((stop) => stop()));
+    };
+  }, []);
+
+  // Reports for today pick up new ones as they are generated
+  useEffect(() => {
+    if (!today || selectedDate !== today) return;
+    const unlisten = listenEvent<ChronicleEvent>("report_created", (event) => {
+      if (event.type !== "report_created") return;
+      setReportsForDate((previous) => [event.report, ...previous]);
+    });
+
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, [today, selectedDate]);
+
   useEffect(() => {
     const loadSettings = async () => {
       try {
         const response = await invokeCommand<{ 
           provider_url: string; 
-          api_key?: string | null;
+          has_api_key: boolean;
+          api_key_hint?: string | null;
           model_name: string;  // ✨ ADD THIS
         }>("fetch_ai_settings");
         setAiSettings({
           providerUrl: response.provider_url,
-          apiKey: response.api_key ?? "",
+          apiKey: response.api_key_hint ?? "",
           model_name: response.model_name,  // ✨ ADD THIS
         });
       } catch (err) {
@@ -62,14 +121,36 @@ function App(): JSX.Element {
     loadSettings();
   }, []);
 
+  const handleLoadMoreLogs = async () => {
+    if (!logsQuery || !nextCursor) return;
+    setIsLoadingMore(true);
+    setError(undefined);
+
+    try {
+      const page = await invokeCommand<Page<ActivityLog>>("query_logs", {
+        query: { ...logsQuery, cursor: nextCursor },
+      });
+      setLogs((previous) => [...previous, ...page.items]);
+      setLogsTotal(page.total);
+      setNextCursor(page.next_cursor);
+    } catch (err) {
+      console.error("Failed to fetch more logs", err);
+      setError(err instanceof Error ? err.message : String(err));
+    } finally {
+      setIsLoadingMore(false);
+    }
+  };
+
   const handleDateChange = async (date: string) => {
     setSelectedDate(date);
     setIsLoadingReports(true);
     setReportsError(undefined);
 
     try {
-      const response = await invokeCommand<AiReport[]>("get_reports_by_date", { date });
-      setReportsForDate(response);
+      const page = await invokeCommand<Page<AiReport>>("query_reports", {
+        query: { from_date: date, to_date: date },
+      });
+      setReportsForDate(page.items);
     } catch (err) {
       console.error("Failed to fetch reports", err);
       setReportsError(err instanceof Error ? err.message : String(err));
@@ -78,11 +159,6 @@ function App(): JSX.Element {
     }
   };
 
-  // Load reports for the initial selected date
-  useEffect(() => {
-    handleDateChange(selectedDate);
-  }, []);
-
   const handleGenerateReport = async () => {
     setReportError(undefined);
     setIsGeneratingReport(true);
@@ -135,10 +211,27 @@ function App(): JSX.Element {
         </nav>
       </header>
 
+      {serverStatus && (serverStatus.state === "failed" || serverStatus.state === "stopped") && (
+        <div className="mb-6 rounded-md border border-red-500/50 bg-red-500/10 p-3 text-sm text-red-300" role="alert">
+          The ingestion server is not running, so no new events are recorded.
+          {serverStatus.error && ` ${serverStatus.error}.`} Change the port in Settings and restart the app.
+        </div>
+      )}
+      {serverStatus?.state === "running" && serverStatus.port !== serverStatus.requested_port && (
+        <div className="mb-6 rounded-md border border-amber-500/50 bg-amber-500/10 p-3 text-sm text-amber-200" role="status">
+          Port {serverStatus.requested_port} is in use, so the ingestion server listens on {serverStatus.url}. Extensions
+          with a fixed endpoint need to be pointed there.
+        </div>
+      )}
+
       {activeView === "dashboard" ? (
         <>
           <Dashboard
             logs={logs}
+            logsTotal={logsTotal}
+            hasMoreLogs={nextCursor !== null}
+            onLoadMoreLogs={handleLoadMoreLogs}
+            isLoadingMoreLogs={isLoadingMore}
             isLoading={isLoading}
             error={error}
             onGenerateReport={handleGenerateReport}
@@ -159,7 +252,7 @@ function App(): JSX.Element {
             >
               <div className="flex items-center gap-2">
                 <h2 className="text-xl font-semibold">Debug: Recent Events</h2>
-                <span className="text-sm text-slate-400">Total logs today: {logs.length}</span>
+                <span className="text-sm text-slate-400">Total logs today: {logsTotal}</span>
               </div>
               <button
                 type="button"
@@ -7,6 +7,10 @@ import { ActivityLog, AiReport } from "../../types";
 
 interface DashboardProps {
   logs: ActivityLog[];
+  logsTotal: number;
+  hasMoreLogs: boolean;
+  onLoadMoreLogs: () => void;
+  isLoadingMoreLogs: boolean;
   isLoading: boolean;
   error?: string;
   onGenerateReport: () => void;
@@ -22,6 +26,10 @@ interface DashboardProps {
 
 const Dashboard: React.FC<DashboardProps> = ({
   logs,
+  logsTotal,
+  hasMoreLogs,
+  onLoadMoreLogs,
+  isLoadingMoreLogs,
   isLoading,
   error,
   onGenerateReport,
@@ -41,8 +49,8 @@ const Dashboard: React.FC<DashboardProps> = ({
           <div>
             <h1 className="text-2xl font-bold">DevChronicle Dashboard</h1>
             <p className="mt-2 text-sm text-slate-400">
-              Debug view: fetched <span className="font-semibold text-slate-200">{logs.length}</span> logs
-              for the selected date.
+              Debug view: fetched <span className="font-semibold text-slate-200">{logs.length}</span> of{" "}
+              <span className="font-semibold text-slate-200">{logsTotal}</span> logs for the selected date.
             </p>
           </div>
           <button
@@ -67,7 +75,13 @@ const Dashboard: React.FC<DashboardProps> = ({
         )}
       </section>
 
-      <Timeline logs={logs} />
+      <Timeline
+        logs={logs}
+        total={logsTotal}
+        hasMore={hasMoreLogs}
+        onLoadMore={onLoadMoreLogs}
+        isLoadingMore={isLoadingMoreLogs}
+      />
 
       <CollapsibleSection
         title="Latest AI Report"
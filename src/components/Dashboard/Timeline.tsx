@@ -4,13 +4,17 @@ import CollapsibleSection from "./CollapsibleSection";
 
 interface TimelineProps {
   logs: ActivityLog[];
+  total: number;
+  hasMore: boolean;
+  onLoadMore: () => void;
+  isLoadingMore: boolean;
 }
 
-const Timeline: React.FC<TimelineProps> = ({ logs }) => {
+const Timeline: React.FC<TimelineProps> = ({ logs, total, hasMore, onLoadMore, isLoadingMore }) => {
   return (
     <CollapsibleSection
       title="Activity Timeline"
-      count={logs.length}
+      count={total}
       countLabel="events"
       maxHeight="500px"
     >
@@ -32,6 +36,16 @@ const Timeline: React.FC<TimelineProps> = ({ logs }) => {
           </li>
         ))}
       </ol>
+      {hasMore && (
+        <button
+          type="button"
+          onClick={onLoadMore}
+          disabled={isLoadingMore}
+          className="mt-3 w-full rounded-md border border-slate-700 bg-slate-900 px-4 py-2 text-sm text-slate-300 transition hover:bg-slate-800 disabled:cursor-not-allowed disabled:opacity-60"
+        >
+          {isLoadingMore ? "Loading…" : `Load older events (${logs.length} of ${total})`}
+        </button>
+      )}
     </CollapsibleSection>
   );
 };
@@ -1,6 +1,6 @@
 // src/components/Settings/Settings.tsx
 import React, { useEffect, useState } from "react";
-import { AiSettings } from "../../types";
+import { AiSettings, ServerSettings, ServerStatus, TimeSettings } from "../../types";
 import { invokeCommand } from "../../utils/tauri";
 
 interface SettingsProps {
@@ -11,10 +11,13 @@ interface ExtendedSettings extends AiSettings {
   temperature?: number;
   batch_size?: number;
   summary_frequency_min?: number;
+  local_only?: boolean;
+  audit_store_prompts?: boolean;
 }
 
 const DEFAULT_URL = "http://localhost:1234/v1";
 const DEFAULT_MODEL = "gpt-4o-mini";
+const DEFAULT_SERVER: ServerSettings = { host: "127.0.0.1", port: 3030, fallback_ports: 10 };
 
 const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
   const [isLoading, setIsLoading] = useState(true);
@@ -25,7 +28,16 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
     temperature: 0.2,
     batch_size: 100,
     summary_frequency_min: 10,
+    local_only: false,
+    audit_store_prompts: false,
   });
+  const [localOnlyAudit, setLocalOnlyAudit] = useState<{ enabled: boolean; since: string } | null>(null);
+  const [apiKeyHint, setApiKeyHint] = useState<string | null>(null);
+  const [timezone, setTimezone] = useState("system");
+  const [utcOffset, setUtcOffset] = useState<string>();
+  const [server, setServer] = useState<ServerSettings>(DEFAULT_SERVER);
+  const [serverEnvOverrides, setServerEnvOverrides] = useState<string[]>([]);
+  const [serverStatus, setServerStatus] = useState<ServerStatus>();
   const [error, setError] = useState<string>();
   const [successMessage, setSuccessMessage] = useState<string>();
   const [isSaving, setIsSaving] = useState(false);
@@ -38,23 +50,50 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
       try {
         const response = await invokeCommand<{ 
           provider_url: string; 
-          api_key?: string | null;
+          has_api_key: boolean;
+          api_key_hint?: string | null;
           model_name: string;
           temperature?: number;
           batch_size?: number;
           summary_frequency_min?: number;
+          local_only: boolean;
+          local_only_changed_at?: string | null;
+          audit_store_prompts: boolean;
         }>("fetch_ai_settings");
         if (!isMounted) {
           return;
         }
+        setApiKeyHint(response.has_api_key ? response.api_key_hint ?? "stored" : null);
         setSettings({
           providerUrl: response.provider_url || DEFAULT_URL,
-          apiKey: response.api_key ?? "",
+          apiKey: "",
           model_name: response.model_name || DEFAULT_MODEL,
           temperature: response.temperature ?? 0.2,
           batch_size: response.batch_size ?? 100,
           summary_frequency_min: response.summary_frequency_min ?? 10,
+          local_only: response.local_only,
+          audit_store_prompts: response.audit_store_prompts,
         });
+        setLocalOnlyAudit(
+          response.local_only_changed_at
+            ? { enabled: response.local_only, since: response.local_only_changed_at }
+            : null
+        );
+        const time = await invokeCommand<TimeSettings>("fetch_time_settings");
+        if (isMounted) {
+          setTimezone(time.timezone);
+          setUtcOffset(time.utc_offset);
+        }
+        const serverSettings = await invokeCommand<ServerSettings & { env_overrides: string[] }>(
+          "fetch_server_settings"
+        );
+        const status = await invokeCommand<ServerStatus>("fetch_server_status");
+        if (isMounted) {
+          const { env_overrides, ...stored } = serverSettings;
+          setServer(stored);
+          setServerEnvOverrides(env_overrides);
+          setServerStatus(status);
+        }
       } catch (err) {
         console.error("Failed to load AI settings", err);
         if (isMounted) {
@@ -74,10 +113,10 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
   }, []);
 
   const handleChange = (event: React.ChangeEvent<HTMLInputElement>) => {
-    const { name, value, type } = event.target;
+    const { name, value, type, checked } = event.target;
     setSettings((prev) => ({
       ...prev,
-      [name]: type === "number" ? parseFloat(value) : value,
+      [name]: type === "checkbox" ? checked : type === "number" ? parseFloat(value) : value,
     }));
   };
 
@@ -96,8 +135,20 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
           temperature: settings.temperature,
           batch_size: settings.batch_size,
           summary_frequency_min: settings.summary_frequency_min,
+          local_only: settings.local_only,
+          audit_store_prompts: settings.audit_store_prompts,
         }
       });
+      const saved = await invokeCommand<{ local_only: boolean; local_only_changed_at?: string | null }>(
+        "fetch_ai_settings"
+      );
+      setLocalOnlyAudit(
+        saved.local_only_changed_at ? { enabled: saved.local_only, since: saved.local_only_changed_at } : null
+      );
+      const time = await invokeCommand<TimeSettings>("save_time_settings", { timezone });
+      setTimezone(time.timezone);
+      setUtcOffset(time.utc_offset);
+      await invokeCommand("save_server_settings", { settings: server });
       setSuccessMessage("Settings saved successfully.");
       setTestResult(null);
       onSettingsSaved?.(settings);
@@ -120,6 +171,8 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
             temperature: settings.temperature,
             batch_size: settings.batch_size,
             summary_frequency_min: settings.summary_frequency_min,
+            local_only: settings.local_only,
+            audit_store_prompts: settings.audit_store_prompts,
           }
         });
       } catch (err) {
@@ -174,6 +227,45 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
             </p>
           </div>
 
+          <div className="space-y-2">
+            <label className="flex items-center gap-2 text-sm font-medium text-slate-200" htmlFor="local-only">
+              <input
+                id="local-only"
+                name="local_only"
+                type="checkbox"
+                checked={settings.local_only ?? false}
+                onChange={handleChange}
+                className="h-4 w-4 rounded border-slate-700 bg-slate-950"
+              />
+              Local-only mode
+            </label>
+            <p className="text-xs text-slate-500">
+              Only allow providers whose host resolves to loopback or a private network address. Activity is never sent to cloud models while this is on.
+            </p>
+            {localOnlyAudit && (
+              <p className="text-xs text-slate-400">
+                {localOnlyAudit.enabled ? "Enforced" : "Disabled"} since {new Date(localOnlyAudit.since).toLocaleString()}
+              </p>
+            )}
+          </div>
+
+          <div className="space-y-2">
+            <label className="flex items-center gap-2 text-sm font-medium text-slate-200" htmlFor="audit-store-prompts">
+              <input
+                id="audit-store-prompts"
+                name="audit_store_prompts"
+                type="checkbox"
+                checked={settings.audit_store_prompts ?? false}
+                onChange={handleChange}
+                className="h-4 w-4 rounded border-slate-700 bg-slate-950"
+              />
+              Keep full prompts in the audit trail
+            </label>
+            <p className="text-xs text-slate-500">
+              Every request to the provider is logged with its model, host, token usage and latency. By default only a SHA-256 hash of each prompt is kept; check this to keep the prompts themselves.
+            </p>
+          </div>
+
           {/* ✨ NEW FIELD: Model Name */}
           <div className="space-y-2">
             <label className="block text-sm font-medium text-slate-200" htmlFor="model-name">
@@ -263,7 +355,7 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
               id="api-key"
               name="apiKey"
               type="password"
-              placeholder="sk-..."
+              placeholder={apiKeyHint ? `Stored key ${apiKeyHint} (leave blank to keep)` : "sk-..."}
               value={settings.apiKey ?? ""}
               onChange={handleChange}
               className="w-full rounded-md border border-slate-700 bg-slate-950 px-3 py-2 text-sm text-slate-100 focus:border-blue-500 focus:outline-none focus:ring-2 focus:ring-blue-500/40"
@@ -273,6 +365,74 @@ const Settings: React.FC<SettingsProps> = ({ onSettingsSaved }) => {
             </p>
           </div>
 
+          <div className="space-y-2">
+            <label className="block text-sm font-medium text-slate-200" htmlFor="timezone">
+              Time Zone
+            </label>
+            <input
+              id="timezone"
+              name="timezone"
+              type="text"
+              placeholder="system"
+              value={timezone}
+              onChange={(event) => setTimezone(event.target.value)}
+              className="w-full rounded-md border border-slate-700 bg-slate-950 px-3 py-2 text-sm text-slate-100 focus:border-blue-500 focus:outline-none focus:ring-2 focus:ring-blue-500/40"
+            />
+            <p className="text-xs text-slate-500">
+              Where days begin for the timeline, daily reports, journal and timesheets: "system", "UTC", a zone like Europe/Berlin or a fixed offset like -07:00.
+              {utcOffset && ` Currently UTC${utcOffset}.`}
+            </p>
+          </div>
+
+          <div className="space-y-2">
+            <span className="block text-sm font-medium text-slate-200">Ingestion Server</span>
+            <div className="grid grid-cols-3 gap-3">
+              <input
+                id="server-host"
+                aria-label="Host"
+                type="text"
+                placeholder={DEFAULT_SERVER.host}
+                value={server.host}
+                onChange={(event) => setServer((prev) => ({ ...prev, host: event.target.value }))}
+                className="w-full rounded-md border border-slate-700 bg-slate-950 px-3 py-2 text-sm text-slate-100 focus:border-blue-500 focus:outline-none focus:ring-2 focus:ring-blue-500/40"
+              />
+              <input
+                id="server-port"
+                aria-label="Port"
+                type="number"
+                min="1"
+                max="65535"
+                value={server.port}
+                onChange={(event) => setServer((prev) => ({ ...prev, port: parseInt(event.target.value, 10) || 0 }))}
+                className="w-full rounded-md border border-slate-700 bg-slate-950 px-3 py-2 text-sm text-slate-100 focus:border-blue-500 focus:outline-none focus:ring-2 focus:ring-blue-500/40"
+              />
+              <input
+                id="server-fallback-ports"
+                aria-label="Fallback ports"
+                type="number"
+                min="0"
+                max="100"
+                value={server.fallback_ports}
+                onChange={(event) =>
+                  setServer((prev) => ({ ...prev, fallback_ports: parseInt(event.target.value, 10) || 0 }))
+                }
+                className="w-full rounded-md border border-slate-700 bg-slate-950 px-3 py-2 text-sm text-slate-100 focus:border-blue-500 focus:outline-none focus:ring-2 focus:ring-blue-500/40"
+              />
+            </div>
+            <p className="text-xs text-slate-500">
+              Host, port and how many following ports to try when the port is taken. Takes effect after a restart. Use 0.0.0.0 only if other machines should send events; they need the API token.
+              {serverEnvOverrides.length > 0 && ` Overridden by ${serverEnvOverrides.join(" and ")}.`}
+            </p>
+            {serverStatus && (
+              <p className={`text-xs ${serverStatus.state === "failed" ? "text-red-400" : "text-slate-400"}`}>
+                {serverStatus.state === "running" && `Listening on ${serverStatus.url}`}
+                {serverStatus.state === "failed" && `Not running: ${serverStatus.error}`}
+                {serverStatus.state === "starting" && "Starting…"}
+                {serverStatus.state === "stopped" && "Stopped"}
+              </p>
+            )}
+          </div>
+
           {error && (
             <p className="text-sm text-red-400" role="alert">
               {error}
@@ -14,6 +14,67 @@ export interface AiReport {
   session_id?: string;
 }
 
+/** One page of `query_logs` / `query_reports` results, newest first */
+export interface Page<T> {
+  items: T[];
+  next_cursor: string | null;
+  total: number;
+}
+
+export interface LogQuery {
+  start?: string;
+  end?: string;
+  from_date?: string;
+  to_date?: string;
+  source?: string;
+  log_type?: string;
+  project?: string;
+  domain?: string;
+  exit_code?: number;
+  session_id?: string;
+  cursor?: string;
+  limit?: number;
+}
+
+export interface ReportQuery {
+  start?: string;
+  end?: string;
+  from_date?: string;
+  to_date?: string;
+  source?: string;
+  session_id?: string;
+  cursor?: string;
+  limit?: number;
+}
+
+/** Live events, emitted as Tauri events named after `type` */
+export type ChronicleEvent =
+  | { type: "log_created"; log: ActivityLog }
+  | { type: "report_created"; report: AiReport }
+  | { type: "summary_failed"; error: string; at: string }
+  | { type: "server_status"; status: ServerStatus };
+
+export interface ServerStatus {
+  state: "starting" | "running" | "failed" | "stopped";
+  url: string | null;
+  requested_port: number | null;
+  port: number | null;
+  error: string | null;
+  updated_at: string;
+}
+
+export interface ServerSettings {
+  host: string;
+  port: number;
+  fallback_ports: number;
+}
+
+export interface TimeSettings {
+  timezone: string;
+  utc_offset: string;
+  today: string;
+}
+
 export interface AiSettings {
   providerUrl: string;
   apiKey?: string | null;
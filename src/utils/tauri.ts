@@ -22,3 +22,13 @@ export async function invokeCommand<T>(command: string, args?: InvokeArgs): Prom
   return invoke<T>(command, args);
 }
 
+
+/** Subscribe to an event emitted by the backend. Resolves to a function that unsubscribes. */
+export async function listenEvent<T>(event: string, handler: (payload: T) => void): Promise<() => void> {
+  if (!isTauri()) {
+    return () => {};
+  }
+
+  const { listen } = await import("@tauri-apps/api/event");
+  return listen<T>(event, (received) => handler(received.payload));
+}